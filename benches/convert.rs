@@ -0,0 +1,71 @@
+//! Benchmarks for the row conversion hot path
+//! 
+//! Run with: cargo bench
+//! 
+//! Builds a synthetic sheet in memory (no workbook file involved) and times
+//! convert_rows_to_json() over all of its data rows.
+
+use calamine::{Data, Range};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use excel2json::{convert_rows_to_json, extract_headers, get_visible_column_indices, CellAnnotations, ConvertOptions, ConvertStats};
+
+/// Number of data rows in the synthetic sheet
+const ROWS: u32 = 10_000;
+/// Number of columns in the synthetic sheet
+const COLUMNS: u32 = 30;
+
+/// Builds a sheet with a header row and a mix of text, number and empty cells
+fn synthetic_range() -> Range<Data> {
+    let mut range = Range::new((0, 0), (ROWS, COLUMNS - 1));
+    for col in 0..COLUMNS {
+        range.set_value((0, col), Data::String(format!("Column {}", col + 1)));
+    }
+    for row in 1..=ROWS {
+        for col in 0..COLUMNS {
+            let cell = match col % 4 {
+                0 => Data::String(format!("text {}-{}", row, col)),
+                1 => Data::Float(row as f64 * 1.5),
+                2 => Data::Int((row * col) as i64),
+                _ => Data::Empty,
+            };
+            range.set_value((row, col), cell);
+        }
+    }
+    range
+}
+
+/// Times converting every data row, as strings and with --infer-types
+fn bench_convert_rows(c: &mut Criterion) {
+    let range = synthetic_range();
+    let header_row = range.rows().next().expect("header row");
+    let column_indices = get_visible_column_indices(header_row);
+    let headers = extract_headers(header_row, &column_indices, None);
+    let annotations = CellAnnotations::default();
+
+    let mut group = c.benchmark_group("convert_rows_to_json");
+    group.throughput(Throughput::Elements(ROWS as u64));
+    for (name, opts) in [
+        ("strings", ConvertOptions::default()),
+        ("infer_types", ConvertOptions::builder().infer_types(true).build()),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut stats = ConvertStats::default();
+                let records: Vec<_> = convert_rows_to_json(
+                    range.rows().skip(1).enumerate(),
+                    &headers,
+                    &column_indices,
+                    &opts,
+                    &annotations,
+                    &mut stats,
+                )
+                .collect();
+                black_box(records)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_convert_rows);
+criterion_main!(benches);
@@ -0,0 +1,2888 @@
+//! Cell and row conversion behind the excel2json command-line tool
+//! 
+//! The binary (main.rs) handles arguments, sheet selection and output files;
+//! this library turns worksheet rows into JSON records, so the conversion can
+//! also be embedded - e.g. in a web service converting uploaded workbooks with
+//! convert_bytes() or convert_file(), configured via ConvertOptions::builder().
+//! convert_sheet_iter() yields the records of a sheet one at a time instead.
+
+// External dependencies
+use anyhow::{Context, Result}; // Error handling with context
+use calamine::{open_workbook_from_rs, ExcelDateTime, ExcelDateTimeType, Ods, Reader, Sheets, Xls, Xlsb, Xlsx}; // Excel file reading library
+use clap::ValueEnum; // Option values shared with the command line
+use regex::Regex; // Regular expressions for --replace rules and --where parsing
+use serde_json::Value; // JSON serialization
+use std::borrow::{Borrow, BorrowMut, Cow}; // Cells passed through unchanged without copying; owned or borrowed arguments
+use std::cmp::Ordering; // Comparison results for --where conditions
+use std::collections::HashMap; // Cell annotation lookups
+use std::io::{Cursor, Read, Seek}; // In-memory workbook reading for convert_bytes()
+use std::path::Path; // Workbook paths for convert_file()
+use std::sync::{Arc, Mutex, PoisonError}; // Stats shared with convert_sheet_iter()'s error check, which stays Send
+use unicode_normalization::UnicodeNormalization; // NFC/NFKC forms for --normalize-unicode
+
+/// Thousands/decimal separator convention for parsing numeric text
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberLocale {
+    /// 1,234.56 - comma as thousands separator, dot as decimal separator
+    Us,
+    /// 1.234,56 - dot as thousands separator, comma as decimal separator
+    Eu,
+}
+
+impl NumberLocale {
+    /// Returns the (thousands separator, decimal separator) pair for this locale
+    fn separators(self) -> (char, char) {
+        match self {
+            NumberLocale::Us => (',', '.'),
+            NumberLocale::Eu => ('.', ','),
+        }
+    }
+}
+
+/// Decimal separator of numeric text (--decimal-separator); the thousands separator is the other one
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    /// 1,234.56
+    Dot,
+    /// 1.234,56
+    Comma,
+}
+
+impl DecimalSeparator {
+    /// The separator convention this decimal separator implies
+    pub fn locale(self) -> NumberLocale {
+        match self {
+            DecimalSeparator::Dot => NumberLocale::Us,
+            DecimalSeparator::Comma => NumberLocale::Eu,
+        }
+    }
+}
+
+/// How date/time cells are written to the output
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateMode {
+    /// Excel's serial number as stored in the file (e.g. "45000.5")
+    Serial,
+    /// ISO 8601 text: "2023-03-15" for whole days, "2023-03-15T12:00:00" otherwise
+    Iso,
+    /// Like iso, but time-only values become "12:00:00" and elapsed-time
+    /// ([h]:mm:ss) values "25:30:00"
+    Time,
+    /// Seconds since 1970-01-01 00:00:00 UTC as a JSON number (e.g. 1678838400)
+    Epoch,
+}
+
+/// Unicode normalization form applied to headers and text (--normalize-unicode)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical composition: "e" + combining acute becomes the single character "é"
+    Nfc,
+    /// Compatibility composition: also folds full-width and other compatibility
+    /// characters, e.g. "ＡＢＣ１" becomes "ABC1"
+    Nfkc,
+}
+
+impl UnicodeForm {
+    /// Returns `text` in this normalization form
+    /// 
+    /// # Examples
+    /// - Nfc: "Cafe\u{301}" -> "Café"
+    /// - Nfkc: "ＩＤ（２）" -> "ID(2)"
+    pub fn normalize(self, text: &str) -> String {
+        match self {
+            UnicodeForm::Nfc => text.nfc().collect(),
+            UnicodeForm::Nfkc => text.nfkc().collect(),
+        }
+    }
+}
+
+/// A regex substitution for one column, parsed from --replace
+#[derive(Debug, Clone)]
+pub struct ReplaceRule {
+    /// Normalized header name (JSON key) of the column the rule applies to
+    pub column: String,
+    /// Compiled pattern to search for
+    pub pattern: Regex,
+    /// Replacement text (may reference capture groups like $1)
+    pub replacement: String,
+}
+
+/// Parses a --replace rule of the form "header:pattern=>replacement"
+/// 
+/// Used as a clap value parser, so an invalid rule or regex is rejected at
+/// startup before any file is read.
+/// 
+/// # Example
+/// "Code:^ID-(\d+)=>$1" -> column "code", pattern "^ID-(\d+)", replacement "$1"
+pub fn parse_replace_rule(spec: &str) -> std::result::Result<ReplaceRule, String> {
+    let (header, rest) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("expected \"header:pattern=>replacement\", got '{}'", spec))?;
+    let (pattern, replacement) = rest
+        .split_once("=>")
+        .ok_or_else(|| format!("missing \"=>\" in replace rule '{}'", spec))?;
+    let pattern = Regex::new(pattern).map_err(|e| format!("invalid regex '{}': {}", pattern, e))?;
+
+    Ok(ReplaceRule {
+        column: normalize_column_name(header),
+        pattern,
+        replacement: replacement.to_string(),
+    })
+}
+
+/// A column whose values are split into arrays, parsed from --split-cells
+#[derive(Debug, Clone)]
+pub struct SplitRule {
+    /// Normalized header name (JSON key) of the column to split
+    pub column: String,
+    /// Text separating the values within a cell (e.g. ";")
+    pub delimiter: String,
+}
+
+/// Parses a --split-cells rule of the form "header:delimiter"
+/// 
+/// Used as a clap value parser, so an invalid rule is rejected at startup.
+/// 
+/// # Example
+/// "Tags:;" -> column "tags", delimiter ";"
+pub fn parse_split_rule(spec: &str) -> std::result::Result<SplitRule, String> {
+    let (header, delimiter) = spec
+        .split_once(':')
+        .ok_or_else(|| format!("expected \"header:delimiter\", got '{}'", spec))?;
+    if delimiter.is_empty() {
+        return Err(format!("missing delimiter in split rule '{}'", spec));
+    }
+
+    Ok(SplitRule {
+        column: normalize_column_name(header),
+        delimiter: delimiter.to_string(),
+    })
+}
+
+/// How whitespace around and inside text cells is handled
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimMode {
+    /// Keep text exactly as stored
+    None,
+    /// Remove leading and trailing whitespace
+    Ends,
+    /// Also collapse runs of whitespace inside the text to a single space
+    Collapse,
+}
+
+/// What an empty cell becomes in a --split-cells column
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitEmpty {
+    /// An empty array: []
+    Array,
+    /// null
+    Null,
+}
+
+/// How normalized header names are written as JSON keys (--key-style)
+/// 
+/// Only the written keys change: options that name columns still take the
+/// normalized (snake_case) header names.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStyle {
+    /// Lowercase words joined by underscores, as the command line writes them: "customer_id"
+    Snake,
+    /// The same words in camelCase: "customerId"
+    Camel,
+}
+
+impl KeyStyle {
+    /// Applies the style to a key normalized by normalize_column_name()
+    /// 
+    /// # Examples
+    /// - Snake: "unit_price" -> "unit_price"
+    /// - Camel: "unit_price" -> "unitPrice", "column_3" -> "column3", "col_2023" -> "col2023"
+    pub fn apply(self, key: &str) -> String {
+        match self {
+            KeyStyle::Snake => key.to_string(),
+            KeyStyle::Camel => {
+                let mut words = key.split('_').filter(|word| !word.is_empty());
+                let mut camel = words.next().unwrap_or_default().to_string();
+                for word in words {
+                    let mut chars = word.chars();
+                    if let Some(first) = chars.next() {
+                        camel.extend(first.to_uppercase());
+                        camel.push_str(chars.as_str());
+                    }
+                }
+                camel
+            }
+        }
+    }
+}
+
+/// What empty cells become in the records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyMode {
+    /// Whatever the conversion gives: "" without infer_types, null with it
+    Keep,
+    /// Always null, as is an empty text cell
+    Null,
+    /// Left out of the record, as are other null or empty values (--compact-empty)
+    Omit,
+}
+
+/// Value type of a column, decided from its first rows (--sample-size)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnKind {
+    /// Numbers (and, with --strip-currency, text that parses as one)
+    Number,
+    /// true/false cells
+    Boolean,
+    /// Anything else: text, dates, error values
+    Text,
+}
+
+impl ColumnKind {
+    /// The kind of value a cell converts to with --infer-types
+    /// 
+    /// # Returns
+    /// None for empty cells, which fit any column
+    fn of_cell(cell: &calamine::Data, opts: &ConvertOptions) -> Option<Self> {
+        match cell {
+            cell if is_blank_cell(cell) => None,
+            calamine::Data::Int(_) | calamine::Data::Float(_) => Some(ColumnKind::Number),
+            calamine::Data::Bool(_) => Some(ColumnKind::Boolean),
+            calamine::Data::String(text) if opts.strip_currency && parse_currency_number(text, opts.locale).is_some() => {
+                Some(ColumnKind::Number)
+            }
+            calamine::Data::String(text) if opts.parse_numeric_text && parse_numeric_text(text, opts.locale).is_some() => {
+                Some(ColumnKind::Number)
+            }
+            _ => Some(ColumnKind::Text),
+        }
+    }
+}
+
+/// Options that control how cell values are turned into JSON values
+/// 
+/// Start from ConvertOptions::default() or ConvertOptions::builder() rather than
+/// listing every field.
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// Emit native JSON types instead of strings (--infer-types)
+    pub infer_types: bool,
+    /// Parse currency-formatted text as numbers during inference (--strip-currency)
+    pub strip_currency: bool,
+    /// Parse plain numeric text such as "1.234,56" as numbers during inference (--decimal-separator)
+    pub parse_numeric_text: bool,
+    /// Separator convention used when parsing numeric text (--locale, --decimal-separator)
+    pub locale: NumberLocale,
+    /// Per-column regex substitutions, applied in order (--replace)
+    pub replace_rules: Vec<ReplaceRule>,
+    /// Longest text value to emit, in characters (--max-cell-length)
+    pub max_cell_length: Option<usize>,
+    /// Whitespace handling for text cells (--trim)
+    pub trim: TrimMode,
+    /// Appended to text cut by max_cell_length (--truncate-marker)
+    pub truncate_marker: String,
+    /// How date/time cells are written (--dates)
+    pub dates: DateMode,
+    /// Whether the workbook counts date serials from 1904 instead of 1900
+    /// (read from the workbook, see uses_1904_dates())
+    pub date1904: bool,
+    /// Columns whose values are split into arrays (--split-cells)
+    pub split_rules: Vec<SplitRule>,
+    /// Values for empty or missing cells, by normalized header name (--defaults)
+    pub defaults: HashMap<String, String>,
+    /// Row filter - only records matching every condition are kept (--where)
+    pub conditions: Vec<Condition>,
+    /// What empty cells become in split columns (--split-empty)
+    pub split_empty: SplitEmpty,
+    /// Order each record's keys alphabetically instead of by column (--sort-keys)
+    pub sort_keys: bool,
+    /// Columns parsed as numbers even without infer_types, by normalized header name (--numeric-columns)
+    pub numeric_columns: Vec<String>,
+    /// Columns written as text even with infer_types, by normalized header name (--string-columns)
+    pub string_columns: Vec<String>,
+    /// Columns whose 1/0 values become true/false, by normalized header name (--bool-columns)
+    pub bool_columns: Vec<String>,
+    /// Stop at the first --bool-columns value that isn't 1, 0 or a boolean (--strict-bool-columns)
+    pub strict_bool: bool,
+    /// Columns whose text values are parsed as dates, by normalized header name (--date-columns)
+    pub date_columns: Vec<String>,
+    /// chrono format of the text dates in date_columns, e.g. "%m/%d/%Y" (--date-input-format)
+    pub date_input_format: String,
+    /// What empty cells become; Omit leaves out every null or empty value (--compact-empty)
+    pub empty: EmptyMode,
+    /// Unicode normalization applied to text cells (--normalize-unicode with --normalize-unicode-values)
+    pub normalize_values: Option<UnicodeForm>,
+    /// With infer_types, fix each column's type from this many data rows instead of
+    /// inferring every cell on its own (--sample-size)
+    pub sample_size: Option<usize>,
+    /// Stop at the first cell that doesn't match its column's sampled type (--strict-types)
+    pub strict_types: bool,
+    /// Stop at the first --numeric-columns value that isn't a number (--strict)
+    pub strict_numeric: bool,
+    /// Round numbers emitted as JSON numbers to this many decimal places (--float-precision)
+    pub float_precision: Option<u32>,
+    /// Columns to include, by normalized header name; empty means every column (--columns)
+    /// 
+    /// This and the header options below are applied by convert_sheet_iter(),
+    /// convert_bytes() and convert_file(); convert_rows_to_json() takes its headers ready-made.
+    pub columns: Vec<String>,
+    /// How the normalized header names are written as keys
+    pub key_style: KeyStyle,
+    /// Split camelCase/PascalCase headers into words before normalizing them (--split-case)
+    pub split_case: bool,
+    /// Unicode normalization applied to the headers (--normalize-unicode)
+    pub normalize_keys: Option<UnicodeForm>,
+    /// Keys for columns, replacing their normalized header names (--header-map)
+    pub header_map: HeaderMap,
+    /// Prefix for keys that start with a digit (--key-prefix)
+    pub key_prefix: Option<String>,
+    /// Prefix for keys that are entirely numeric, such as years (--numeric-header-prefix)
+    pub numeric_header_prefix: Option<String>,
+    /// Include columns with a blank header, keyed "column_N" (--include-blank-columns)
+    pub include_blank_columns: bool,
+    /// Leave out records that have no keys left (--drop-empty-records)
+    pub drop_empty_records: bool,
+}
+
+/// Counters collected while converting cells, reported in the summary
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConvertStats {
+    /// Text cells shortened by --max-cell-length
+    pub truncated_cells: usize,
+    /// Values in --numeric-columns columns that aren't numbers and were kept as text
+    pub numeric_fallbacks: usize,
+    /// Values that don't match their column's --sample-size type and were kept as text
+    pub type_fallbacks: usize,
+    /// (data row index, position in the selected columns) of the first such value
+    pub first_type_fallback: Option<(usize, usize)>,
+    /// (data row index, position in the selected columns) of the first --numeric-columns fallback
+    pub first_numeric_fallback: Option<(usize, usize)>,
+    /// Values in --bool-columns columns other than 1, 0 or a boolean, converted as usual
+    pub bool_fallbacks: usize,
+    /// (data row index, position in the selected columns) of the first --bool-columns fallback
+    pub first_bool_fallback: Option<(usize, usize)>,
+    /// Text values in --date-columns columns that don't match the input format, kept as text
+    pub date_fallbacks: usize,
+}
+
+impl Default for ConvertOptions {
+    /// The command-line defaults: every value as a string, dates as serials
+    fn default() -> Self {
+        ConvertOptions {
+            infer_types: false,
+            strip_currency: false,
+            parse_numeric_text: false,
+            locale: NumberLocale::Us,
+            replace_rules: Vec::new(),
+            trim: TrimMode::None,
+            max_cell_length: None,
+            truncate_marker: String::new(),
+            dates: DateMode::Serial,
+            date1904: false,
+            split_rules: Vec::new(),
+            defaults: HashMap::new(),
+            conditions: Vec::new(),
+            split_empty: SplitEmpty::Array,
+            sort_keys: false,
+            numeric_columns: Vec::new(),
+            string_columns: Vec::new(),
+            bool_columns: Vec::new(),
+            strict_bool: false,
+            date_columns: Vec::new(),
+            date_input_format: "%Y-%m-%d".to_string(),
+            empty: EmptyMode::Keep,
+            normalize_values: None,
+            sample_size: None,
+            strict_types: false,
+            strict_numeric: false,
+            float_precision: None,
+            columns: Vec::new(),
+            key_style: KeyStyle::Snake,
+            split_case: false,
+            normalize_keys: None,
+            header_map: HeaderMap::new(),
+            key_prefix: None,
+            numeric_header_prefix: None,
+            include_blank_columns: false,
+            drop_empty_records: false,
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Starts a builder with the command-line defaults
+    /// 
+    /// Saves writing out every field when only a few options differ from the
+    /// defaults.
+    /// 
+    /// # Example
+    /// ```rust
+    /// use excel2json::{convert_file, ConvertOptions, EmptyMode, KeyStyle};
+    /// use serde_json::json;
+    /// 
+    /// let opts = ConvertOptions::builder()
+    ///     .columns(["Name", "Customer ID", "Notes"])
+    ///     .key_style(KeyStyle::Camel)
+    ///     .empty(EmptyMode::Null)
+    ///     .build();
+    /// let records = convert_file("tests/fixtures/people.xlsx", "Sheet1", &opts)?;
+    /// assert_eq!(records[0], json!({"name": "John", "customerId": "C-1", "notes": null}));
+    /// assert_eq!(records[1], json!({"name": "Jane", "customerId": "C-2", "notes": "vip"}));
+    /// # anyhow::Ok(())
+    /// ```
+    pub fn builder() -> ConvertOptionsBuilder {
+        ConvertOptionsBuilder::default()
+    }
+}
+
+/// Builder for ConvertOptions, created with ConvertOptions::builder()
+/// 
+/// Each method sets the option of the command-line flag named in its
+/// documentation; options that are not set keep their default.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptionsBuilder {
+    /// Options built so far
+    opts: ConvertOptions,
+}
+
+impl ConvertOptionsBuilder {
+    /// Emit native JSON types instead of strings (--infer-types)
+    pub fn infer_types(mut self, infer_types: bool) -> Self {
+        self.opts.infer_types = infer_types;
+        self
+    }
+
+    /// Parse currency-formatted text as numbers during inference (--strip-currency)
+    pub fn strip_currency(mut self, strip_currency: bool) -> Self {
+        self.opts.strip_currency = strip_currency;
+        self
+    }
+
+    /// Parse plain numeric text such as "1.234,56" as numbers during inference (--decimal-separator)
+    pub fn parse_numeric_text(mut self, parse_numeric_text: bool) -> Self {
+        self.opts.parse_numeric_text = parse_numeric_text;
+        self
+    }
+
+    /// Separator convention used when parsing numeric text (--locale, --decimal-separator)
+    pub fn locale(mut self, locale: NumberLocale) -> Self {
+        self.opts.locale = locale;
+        self
+    }
+
+    /// Parses plain numeric text with this decimal separator during inference (--decimal-separator)
+    /// 
+    /// Sets both parse_numeric_text and the locale the separator implies.
+    pub fn decimal_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.opts.parse_numeric_text = true;
+        self.opts.locale = separator.locale();
+        self
+    }
+
+    /// Adds a regex substitution; rules run in the order they were added (--replace)
+    pub fn replace(mut self, rule: ReplaceRule) -> Self {
+        self.opts.replace_rules.push(rule);
+        self
+    }
+
+    /// Whitespace handling for text cells (--trim)
+    pub fn trim(mut self, trim: TrimMode) -> Self {
+        self.opts.trim = trim;
+        self
+    }
+
+    /// Longest text value to emit, in characters (--max-cell-length)
+    pub fn max_cell_length(mut self, max_cell_length: usize) -> Self {
+        self.opts.max_cell_length = Some(max_cell_length);
+        self
+    }
+
+    /// Text appended to values cut by max_cell_length (--truncate-marker)
+    pub fn truncate_marker(mut self, marker: impl Into<String>) -> Self {
+        self.opts.truncate_marker = marker.into();
+        self
+    }
+
+    /// How date/time cells are written (--dates)
+    pub fn dates(mut self, dates: DateMode) -> Self {
+        self.opts.dates = dates;
+        self
+    }
+
+    /// Count date serials from 1904 instead of 1900 (see uses_1904_dates())
+    pub fn date1904(mut self, date1904: bool) -> Self {
+        self.opts.date1904 = date1904;
+        self
+    }
+
+    /// Adds a column whose values are split into arrays (--split-cells)
+    pub fn split(mut self, rule: SplitRule) -> Self {
+        self.opts.split_rules.push(rule);
+        self
+    }
+
+    /// What empty cells become in split columns (--split-empty)
+    pub fn split_empty(mut self, split_empty: SplitEmpty) -> Self {
+        self.opts.split_empty = split_empty;
+        self
+    }
+
+    /// Sets the value for empty or missing cells of a column (--defaults)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys,
+    /// so "Status" and "status" name the same column.
+    pub fn default_value(mut self, column: &str, value: impl Into<String>) -> Self {
+        self.opts.defaults.insert(normalize_column_name(column), value.into());
+        self
+    }
+
+    /// Adds a row filter condition; records must match all of them (--where)
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.opts.conditions.push(condition);
+        self
+    }
+
+    /// Order each record's keys alphabetically instead of by column (--sort-keys)
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.opts.sort_keys = sort_keys;
+        self
+    }
+
+    /// What empty cells become; EmptyMode::Omit is --compact-empty
+    pub fn empty(mut self, empty: EmptyMode) -> Self {
+        self.opts.empty = empty;
+        self
+    }
+
+    /// Applies a Unicode normalization form to text cells (--normalize-unicode-values)
+    pub fn normalize_values(mut self, form: UnicodeForm) -> Self {
+        self.opts.normalize_values = Some(form);
+        self
+    }
+
+    /// Fixes each column's type from the first `rows` data rows (--sample-size)
+    pub fn sample_size(mut self, rows: usize) -> Self {
+        self.opts.sample_size = Some(rows);
+        self
+    }
+
+    /// Rounds numbers emitted as JSON numbers to `digits` decimal places (--float-precision)
+    pub fn float_precision(mut self, digits: u32) -> Self {
+        self.opts.float_precision = Some(digits);
+        self
+    }
+
+    /// Stops at the first value that doesn't match its column's sampled type (--strict-types)
+    pub fn strict_types(mut self, strict_types: bool) -> Self {
+        self.opts.strict_types = strict_types;
+        self
+    }
+
+    /// Stops at the first --numeric-columns value that isn't a number
+    pub fn strict_numeric(mut self, strict_numeric: bool) -> Self {
+        self.opts.strict_numeric = strict_numeric;
+        self
+    }
+
+    /// Stops at the first value that doesn't fit its column in any of the ways above (--strict)
+    /// 
+    /// Sets strict_types, strict_numeric and strict_bool together.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.opts.strict_types = strict;
+        self.opts.strict_numeric = strict;
+        self.opts.strict_bool = strict;
+        self
+    }
+
+    /// Parses a column's values as numbers even without infer_types (--numeric-columns)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys.
+    pub fn numeric_column(mut self, column: &str) -> Self {
+        self.opts.numeric_columns.push(normalize_column_name(column));
+        self
+    }
+
+    /// Writes a column's values as text even with infer_types (--string-columns)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys.
+    pub fn string_column(mut self, column: &str) -> Self {
+        self.opts.string_columns.push(normalize_column_name(column));
+        self
+    }
+
+    /// Writes a column's 1/0 values as true/false (--bool-columns)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys.
+    pub fn bool_column(mut self, column: &str) -> Self {
+        self.opts.bool_columns.push(normalize_column_name(column));
+        self
+    }
+
+    /// Stops at the first --bool-columns value that isn't 1, 0 or a boolean (--strict-bool-columns)
+    pub fn strict_bool(mut self, strict_bool: bool) -> Self {
+        self.opts.strict_bool = strict_bool;
+        self
+    }
+
+    /// Parses a column's text values as dates and writes them like date cells (--date-columns)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys.
+    pub fn date_column(mut self, column: &str) -> Self {
+        self.opts.date_columns.push(normalize_column_name(column));
+        self
+    }
+
+    /// Sets the chrono format of the text dates in date columns (--date-input-format)
+    pub fn date_input_format(mut self, format: impl Into<String>) -> Self {
+        self.opts.date_input_format = format.into();
+        self
+    }
+
+    /// Includes only these columns, in this order (--columns)
+    /// 
+    /// Columns are given as headers and normalized like the JSON keys;
+    /// converting a sheet that lacks one of them is an error.
+    pub fn columns<S: AsRef<str>>(mut self, columns: impl IntoIterator<Item = S>) -> Self {
+        self.opts.columns = columns.into_iter().map(|column| normalize_column_name(column.as_ref())).collect();
+        self
+    }
+
+    /// How the normalized header names are written as keys (--key-style)
+    /// 
+    /// Options that name columns keep using the normalized names, e.g.
+    /// string_column("Zip Code") for the "zipCode" key.
+    pub fn key_style(mut self, key_style: KeyStyle) -> Self {
+        self.opts.key_style = key_style;
+        self
+    }
+
+    /// Splits camelCase/PascalCase headers into words before normalizing them (--split-case)
+    pub fn split_case(mut self, split_case: bool) -> Self {
+        self.opts.split_case = split_case;
+        self
+    }
+
+    /// Applies a Unicode normalization form to the headers (--normalize-unicode)
+    pub fn normalize_keys(mut self, form: UnicodeForm) -> Self {
+        self.opts.normalize_keys = Some(form);
+        self
+    }
+
+    /// Writes a column under `key` instead of its normalized header name (--header-map)
+    /// 
+    /// The column is given as a header and normalized like the JSON keys; `key`
+    /// is used as it is, without key_style.
+    pub fn rename_column(mut self, column: &str, key: impl Into<String>) -> Self {
+        self.opts.header_map.insert(normalize_column_name(column), key.into());
+        self
+    }
+
+    /// Prepends `prefix` to keys that start with a digit (--key-prefix)
+    pub fn key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.opts.key_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Prepends `prefix` to keys that are entirely numeric, such as years (--numeric-header-prefix)
+    pub fn numeric_header_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.opts.numeric_header_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Includes columns with a blank header, keyed "column_N" (--include-blank-columns)
+    pub fn include_blank_columns(mut self, include_blank_columns: bool) -> Self {
+        self.opts.include_blank_columns = include_blank_columns;
+        self
+    }
+
+    /// Leaves out records that have no keys left (--drop-empty-records)
+    pub fn drop_empty_records(mut self, drop_empty_records: bool) -> Self {
+        self.opts.drop_empty_records = drop_empty_records;
+        self
+    }
+
+    /// Finishes the builder
+    pub fn build(self) -> ConvertOptions {
+        self.opts
+    }
+}
+
+/// Normalizes Excel column header names to valid JSON keys
+/// 
+/// Rules:
+/// - Single special characters are converted to meaningful words (e.g., "#" -> "number")
+/// - Converts to lowercase
+/// - Replaces special characters with underscores or meaningful text
+/// - Removes parentheses
+/// - Removes consecutive underscores
+/// 
+/// # Arguments
+/// * `name` - The original column header name from Excel
+/// 
+/// # Returns
+/// A normalized string suitable for use as a JSON key
+/// 
+/// # Examples
+/// - "First Name" -> "first_name"
+/// - "#" -> "number"
+/// - "Sales/Revenue" -> "sales_revenue"
+/// - "Profit & Loss" -> "profit_and_loss"
+pub fn normalize_column_name(name: &str) -> String {
+    let trimmed = name.trim();
+    
+    // Handle single special characters with meaningful names
+    let result = match trimmed {
+        "#" => "number".to_string(),
+        "@" => "at".to_string(),
+        "%" => "percent".to_string(),
+        "$" => "usd".to_string(),
+        "/" => "slash".to_string(),
+        "&" => "and".to_string(),
+        _ => {
+            // For all other cases, apply transformation rules
+            trimmed
+                .to_lowercase() // Convert to lowercase
+                .replace(" & ", "_and_") // Replace " & " with "_and_"
+                .replace("&", "_and_") // Replace "&" with "_and_"
+                .replace("/", "_") // Replace "/" with "_"
+                .replace("@", "_at_") // Replace "@" with "_at_"
+                .replace("#", "_") // Replace "#" with "_"
+                .replace("%", "_percent") // Replace "%" with "_percent"
+                .replace("$", "_usd") // Replace "$" with "_usd"
+                .replace("(", "") // Remove opening parenthesis
+                .replace(")", "") // Remove closing parenthesis
+                .replace(" ", "_") // Replace spaces with underscores
+        }
+    };
+    
+    // Clean up: remove consecutive underscores and empty segments
+    result
+        .split('_')
+        .filter(|s| !s.is_empty()) // Remove empty segments
+        .collect::<Vec<_>>()
+        .join("_") // Join with single underscore
+}
+
+/// Whether a normalized key is a number, as from a header like "2023" or "2023.5"
+pub fn is_numeric_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_digit()) && key.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '_')
+}
+
+/// Converts a 0-based column index to Excel column letters
+/// 
+/// # Examples
+/// - 0 -> "A"
+/// - 27 -> "AB"
+pub fn column_letters(index: usize) -> String {
+    let mut letters = Vec::new();
+    let mut n = index + 1;
+    while n > 0 {
+        let rem = (n - 1) % 26;
+        letters.push(b'A' + rem as u8);
+        n = (n - 1) / 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).expect("ASCII letters")
+}
+
+/// Splits camelCase and PascalCase header text into words (--split-case)
+/// 
+/// normalize_column_name() lowercases everything, which loses the word boundaries
+/// of "CustomerID". Running the header through this first puts a space at each
+/// case transition, so they survive normalization:
+/// - before an uppercase letter that follows a lowercase letter or digit ("customerID")
+/// - before the last capital of a run followed by lowercase, which starts the next
+///   word ("HTTPStatus"), unless that is just a plural "s" ("IDs")
+/// 
+/// Text without mixed case (all caps, Thai, ...) is returned unchanged.
+/// 
+/// # Arguments
+/// * `name` - The original column header text
+/// 
+/// # Returns
+/// The header text with spaces between its words
+/// 
+/// # Examples
+/// - "CustomerID" -> "Customer ID" (normalized: "customer_id")
+/// - "HTTPStatus" -> "HTTP Status" (normalized: "http_status")
+/// - "getHTTPResponseCode" -> "get HTTP Response Code"
+/// - "UserIDs" -> "User IDs"
+/// - "Address2Line" -> "Address2 Line"
+/// - "USD Amount" -> "USD Amount"
+pub fn split_case_words(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::with_capacity(name.len() + 4);
+    for (i, &c) in chars.iter().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            let prev = chars[i - 1];
+            let next = chars.get(i + 1).copied();
+            // "IDs": a lone trailing "s" pluralizes the acronym rather than starting a word
+            let plural = next == Some('s') && !chars.get(i + 2).is_some_and(|after| after.is_lowercase());
+            let word_start = prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next.is_some_and(char::is_lowercase) && !plural);
+            if word_start {
+                result.push(' ');
+            }
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Identifies visible columns by filtering out columns with empty headers
+/// 
+/// This function helps distinguish between actual data columns and hidden/unused columns.
+/// Only columns with non-empty header values are considered "visible".
+/// 
+/// # Arguments
+/// * `header_row` - The first row of the Excel sheet containing column headers
+/// 
+/// # Returns
+/// A vector of column indices (0-based) that have non-empty headers
+/// 
+/// # Example
+/// If header row is: ["Name", "Age", "", "Email", "", "Phone"]
+/// Returns: [0, 1, 3, 5] (indices of non-empty columns)
+pub fn get_visible_column_indices(header_row: &[calamine::Data]) -> Vec<usize> {
+    header_row
+        .iter() // Iterate through all cells in the header row
+        .enumerate() // Get index along with each cell
+        .filter_map(|(idx, cell)| {
+            // Convert cell to string and trim whitespace
+            let cell_str = cell.to_string().trim().to_string();
+            // Only include columns with non-empty headers
+            if !cell_str.is_empty() {
+                Some(idx) // Return the column index
+            } else {
+                None // Skip empty columns
+            }
+        })
+        .collect() // Collect all visible column indices into a vector
+}
+
+/// Text values keyed by absolute (row, column) cell position
+pub type CellMap = HashMap<(u32, u32), String>;
+
+/// Extra per-cell values read from the raw xlsx parts
+/// 
+/// Each layer adds a "<key>_<suffix>" field next to a cell's value when the
+/// cell has an entry in that layer (e.g. "website_url" for hyperlinks).
+#[derive(Debug, Default)]
+pub struct CellAnnotations {
+    /// Absolute (row, column) of the first data row's first cell in the Range
+    pub origin: (u32, u32),
+    /// (field suffix, values keyed by absolute (row, column)) pairs
+    pub layers: Vec<(&'static str, CellMap)>,
+}
+
+impl CellAnnotations {
+    /// Looks up the annotations for a cell given its position relative to `origin`
+    fn get(&self, row_idx: usize, col_idx: usize) -> impl Iterator<Item = (&'static str, &String)> {
+        let position = (self.origin.0 + row_idx as u32, self.origin.1 + col_idx as u32);
+        self.layers
+            .iter()
+            .filter_map(move |(suffix, values)| values.get(&position).map(|v| (*suffix, v)))
+    }
+}
+
+/// Output key overrides from --header-map: normalized header name -> output key
+pub type HeaderMap = HashMap<String, String>;
+
+/// Extracts and normalizes column headers for the specified column indices
+/// 
+/// # Arguments
+/// * `header_row` - The first row containing column headers
+/// * `column_indices` - Vector of column indices to extract headers from
+/// * `header_map` - Optional renames applied after normalization (--header-map)
+/// 
+/// # Returns
+/// A vector of normalized header names suitable for use as JSON keys
+/// 
+/// # Behavior
+/// - Normalizes each header using normalize_column_name()
+/// - If a column index is out of bounds or its header is blank, generates a default name "column_N"
+/// - Replaces names found in `header_map` with the mapped key; others stay as they are
+pub fn extract_headers(
+    header_row: &[calamine::Data],
+    column_indices: &[usize],
+    header_map: Option<&HeaderMap>,
+) -> Vec<String> {
+    column_indices
+        .iter() // Iterate through selected column indices
+        .map(|&i| {
+            let name = header_row
+                .get(i) // Try to get the cell at this index
+                .map(|cell| normalize_column_name(&cell.to_string())) // Normalize if found
+                .filter(|name| !name.is_empty()) // Blank headers (--include-blank-columns) get the fallback too
+                .unwrap_or_else(|| format!("column_{}", i + 1)); // Fallback name if not found
+            // Rename if the header map has an entry for this column
+            match header_map.and_then(|map| map.get(&name)) {
+                Some(key) => key.clone(),
+                None => name,
+            }
+        })
+        .collect() // Collect into a vector of strings
+}
+
+/// Cleans the text of a header row before anything reads it
+/// 
+/// Applies normalize_keys, then split_case. With several header rows
+/// (--header-rows) each row is cleaned on its own before they are joined.
+/// 
+/// # Arguments
+/// * `header_row` - One row of header cells
+/// * `opts` - Conversion options
+/// 
+/// # Returns
+/// The row with its text cells cleaned; other cells are copied as they are
+/// 
+/// # Example
+/// With normalize_keys Nfc and split_case: ["CustomerID", "Cafe\u{301}", 2023] -> ["Customer ID", "Caf\u{e9}", 2023]
+pub fn clean_header_row(header_row: &[calamine::Data], opts: &ConvertOptions) -> Vec<calamine::Data> {
+    header_row
+        .iter()
+        .map(|cell| match cell {
+            calamine::Data::String(text) => calamine::Data::String(clean_header_text(text, opts)),
+            other => other.clone(),
+        })
+        .collect()
+}
+
+/// Cleans one header text as clean_header_row() does
+pub fn clean_header_text(text: &str, opts: &ConvertOptions) -> String {
+    let text = opts.normalize_keys.map_or_else(|| text.to_string(), |form| form.normalize(text));
+    if opts.split_case { split_case_words(&text) } else { text }
+}
+
+/// Finds the columns of a cleaned header row that get a key
+/// 
+/// # Arguments
+/// * `header_row` - The header row, cleaned by clean_header_row()
+/// * `width` - Number of columns in the sheet
+/// * `opts` - Conversion options
+/// 
+/// # Returns
+/// Every column index with include_blank_columns, otherwise those of
+/// get_visible_column_indices()
+pub fn header_column_indices(header_row: &[calamine::Data], width: usize, opts: &ConvertOptions) -> Vec<usize> {
+    if opts.include_blank_columns {
+        (0..width).collect()
+    } else {
+        get_visible_column_indices(header_row)
+    }
+}
+
+/// Builds the keys of the selected columns
+/// 
+/// Each header is normalized (extract_headers()) or renamed through
+/// header_map, then given the numeric_header_prefix if it is all digits and
+/// the key_prefix if it starts with a digit. The keys are still in snake_case:
+/// options name columns by them, and styled_keys() gives the keys as written.
+/// 
+/// # Arguments
+/// * `header_row` - The header row, cleaned by clean_header_row()
+/// * `column_indices` - The selected columns
+/// * `opts` - Conversion options
+/// 
+/// # Returns
+/// One key per selected column
+/// 
+/// # Example
+/// ["3d Model", "2023"] with key_prefix "col_" and numeric_header_prefix "y"
+/// -> ["col_3d_model", "y2023"]
+pub fn header_keys(header_row: &[calamine::Data], column_indices: &[usize], opts: &ConvertOptions) -> Vec<String> {
+    extract_headers(header_row, column_indices, Some(&opts.header_map))
+        .into_iter()
+        .map(|mut key| {
+            if let Some(prefix) = &opts.numeric_header_prefix
+                && is_numeric_key(&key)
+            {
+                key.insert_str(0, prefix);
+            }
+            if let Some(prefix) = &opts.key_prefix
+                && key.starts_with(|c: char| c.is_ascii_digit())
+            {
+                key.insert_str(0, prefix);
+            }
+            key
+        })
+        .collect()
+}
+
+/// Writes the keys from header_keys() in key_style
+/// 
+/// Keys chosen in header_map are kept as they are.
+/// 
+/// # Arguments
+/// * `keys` - The keys of a sheet's columns
+/// * `opts` - Conversion options
+/// 
+/// # Returns
+/// The written key of each column, in the same order
+/// 
+/// # Errors
+/// Returns error if two different keys are written the same ("column_1" and
+/// "column1" are both "column1" in camelCase)
+pub fn styled_keys(keys: &[String], opts: &ConvertOptions) -> Result<Vec<String>> {
+    let styled: Vec<String> = keys
+        .iter()
+        .map(|key| {
+            if opts.header_map.values().any(|mapped| mapped == key) {
+                key.clone()
+            } else {
+                opts.key_style.apply(key)
+            }
+        })
+        .collect();
+    for (position, written) in styled.iter().enumerate() {
+        if let Some(other) = (0..position).find(|&other| styled[other] == *written && keys[other] != keys[position]) {
+            anyhow::bail!(
+                "Keys '{}' and '{}' are both written as '{}' in key style {:?}",
+                keys[other],
+                keys[position],
+                written,
+                opts.key_style
+            );
+        }
+    }
+    Ok(styled)
+}
+
+/// Renames the keys of a record, in nested objects too
+/// 
+/// # Arguments
+/// * `record` - A converted record
+/// * `renames` - Key -> written key, e.g. from styled_keys()
+/// 
+/// # Returns
+/// The record with its fields in the same order; keys not in `renames` stay
+/// 
+/// # Example
+/// {"unit_price": 5, "id": 1} with unit_price -> unitPrice -> {"unitPrice": 5, "id": 1}
+pub fn rename_keys(record: Value, renames: &HashMap<String, String>) -> Value {
+    match record {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| {
+                    let key = renames.get(&key).cloned().unwrap_or(key);
+                    (key, rename_keys(value, renames))
+                })
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Characters treated as currency symbols by --strip-currency
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '₹', '₩', '₽', '฿', '₫', '₱', '¢'];
+
+/// Parses currency-formatted text such as "$1,234.56" or "€2.000,00" into a number
+/// 
+/// Currency symbols and whitespace are removed, thousands separators are
+/// validated (groups of exactly three digits) and removed, and the locale's
+/// decimal separator is turned into a dot. A value wrapped in parentheses,
+/// as in accounting formats, is negative.
+/// 
+/// # Arguments
+/// * `text` - The cell text to parse
+/// * `locale` - Thousands/decimal separator convention
+/// 
+/// # Returns
+/// Some(number) if the text is a well-formed number once symbols are removed, None otherwise
+/// 
+/// # Examples
+/// - "$1,234.56" (us) -> Some(1234.56)
+/// - "€2.000,00" (eu) -> Some(2000.0)
+/// - "(1,000)" (us) -> Some(-1000.0)
+/// - "1,2,3" (us) -> None
+fn parse_currency_number(text: &str, locale: NumberLocale) -> Option<f64> {
+    // Remove currency symbols and any kind of whitespace (including non-breaking spaces)
+    let mut cleaned: String = text
+        .chars()
+        .filter(|c| !CURRENCY_SYMBOLS.contains(c) && !c.is_whitespace())
+        .collect();
+
+    // Accounting negatives: "(1,000)" -> -1000
+    let mut negative = false;
+    if cleaned.starts_with('(') && cleaned.ends_with(')') && cleaned.len() > 2 {
+        negative = true;
+        cleaned = cleaned[1..cleaned.len() - 1].to_string();
+    }
+
+    let value = parse_grouped_number(&cleaned, locale)?;
+    Some(if negative { -value } else { value })
+}
+
+/// Parses plain numeric text such as "1.234,56" (eu) or "-1,234.56" (us) into a number
+/// 
+/// Unlike parse_currency_number() nothing is removed except surrounding
+/// whitespace, so "$5" or "1 234" stay text. Integers written with leading
+/// zeros ("007") stay text as well, as they are usually codes rather than
+/// quantities.
+/// 
+/// # Arguments
+/// * `text` - The cell text to parse
+/// * `locale` - Thousands/decimal separator convention
+/// 
+/// # Returns
+/// Some(number) if the whole text is a well-formed number, None otherwise
+/// 
+/// # Examples
+/// - "1.234,56" (eu) -> Some(1234.56)
+/// - "1.234,56" (us) -> None
+/// - "0,5" (eu) -> Some(0.5)
+/// - "007" -> None
+fn parse_numeric_text(text: &str, locale: NumberLocale) -> Option<f64> {
+    let text = text.trim();
+    let (_, decimal_sep) = locale.separators();
+    let int_part = text.trim_start_matches('-').split(decimal_sep).next().unwrap_or_default();
+    if int_part.len() > 1 && int_part.starts_with('0') {
+        return None;
+    }
+    parse_grouped_number(text, locale)
+}
+
+/// Parses an optionally negative number with the locale's thousands and decimal separators
+/// 
+/// Thousands separators are validated (groups of exactly three digits) and
+/// removed, and the decimal separator is turned into a dot.
+/// 
+/// # Examples
+/// - "1,234.56" (us) -> Some(1234.56)
+/// - "-2.000,5" (eu) -> Some(-2000.5)
+/// - "12,34" (us) -> None
+fn parse_grouped_number(text: &str, locale: NumberLocale) -> Option<f64> {
+    let (group_sep, decimal_sep) = locale.separators();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    // Split into integer and fractional parts on the decimal separator
+    let (int_part, frac_part) = match text.split_once(decimal_sep) {
+        Some((i, f)) => (i, Some(f)),
+        None => (text, None),
+    };
+
+    // Validate thousands grouping: first group 1-3 digits, the rest exactly 3
+    let groups: Vec<&str> = int_part.split(group_sep).collect();
+    let grouping_ok = groups.iter().all(|g| !g.is_empty() && g.chars().all(|c| c.is_ascii_digit()))
+        && (groups.len() == 1 || (groups[0].len() <= 3 && groups[1..].iter().all(|g| g.len() == 3)));
+    let fraction_ok = frac_part.is_none_or(|f| !f.is_empty() && f.chars().all(|c| c.is_ascii_digit()));
+    if !grouping_ok || !fraction_ok {
+        return None;
+    }
+
+    // Rebuild as a plain "1234.56" string and parse it
+    let mut plain = groups.concat();
+    if let Some(f) = frac_part {
+        plain.push('.');
+        plain.push_str(f);
+    }
+    let value: f64 = plain.parse().ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// Converts a floating point number to a JSON number
+/// 
+/// Excel stores every number as f64, so whole numbers are emitted as integers
+/// to serialize as `42` rather than `42.0`. Values with a fractional part or
+/// outside the i64 range stay floats; NaN/infinity become null.
+/// 
+/// With a precision (--float-precision) the value is first rounded to that many
+/// decimal places, see round_float().
+/// 
+/// # Examples
+/// - (42.0, None) -> 42
+/// - (42.5, None) -> 42.5
+/// - (1e20, None) -> 1e20 (too large for i64, stays a float)
+/// - (0.30000000000000004, Some(2)) -> 0.3
+fn number_to_json(value: f64, precision: Option<u32>) -> Value {
+    let value = match precision {
+        Some(digits) => round_float(value, digits),
+        None => value,
+    };
+    // i64::MAX as f64 rounds up to 2^63, so the upper bound is exclusive
+    let fits_i64 = value >= i64::MIN as f64 && value < i64::MAX as f64;
+    if value.fract() == 0.0 && fits_i64 {
+        return Value::from(value as i64);
+    }
+
+    serde_json::Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+/// Rounds a number to a fixed number of decimal places
+/// 
+/// The stored binary value is rounded correctly to the nearest number with
+/// `digits` decimals; a value exactly halfway rounds to the even digit. Since
+/// most decimal fractions aren't exact in binary, "halfway" values usually
+/// aren't: 2.675 is stored as 2.67499999... and rounds down.
+/// 
+/// # Examples
+/// - (0.30000000000000004, 2) -> 0.3
+/// - (1234.5678, 0) -> 1235.0
+/// - (0.125, 2) -> 0.12 (exactly halfway, to even)
+fn round_float(value: f64, digits: u32) -> f64 {
+    if !value.is_finite() {
+        return value;
+    }
+    // Formatting rounds correctly; parsing gives the f64 closest to the rounded decimal
+    format!("{:.*}", digits as usize, value).parse().unwrap_or(value)
+}
+
+/// Converts an Excel cell value to a JSON value
+/// 
+/// By default converts all cell values to strings to preserve formatting
+/// and handle cases where numbers represent identifiers (like bullet numbers)
+/// rather than numeric values.
+/// 
+/// With --infer-types, native JSON types are emitted instead:
+/// - Numbers -> JSON numbers
+/// - Booleans -> true/false
+/// - Empty cells -> null
+/// - Text -> string (or a number with --strip-currency, if it parses as one)
+/// 
+/// Text cells are first brought into the --normalize-unicode form (with
+/// --normalize-unicode-values) and trimmed according to --trim (see trim_text());
+/// text cells longer than --max-cell-length are cut afterwards (see truncate_text()).
+/// 
+/// # Arguments
+/// * `cell` - Reference to a cell from the Excel sheet
+/// * `opts` - Value conversion options
+/// * `stats` - Counters updated when a value is truncated
+/// 
+/// # Returns
+/// A serde_json::Value representing the cell content
+pub fn convert_cell_to_json(cell: &calamine::Data, opts: &ConvertOptions, stats: &mut ConvertStats) -> Value {
+    convert_cell(cell, opts, opts.infer_types, stats)
+}
+
+/// Converts a cell like convert_cell_to_json(), with type inference switched separately
+/// 
+/// Lets a cell that doesn't fit its column's sampled type (--sample-size) be
+/// written as text without copying the options.
+fn convert_cell(cell: &calamine::Data, opts: &ConvertOptions, infer_types: bool, stats: &mut ConvertStats) -> Value {
+    // Dates stored as ISO text (ODS files, "d" cells in xlsx) are handled like date serials,
+    // so both storage styles give the same output under every --dates mode
+    let from_iso;
+    let cell = match iso_cell_to_datetime(cell, opts.date1904) {
+        Some(datetime) => {
+            from_iso = calamine::Data::DateTime(datetime);
+            &from_iso
+        }
+        None => cell, // Anything else, including ISO text that doesn't parse
+    };
+
+    // Normalize before trimming, so NFKC-folded spaces (e.g. U+3000) are trimmed as well
+    let normalized;
+    let cell = match (cell, opts.normalize_values) {
+        (calamine::Data::String(text), Some(form)) => {
+            normalized = calamine::Data::String(form.normalize(text));
+            &normalized
+        }
+        _ => cell,
+    };
+
+    // --trim only touches text cells - numbers, booleans and dates keep their value
+    let trimmed;
+    let cell = match cell {
+        calamine::Data::String(text) if opts.trim != TrimMode::None => {
+            trimmed = calamine::Data::String(trim_text(text, opts.trim));
+            &trimmed
+        }
+        other => other,
+    };
+
+    let value = if let (calamine::Data::DateTime(date), DateMode::Iso | DateMode::Time) = (cell, opts.dates) {
+        // Dates are text in JSON either way, so this doesn't depend on --infer-types
+        let serial = date.as_f64();
+        let text = if opts.dates == DateMode::Time && (date.is_duration() || (0.0..1.0).contains(&serial)) {
+            // Below one day there's no date part - only the time of day (or elapsed time) matters
+            excel_serial_to_time(serial)
+        } else {
+            excel_serial_to_iso(serial, opts.date1904)
+        };
+        Value::String(text.unwrap_or_else(|| cell.to_string())) // Out-of-range serials stay as they are
+    } else if let (calamine::Data::DateTime(date), DateMode::Epoch) = (cell, opts.dates)
+        && !date.is_duration()
+    {
+        // A number even without --infer-types; elapsed times ([h]:mm:ss) aren't points in time
+        excel_serial_to_epoch(date.as_f64(), opts.date1904)
+            .map(Value::from)
+            .unwrap_or_else(|| Value::String(cell.to_string()))
+    } else if !infer_types {
+        // Convert all values to strings to preserve formatting
+        // This is useful for bullet numbers, IDs, and other non-numeric data
+        Value::String(cell.to_string())
+    } else {
+        match cell {
+            calamine::Data::Int(i) => Value::from(*i),
+            calamine::Data::Float(f) => number_to_json(*f, opts.float_precision),
+            calamine::Data::Bool(b) => Value::Bool(*b),
+            calamine::Data::Empty => Value::Null,
+            calamine::Data::String(s) if opts.strip_currency => parse_currency_number(s, opts.locale)
+                .map(|number| number_to_json(number, opts.float_precision)) // Currency/grouped text that parses as a number
+                .unwrap_or_else(|| Value::String(s.clone())), // Anything else stays text
+            calamine::Data::String(s) if opts.parse_numeric_text => parse_numeric_text(s, opts.locale)
+                .map(|number| number_to_json(number, opts.float_precision)) // "1.234,56" with --decimal-separator comma
+                .unwrap_or_else(|| Value::String(s.clone())),
+            // Text, dates, durations and error values keep their display form
+            other => Value::String(other.to_string()),
+        }
+    };
+
+    // Only text cells are cut - numbers and booleans stay intact even when stringified
+    match (value, opts.max_cell_length) {
+        (Value::String(text), Some(max)) if matches!(cell, calamine::Data::String(_)) => {
+            match truncate_text(&text, max, &opts.truncate_marker) {
+                Some(short) => {
+                    stats.truncated_cells += 1;
+                    Value::String(short)
+                }
+                None => Value::String(text),
+            }
+        }
+        (value, _) => value,
+    }
+}
+
+/// Converts a cell of a --numeric-columns column to a JSON number
+/// 
+/// Numeric cells keep their value; text is parsed with the locale's grouping
+/// and decimal separators (as with --strip-currency), so "1,234" becomes 1234.
+/// Text that isn't a number is counted in `stats` and converted like any
+/// other cell, as are empty, boolean and date cells.
+/// 
+/// # Examples
+/// - String("1,234.5") (us) -> 1234.5
+/// - Float(42.0) -> 42
+/// - String("n/a") -> "n/a" (numeric_fallbacks + 1)
+fn convert_numeric_cell(cell: &calamine::Data, opts: &ConvertOptions, stats: &mut ConvertStats) -> Value {
+    match cell {
+        calamine::Data::Int(i) => Value::from(*i),
+        calamine::Data::Float(f) => number_to_json(*f, opts.float_precision),
+        calamine::Data::String(text) if !text.trim().is_empty() => {
+            // Full-width digits ("１２３") only parse once nfkc has folded them
+            let text = match opts.normalize_values {
+                Some(form) => Cow::Owned(form.normalize(text)),
+                None => Cow::Borrowed(text.as_str()),
+            };
+            match parse_currency_number(&text, opts.locale) {
+                Some(number) => number_to_json(number, opts.float_precision),
+                None => {
+                    stats.numeric_fallbacks += 1;
+                    convert_cell_to_json(cell, opts, stats)
+                }
+            }
+        }
+        other => convert_cell_to_json(other, opts, stats),
+    }
+}
+
+/// Converts a cell of a --bool-columns column to a JSON boolean
+/// 
+/// The numbers 1 and 0 (also as text, e.g. "1") become true and false, and
+/// boolean cells stay booleans. Empty cells are converted like any other cell;
+/// any other value is too, and is counted in `stats`.
+/// 
+/// # Examples
+/// - Float(1.0) -> true
+/// - String(" 0 ") -> false
+/// - Float(2.0) -> "2" (bool_fallbacks + 1)
+fn convert_bool_cell(cell: &calamine::Data, opts: &ConvertOptions, stats: &mut ConvertStats) -> Value {
+    let number = match cell {
+        calamine::Data::Bool(b) => return Value::Bool(*b),
+        calamine::Data::Empty => return convert_cell_to_json(cell, opts, stats),
+        calamine::Data::String(text) if text.trim().is_empty() => return convert_cell_to_json(cell, opts, stats),
+        calamine::Data::Int(i) => Some(*i as f64),
+        calamine::Data::Float(f) => Some(*f),
+        calamine::Data::String(text) => text.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    match number {
+        Some(1.0) => Value::Bool(true),
+        Some(0.0) => Value::Bool(false),
+        _ => {
+            stats.bool_fallbacks += 1;
+            convert_cell_to_json(cell, opts, stats)
+        }
+    }
+}
+
+/// Converts a cell of a --date-columns column, reading text as a date
+/// 
+/// Text matching the --date-input-format becomes a date cell, so it is written
+/// in the --dates format like a date stored as a serial. Text that doesn't match
+/// is kept as text and counted in `stats`; other cells (real dates, numbers,
+/// empty cells) are converted as usual.
+/// 
+/// # Examples
+/// With "%m/%d/%Y" and --dates iso:
+/// - String("03/15/2023") -> "2023-03-15"
+/// - String("next week") -> "next week" (date_fallbacks + 1)
+fn convert_date_text_cell(cell: &calamine::Data, opts: &ConvertOptions, stats: &mut ConvertStats) -> Value {
+    let calamine::Data::String(text) = cell else {
+        return convert_cell_to_json(cell, opts, stats);
+    };
+    if text.trim().is_empty() {
+        return convert_cell_to_json(cell, opts, stats);
+    }
+    match parse_date_text(text.trim(), &opts.date_input_format, opts.date1904) {
+        Some(seconds) => {
+            let date = ExcelDateTime::new(seconds / 86_400.0, ExcelDateTimeType::DateTime, opts.date1904);
+            convert_cell_to_json(&calamine::Data::DateTime(date), opts, stats)
+        }
+        None => {
+            stats.date_fallbacks += 1;
+            convert_cell(cell, opts, false, stats) // The original text, even with --infer-types
+        }
+    }
+}
+
+/// Parses text in a chrono format into seconds of the workbook's date system
+/// 
+/// The format may describe a date and time, a date only (midnight) or a time
+/// of day only (a fraction of a day, like a time-formatted serial).
+/// 
+/// # Returns
+/// None if the text doesn't match the format or is outside the supported date range
+/// 
+/// # Examples
+/// - ("03/15/2023", "%m/%d/%Y") -> seconds of serial 45000
+/// - ("15.03.2023 12:00", "%d.%m.%Y %H:%M") -> seconds of serial 45000.5
+fn parse_date_text(text: &str, format: &str, date1904: bool) -> Option<f64> {
+    if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(text, format) {
+        return datetime_to_serial_seconds(datetime, date1904);
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(text, format) {
+        return datetime_to_serial_seconds(date.and_hms_opt(0, 0, 0)?, date1904);
+    }
+    let time = chrono::NaiveTime::parse_from_str(text, format).ok()?;
+    Some(chrono::Timelike::num_seconds_from_midnight(&time) as f64)
+}
+
+/// Converts an Excel date serial number to ISO 8601 text
+/// 
+/// In the 1900 date system serial 1 is 1900-01-01, and Excel treats 1900 as a
+/// leap year (serial 60 is the non-existent 1900-02-29), so serials before that
+/// are shifted by a day. In the 1904 date system (used by older Mac workbooks)
+/// serial 0 is 1904-01-01.
+/// 
+/// # Arguments
+/// * `serial` - Days since the epoch; the fraction is the time of day
+/// * `date1904` - Whether the workbook uses the 1904 date system
+/// 
+/// # Returns
+/// "YYYY-MM-DD" for whole days, "YYYY-MM-DDTHH:MM:SS" otherwise,
+/// or None if the serial is outside the supported date range
+/// 
+/// # Examples
+/// - (45000.0, false) -> Some("2023-03-15")
+/// - (45000.5, false) -> Some("2023-03-15T12:00:00")
+/// - (43538.0, true) -> Some("2023-03-15")
+fn excel_serial_to_iso(serial: f64, date1904: bool) -> Option<String> {
+    let datetime = excel_serial_to_datetime(serial, date1904)?;
+    if datetime.time() == chrono::NaiveTime::MIN {
+        Some(datetime.format("%Y-%m-%d").to_string())
+    } else {
+        Some(datetime.format("%Y-%m-%dT%H:%M:%S").to_string())
+    }
+}
+
+/// Converts an Excel date serial number to Unix time (--dates epoch)
+/// 
+/// Excel dates carry no time zone, so they are taken as UTC. The time of day
+/// adds its seconds; see excel_serial_to_iso() for the date systems.
+/// 
+/// # Returns
+/// Whole seconds since 1970-01-01 00:00:00 UTC (negative before 1970),
+/// or None if the serial is outside the supported date range
+/// 
+/// # Examples
+/// - (45000.0, false) -> Some(1678838400) (2023-03-15)
+/// - (45000.5, false) -> Some(1678881600) (2023-03-15T12:00:00)
+/// - (43538.0, true) -> Some(1678838400)
+/// - (25569.0, false) -> Some(0)
+fn excel_serial_to_epoch(serial: f64, date1904: bool) -> Option<i64> {
+    excel_serial_to_datetime(serial, date1904).map(|datetime| datetime.and_utc().timestamp())
+}
+
+/// Converts an Excel date serial number to a date and time, rounded to whole seconds
+/// 
+/// # Returns
+/// None if the serial is negative, not finite or beyond the supported date range
+fn excel_serial_to_datetime(serial: f64, date1904: bool) -> Option<chrono::NaiveDateTime> {
+    if !serial.is_finite() || serial < 0.0 {
+        return None;
+    }
+
+    // Day zero of each date system (1899-12-30 makes the 1900 system correct from serial 61 on)
+    let (epoch, serial) = if date1904 {
+        (chrono::NaiveDate::from_ymd_opt(1904, 1, 1)?, serial)
+    } else if serial < 60.0 {
+        (chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?, serial + 1.0) // Before the phantom leap day
+    } else {
+        (chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?, serial)
+    };
+
+    // Round to whole seconds so 0.999999... of a day doesn't show up as 23:59:59
+    let seconds = (serial * 86_400.0).round() as i64;
+    epoch
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(chrono::Duration::seconds(seconds))
+}
+
+/// Converts a date or duration cell stored as ISO 8601 text to an Excel serial
+/// 
+/// Some files keep dates as text instead of serial numbers: ODS files always,
+/// xlsx files in cells of type "d". The serial is counted in the workbook's
+/// date system, exactly as if the file had stored the number.
+/// 
+/// # Arguments
+/// * `cell` - The cell; only DateTimeIso and DurationIso cells are converted
+/// * `date1904` - Whether the workbook uses the 1904 date system
+/// 
+/// # Returns
+/// The equivalent date/time value, or None for other cells and text that doesn't parse
+/// 
+/// # Examples
+/// - DateTimeIso("2023-03-15") -> serial 45000
+/// - DateTimeIso("2023-03-15T12:00:00") -> serial 45000.5
+/// - DateTimeIso("12:00:00") -> serial 0.5
+/// - DurationIso("PT25H30M0S") -> duration 1.0625
+fn iso_cell_to_datetime(cell: &calamine::Data, date1904: bool) -> Option<ExcelDateTime> {
+    match cell {
+        calamine::Data::DateTimeIso(text) => {
+            let text = text.trim();
+            let seconds = if let Ok(time) = text.parse::<chrono::NaiveTime>() {
+                // Time of day only - a fraction of a day, like a time-formatted serial
+                chrono::Timelike::num_seconds_from_midnight(&time) as f64
+            } else {
+                let datetime = text
+                    .parse::<chrono::NaiveDateTime>()
+                    .ok()
+                    .or_else(|| Some(chrono::DateTime::parse_from_rfc3339(text).ok()?.naive_local()))
+                    .or_else(|| text.parse::<chrono::NaiveDate>().ok()?.and_hms_opt(0, 0, 0))?;
+                datetime_to_serial_seconds(datetime, date1904)?
+            };
+            Some(ExcelDateTime::new(seconds / 86_400.0, ExcelDateTimeType::DateTime, date1904))
+        }
+        calamine::Data::DurationIso(text) => {
+            let days = parse_iso_duration(text.trim())?;
+            Some(ExcelDateTime::new(days, ExcelDateTimeType::TimeDelta, date1904))
+        }
+        _ => None,
+    }
+}
+
+/// Seconds from the start of the workbook's date system to `datetime`
+/// 
+/// The inverse of excel_serial_to_iso(): dates before 1900-03-01 are shifted
+/// back by the day Excel adds for its phantom 1900-02-29.
+fn datetime_to_serial_seconds(datetime: chrono::NaiveDateTime, date1904: bool) -> Option<f64> {
+    let epoch = if date1904 {
+        chrono::NaiveDate::from_ymd_opt(1904, 1, 1)?
+    } else {
+        chrono::NaiveDate::from_ymd_opt(1899, 12, 30)?
+    };
+    let millis = (datetime - epoch.and_hms_opt(0, 0, 0)?).num_milliseconds() as f64;
+    let seconds = millis / 1000.0;
+    if !date1904 && seconds < 61.0 * 86_400.0 {
+        Some(seconds - 86_400.0) // Before the phantom leap day
+    } else {
+        Some(seconds)
+    }
+}
+
+/// Parses an ISO 8601 duration (as stored in ODS time cells) into days
+/// 
+/// Weeks and days are read before the "T", hours, minutes and (fractional)
+/// seconds after it. Years and months have no fixed length and aren't accepted.
+/// 
+/// # Examples
+/// - "PT25H30M0S" -> Some(1.0625)
+/// - "P1DT12H" -> Some(1.5)
+/// - "-PT6H" -> Some(-0.25)
+/// - "P1M" -> None
+fn parse_iso_duration(text: &str) -> Option<f64> {
+    let (sign, rest) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text),
+    };
+    let rest = rest.strip_prefix('P')?;
+
+    let mut seconds = 0.0;
+    let mut in_time = false;
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            'T' if !in_time && number.is_empty() => in_time = true,
+            '0'..='9' | '.' => number.push(c),
+            ',' => number.push('.'), // Decimal comma is allowed by ISO 8601
+            unit => {
+                let unit_seconds = match (in_time, unit) {
+                    (false, 'W') => 604_800.0,
+                    (false, 'D') => 86_400.0,
+                    (true, 'H') => 3_600.0,
+                    (true, 'M') => 60.0,
+                    (true, 'S') => 1.0,
+                    _ => return None,
+                };
+                seconds += number.parse::<f64>().ok()? * unit_seconds;
+                number.clear();
+            }
+        }
+    }
+    if !number.is_empty() {
+        return None; // A number without its unit
+    }
+    Some(sign * seconds / 86_400.0)
+}
+
+/// Removes whitespace from text according to --trim
+/// 
+/// Any Unicode whitespace counts, including non-breaking spaces and line breaks.
+/// 
+/// # Examples
+/// - ("  New   York ", Ends) -> "New   York"
+/// - ("  New   York ", Collapse) -> "New York"
+fn trim_text(text: &str, mode: TrimMode) -> String {
+    match mode {
+        TrimMode::None => text.to_string(),
+        TrimMode::Ends => text.trim().to_string(),
+        TrimMode::Collapse => text.split_whitespace().collect::<Vec<_>>().join(" "),
+    }
+}
+
+/// Converts an Excel time or duration value (a fraction of days) to "HH:MM:SS" text
+/// 
+/// Hours are not wrapped at 24, so elapsed time keeps its full length, and
+/// negative durations get a leading "-".
+/// 
+/// # Returns
+/// The formatted time, or None if the value isn't a finite number
+/// 
+/// # Examples
+/// - 0.5 -> Some("12:00:00")
+/// - 1.0625 -> Some("25:30:00")
+/// - -0.25 -> Some("-06:00:00")
+fn excel_serial_to_time(serial: f64) -> Option<String> {
+    if !serial.is_finite() {
+        return None;
+    }
+
+    // Round to whole seconds, as excel_serial_to_iso() does
+    let seconds = (serial * 86_400.0).round();
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let seconds = seconds.abs() as u64;
+    Some(format!(
+        "{}{:02}:{:02}:{:02}",
+        sign,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    ))
+}
+
+/// Cuts text to at most `max` characters, appending `marker` if anything was removed
+/// 
+/// Characters are Unicode scalar values, so multibyte text is never split mid-character.
+/// 
+/// # Returns
+/// The shortened text, or None if the text already fits
+/// 
+/// # Examples
+/// - ("สวัสดีครับ", 5, "…") -> Some("สวัสด…")
+/// - ("short", 10, "") -> None
+fn truncate_text(text: &str, max: usize, marker: &str) -> Option<String> {
+    // Byte offset of the first character past the limit, if there is one
+    let (cut, _) = text.char_indices().nth(max)?;
+    Some(format!("{}{}", &text[..cut], marker))
+}
+
+/// Applies the --replace rules for one column to a cell
+/// 
+/// The rules work on the cell's string form. A cell no rule matches keeps its
+/// type; a rewritten text cell stays text, and a rewritten number, boolean or
+/// date is inferred again from its new text (see default_cell()), so it is
+/// still a number under --infer-types if the result is one.
+/// Empty cells are left alone.
+/// 
+/// # Arguments
+/// * `cell` - The original cell
+/// * `rules` - Rules that apply to this cell's column, in order
+/// * `infer_types` - Whether a rewritten non-text cell may become a number or boolean again
+/// 
+/// # Returns
+/// The rewritten cell, or the original (borrowed, not copied) if no rule matches
+/// 
+/// # Examples
+/// - (Float(1200.0), "0+$=>", true) -> Float(12.0)
+/// - (Float(42.0), "x=>", true) -> Float(42.0), unchanged
+/// - (String("ID-7"), "^ID-=>", true) -> String("7")
+fn apply_replace_rules<'c>(cell: &'c calamine::Data, rules: &[&ReplaceRule], infer_types: bool) -> Cow<'c, calamine::Data> {
+    if rules.is_empty() || matches!(cell, calamine::Data::Empty) {
+        return Cow::Borrowed(cell);
+    }
+
+    let mut matched = false;
+    let replaced = rules.iter().fold(cell.to_string(), |text, rule| {
+        match rule.pattern.replace_all(&text, rule.replacement.as_str()) {
+            Cow::Owned(rewritten) => {
+                matched = true;
+                rewritten
+            }
+            Cow::Borrowed(_) => text, // No match - replace_all() didn't copy
+        }
+    });
+    match cell {
+        _ if !matched => Cow::Borrowed(cell),
+        calamine::Data::String(_) => Cow::Owned(calamine::Data::String(replaced)),
+        _ => Cow::Owned(default_cell(&replaced, infer_types)),
+    }
+}
+
+/// Whether a cell counts as empty for --defaults: no value or empty text
+fn is_blank_cell(cell: &calamine::Data) -> bool {
+    match cell {
+        calamine::Data::Empty => true,
+        calamine::Data::String(text) => text.is_empty(),
+        _ => false,
+    }
+}
+
+/// Builds the cell that stands in for an empty cell under --defaults
+/// 
+/// With --infer-types, "true"/"false" become booleans and numeric text a number,
+/// so the default has the type the column's real values would have. Otherwise
+/// every value is text, like all other cells.
+/// 
+/// # Examples
+/// - ("false", true) -> Bool(false)
+/// - ("0", true) -> Float(0.0), written as 0
+/// - ("Unknown", true) -> String("Unknown")
+/// - ("0", false) -> String("0")
+fn default_cell(text: &str, infer_types: bool) -> calamine::Data {
+    if infer_types {
+        if let Ok(b) = text.parse::<bool>() {
+            return calamine::Data::Bool(b);
+        }
+        // "nan"/"inf" parse as floats too, but are meant as text
+        if let Ok(n) = text.parse::<f64>()
+            && n.is_finite()
+        {
+            return calamine::Data::Float(n);
+        }
+    }
+    calamine::Data::String(text.to_string())
+}
+
+/// Splits a cell's text into a JSON array of strings for --split-cells
+/// 
+/// Each element is trimmed and empty elements are dropped, so "a; ;b;" -> ["a", "b"].
+/// 
+/// # Arguments
+/// * `cell` - The cell (after --replace rules)
+/// * `delimiter` - Text separating the values
+/// * `empty` - What an empty cell becomes
+fn split_cell_to_json(cell: &calamine::Data, delimiter: &str, empty: SplitEmpty) -> Value {
+    let text = cell.to_string();
+    if text.trim().is_empty() && empty == SplitEmpty::Null {
+        return Value::Null;
+    }
+
+    let items: Vec<Value> = text
+        .split(delimiter)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| Value::String(item.to_string()))
+        .collect();
+    Value::Array(items)
+}
+
+/// Converts Excel rows to JSON objects
+/// 
+/// Each row becomes a JSON object where keys are the normalized column headers
+/// and values are the cell contents. Rows are converted lazily, so records can be
+/// written out one at a time without holding them all in memory.
+/// 
+/// # Arguments
+/// * `rows` - Iterator over (data row index, row) pairs, excluding the header row(s);
+///   the index positions annotations, so it must count skipped rows too. A row is
+///   anything that derefs to a cell slice, borrowed from a Range or owned
+/// * `headers` - Vector of normalized column header names
+/// * `column_indices` - Vector of column indices to include in the output
+/// * `opts` - Value conversion options passed to convert_cell_to_json()
+/// * `annotations` - Extra per-cell values (hyperlinks, ...) emitted as "<key>_<suffix>"
+/// * `stats` - Counters updated while converting cells
+/// 
+/// `headers`, `column_indices`, `annotations` and `stats` may be borrowed or
+/// owned; owned ones move into the iterator (see convert_sheet_iter()).
+/// 
+/// With --sample-size, the first rows are read ahead to fix each column's type
+/// (see sample_column_kinds()). Every cell of a text column is written as text;
+/// cells of a number or boolean column that have another type are written as
+/// text too, and counted in `stats`. With --strict-types the records end before
+/// the first such row, which stats.first_type_fallback then points to.
+/// 
+/// # Returns
+/// An iterator of JSON values, where each value is an object representing one row
+/// (rows not matching the --where conditions are left out)
+/// 
+/// # Example
+/// Input row: ["John", "25", "john@example.com"]
+/// Headers: ["name", "age", "email"]
+/// Output: {"name": "John", "age": "25", "email": "john@example.com"}
+pub fn convert_rows_to_json<'a, R: AsRef<[calamine::Data]> + 'a>(
+    rows: impl Iterator<Item = (usize, R)> + 'a,
+    headers: impl AsRef<[String]> + 'a,
+    column_indices: impl AsRef<[usize]> + 'a,
+    opts: &'a ConvertOptions,
+    annotations: impl Borrow<CellAnnotations> + 'a,
+    stats: impl BorrowMut<ConvertStats> + 'a,
+) -> impl Iterator<Item = Value> + 'a {
+    convert_rows(rows, headers, column_indices, opts, annotations, LocalStats(stats))
+}
+
+/// Access to the ConvertStats a conversion updates
+trait StatsHandle {
+    /// Runs `f` with the stats
+    fn update<T>(&mut self, f: impl FnOnce(&mut ConvertStats) -> T) -> T;
+}
+
+/// Stats owned or borrowed by the caller of convert_rows_to_json()
+struct LocalStats<B>(B);
+
+impl<B: BorrowMut<ConvertStats>> StatsHandle for LocalStats<B> {
+    fn update<T>(&mut self, f: impl FnOnce(&mut ConvertStats) -> T) -> T {
+        f(self.0.borrow_mut())
+    }
+}
+
+/// Stats shared with SheetRecords, which reads them once the records end
+impl StatsHandle for Arc<Mutex<ConvertStats>> {
+    fn update<T>(&mut self, f: impl FnOnce(&mut ConvertStats) -> T) -> T {
+        f(&mut self.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// convert_rows_to_json() with the stats behind a StatsHandle
+fn convert_rows<'a, R: AsRef<[calamine::Data]> + 'a>(
+    rows: impl Iterator<Item = (usize, R)> + 'a,
+    headers: impl AsRef<[String]> + 'a,
+    column_indices: impl AsRef<[usize]> + 'a,
+    opts: &'a ConvertOptions,
+    annotations: impl Borrow<CellAnnotations> + 'a,
+    mut stats: impl StatsHandle + 'a,
+) -> impl Iterator<Item = Value> + 'a {
+    // Look up the --replace rules for each selected column once, up front
+    let rules_per_column: Vec<Vec<&ReplaceRule>> = headers
+        .as_ref()
+        .iter()
+        .map(|header| opts.replace_rules.iter().filter(|r| &r.column == header).collect())
+        .collect();
+    // ... and whether it is listed in --numeric-columns
+    let numeric_per_column: Vec<bool> = headers.as_ref().iter().map(|header| opts.numeric_columns.contains(header)).collect();
+    // ... or in --string-columns
+    let string_per_column: Vec<bool> = headers.as_ref().iter().map(|header| opts.string_columns.contains(header)).collect();
+    // ... or in --bool-columns
+    let bool_per_column: Vec<bool> = headers.as_ref().iter().map(|header| opts.bool_columns.contains(header)).collect();
+    // ... or in --date-columns
+    let date_per_column: Vec<bool> = headers.as_ref().iter().map(|header| opts.date_columns.contains(header)).collect();
+    // ... and the --split-cells delimiter (the last rule wins if a column has several)
+    let split_per_column: Vec<Option<&str>> = headers
+        .as_ref()
+        .iter()
+        .map(|header| {
+            opts.split_rules
+                .iter()
+                .rfind(|r| &r.column == header)
+                .map(|r| r.delimiter.as_str())
+        })
+        .collect();
+
+    // ... and the --defaults value, typed the way the column's cells would be
+    let default_per_column: Vec<Option<calamine::Data>> = headers
+        .as_ref()
+        .iter()
+        .map(|header| opts.defaults.get(header).map(|text| default_cell(text, opts.infer_types)))
+        .collect();
+
+    // With --sample-size, read ahead to fix each column's type (the sampled rows are converted as usual)
+    let mut rows = rows;
+    let sample_rows = opts.sample_size.filter(|_| opts.infer_types).unwrap_or(0);
+    let sample: Vec<(usize, R)> = rows.by_ref().take(sample_rows).collect();
+    let kind_per_column = if sample_rows > 0 {
+        sample_column_kinds(&sample, column_indices.as_ref(), opts, &rules_per_column, &default_per_column)
+    } else {
+        vec![None; column_indices.as_ref().len()]
+    };
+
+    sample.into_iter().chain(rows).map_while(move |(row_idx, row)| stats.update(|stats| {
+        let (headers, column_indices) = (headers.as_ref(), column_indices.as_ref());
+        let annotations = annotations.borrow();
+        // Create a JSON object for this row, sized for one key per column
+        let mut json_obj = serde_json::Map::with_capacity(column_indices.len());
+        for (header_idx, &col_idx) in column_indices.iter().enumerate() {
+            // Get cell value (after --replace and --defaults) or None if the cell doesn't exist
+            let cell = resolve_cell(
+                row.as_ref(),
+                col_idx,
+                &rules_per_column[header_idx],
+                &default_per_column[header_idx],
+                opts.infer_types,
+            );
+            let value = match (cell, split_per_column[header_idx]) {
+                // --split-cells columns hold an array (or null) even where the cell is missing
+                (cell, Some(delimiter)) => split_cell_to_json(
+                    cell.as_deref().unwrap_or(&calamine::Data::Empty),
+                    delimiter,
+                    opts.split_empty,
+                ),
+                // Identifier-like columns (phone numbers, zip codes) are written as without --infer-types
+                (Some(cell), None) if string_per_column[header_idx] => convert_cell(&cell, opts, false, stats),
+                (Some(cell), None) if numeric_per_column[header_idx] => {
+                    let fallbacks = stats.numeric_fallbacks;
+                    let value = convert_numeric_cell(&cell, opts, stats);
+                    if stats.numeric_fallbacks > fallbacks {
+                        stats.first_numeric_fallback.get_or_insert((row_idx, header_idx));
+                        if opts.strict_numeric {
+                            return None; // --strict: no further records
+                        }
+                    }
+                    value
+                }
+                (Some(cell), None) if bool_per_column[header_idx] => {
+                    let fallbacks = stats.bool_fallbacks;
+                    let value = convert_bool_cell(&cell, opts, stats);
+                    if stats.bool_fallbacks > fallbacks {
+                        stats.first_bool_fallback.get_or_insert((row_idx, header_idx));
+                        if opts.strict_bool {
+                            return None; // --strict-bool-columns: no further records
+                        }
+                    }
+                    value
+                }
+                (Some(cell), None) if date_per_column[header_idx] => convert_date_text_cell(&cell, opts, stats),
+                (Some(cell), None) => match kind_per_column[header_idx] {
+                    None => convert_cell_to_json(&cell, opts, stats), // Convert to JSON if found
+                    // Text columns stay text throughout, numbers included (--sample-size)
+                    Some(ColumnKind::Text) => convert_cell(&cell, opts, false, stats),
+                    // A cell that doesn't fit a number/boolean column is kept as text
+                    Some(expected) => match ColumnKind::of_cell(&cell, opts) {
+                        Some(found) if found != expected => {
+                            stats.type_fallbacks += 1;
+                            stats.first_type_fallback.get_or_insert((row_idx, header_idx));
+                            if opts.strict_types {
+                                return None; // --strict-types: no further records
+                            }
+                            convert_cell(&cell, opts, false, stats)
+                        }
+                        _ => convert_cell_to_json(&cell, opts, stats),
+                    },
+                },
+                (None, None) => Value::Null, // Use null if cell is missing
+            };
+            // Add key-value pair: (header_name, cell_value)
+            json_obj.insert(headers[header_idx].clone(), value);
+
+            // Add "<key>_<suffix>" fields for annotated cells (e.g. hyperlinks)
+            for (suffix, text) in annotations.get(row_idx, col_idx) {
+                json_obj.insert(format!("{}_{}", headers[header_idx], suffix), Value::String(text.clone()));
+            }
+        }
+        match opts.empty {
+            EmptyMode::Keep => {}
+            EmptyMode::Null => {
+                for value in json_obj.values_mut().filter(|value| value.as_str() == Some("")) {
+                    *value = Value::Null;
+                }
+            }
+            // Sparse records: drop keys that ended up empty after type inference (--compact-empty)
+            EmptyMode::Omit => json_obj.retain(|_, value| !matches!(value, Value::Null) && value.as_str() != Some("")),
+        }
+        if opts.sort_keys {
+            // Deterministic key order regardless of column order (--sort-keys)
+            json_obj.sort_keys();
+        }
+        Some(Value::Object(json_obj)) // Wrap the Map as a JSON Value (no copy)
+    }))
+    .filter(move |record| matches_conditions(record, &opts.conditions)) // Apply --where
+}
+
+/// Looks up a row's cell for one column, after --replace and --defaults
+/// 
+/// # Arguments
+/// * `row` - The data row
+/// * `col_idx` - Actual (0-based) column index
+/// * `rules` - The column's --replace rules
+/// * `default` - The column's --defaults cell, if it has one
+/// * `infer_types` - Whether --infer-types is on (see apply_replace_rules())
+/// 
+/// # Returns
+/// The cell to convert, or None if the row has no such cell and no default applies
+fn resolve_cell<'c>(
+    row: &'c [calamine::Data],
+    col_idx: usize,
+    rules: &[&ReplaceRule],
+    default: &'c Option<calamine::Data>,
+    infer_types: bool,
+) -> Option<Cow<'c, calamine::Data>> {
+    let cell = row
+        .get(col_idx) // Try to get the cell at this column index
+        .map(|cell| apply_replace_rules(cell, rules, infer_types)); // Rewrite via --replace
+    // Empty and missing cells take the column's --defaults value, if it has one
+    match (cell, default) {
+        (None, Some(default)) => Some(Cow::Borrowed(default)),
+        (Some(cell), Some(default)) if is_blank_cell(&cell) => Some(Cow::Borrowed(default)),
+        (cell, _) => cell,
+    }
+}
+
+/// Decides each column's type from the sampled rows (--sample-size)
+/// 
+/// A column is a number or boolean column if every non-empty sampled cell is
+/// one, and a text column as soon as the kinds differ. Columns without any
+/// non-empty sampled cell get no type, so their cells are inferred one by one.
+/// 
+/// # Returns
+/// One entry per selected column, in `column_indices` order
+/// 
+/// # Example
+/// Sampled "age" cells: 25, 31, (empty) -> Number; a later "n/a" is written as text
+fn sample_column_kinds<R: AsRef<[calamine::Data]>>(
+    sample: &[(usize, R)],
+    column_indices: &[usize],
+    opts: &ConvertOptions,
+    rules_per_column: &[Vec<&ReplaceRule>],
+    default_per_column: &[Option<calamine::Data>],
+) -> Vec<Option<ColumnKind>> {
+    column_indices
+        .iter()
+        .enumerate()
+        .map(|(header_idx, &col_idx)| {
+            sample
+                .iter()
+                .filter_map(|(_, row)| {
+                    let cell = resolve_cell(
+                        row.as_ref(),
+                        col_idx,
+                        &rules_per_column[header_idx],
+                        &default_per_column[header_idx],
+                        opts.infer_types,
+                    )?;
+                    ColumnKind::of_cell(&cell, opts)
+                })
+                .reduce(|kind, next| if kind == next { kind } else { ColumnKind::Text })
+        })
+        .collect()
+}
+
+/// Comparison operator of a --where condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    /// ==
+    Eq,
+    /// !=
+    Ne,
+    /// >
+    Gt,
+    /// >=
+    Ge,
+    /// <
+    Lt,
+    /// <=
+    Le,
+    /// contains (substring match)
+    Contains,
+}
+
+/// One "column op value" comparison parsed from --where
+#[derive(Debug, Clone)]
+pub struct Condition {
+    /// Normalized header name (JSON key) of the column to test
+    pub column: String,
+    /// How the column's value is compared
+    pub op: CompareOp,
+    /// Literal to compare against (quotes removed)
+    pub value: String,
+}
+
+/// Parsed --where expression - every condition must hold for a row to be kept
+#[derive(Debug, Clone)]
+pub struct WhereClause(pub Vec<Condition>);
+
+/// Parses a --where expression: conditions joined with "and"
+/// 
+/// Each condition is "column op value" with op one of ==, !=, >, >=, <, <= or
+/// contains. The value may be wrapped in single or double quotes. Used as a clap
+/// value parser, so a malformed expression is rejected at startup.
+/// 
+/// # Examples
+/// - "status == Active" -> [status == "Active"]
+/// - "age >= 18 and name contains 'Jo'" -> [age >= "18", name contains "Jo"]
+/// - "name == 'Tom and Jerry'" -> [name == "Tom and Jerry"]
+pub fn parse_where(spec: &str) -> std::result::Result<WhereClause, String> {
+    // Longer operators first, so ">=" isn't read as ">" followed by "=..."
+    let condition_re = Regex::new(r"^\s*(.+?)\s*(==|!=|>=|<=|>|<|\scontains\s)\s*(.*?)\s*$")
+        .expect("condition regex is valid");
+
+    split_conditions(spec)
+        .into_iter()
+        .map(|part| {
+            let caps = condition_re.captures(part).ok_or_else(|| {
+                format!(
+                    "expected \"column op value\" with op ==, !=, >, >=, <, <= or contains, got '{}'",
+                    part.trim()
+                )
+            })?;
+            let op = match caps[2].trim() {
+                "==" => CompareOp::Eq,
+                "!=" => CompareOp::Ne,
+                ">" => CompareOp::Gt,
+                ">=" => CompareOp::Ge,
+                "<" => CompareOp::Lt,
+                "<=" => CompareOp::Le,
+                _ => CompareOp::Contains,
+            };
+            // Quotes allow values with leading/trailing spaces or the word "and"
+            let value = &caps[3];
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+                .unwrap_or(value);
+
+            Ok(Condition {
+                column: normalize_column_name(&caps[1]),
+                op,
+                value: value.to_string(),
+            })
+        })
+        .collect::<std::result::Result<_, _>>()
+        .map(WhereClause)
+}
+
+/// Splits a --where expression into its conditions at each "and"
+/// 
+/// An "and" inside a quoted value belongs to the value: a quote right after the
+/// operator opens the value, and the condition only ends once that quote is closed.
+/// 
+/// # Example
+/// "name == 'Tom and Jerry' and age > 3" -> ["name == 'Tom and Jerry'", "age > 3"]
+fn split_conditions(spec: &str) -> Vec<&str> {
+    let and_re = Regex::new(r"(?i)\s+and\s+").expect("and regex is valid");
+    let quoted_re = Regex::new(r#"(==|!=|>=|<=|>|<|\scontains)\s*(["'])"#).expect("quote regex is valid");
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut search = 0;
+    while let Some(and) = and_re.find_at(spec, search) {
+        search = and.end();
+        let part = &spec[start..and.start()];
+        // A value still inside its quotes takes this "and" as part of the text
+        if let Some(quote) = quoted_re.captures(part).and_then(|caps| caps.get(2))
+            && !part[quote.end()..].contains(quote.as_str())
+        {
+            continue;
+        }
+        parts.push(part);
+        start = and.end();
+    }
+    parts.push(&spec[start..]);
+    parts
+}
+
+/// Checks whether a record satisfies every --where condition
+/// 
+/// Values are compared numerically when both sides parse as numbers, as text
+/// otherwise. Missing and null values compare as "".
+fn matches_conditions(record: &Value, conditions: &[Condition]) -> bool {
+    conditions.iter().all(|condition| {
+        let actual = match record.get(&condition.column) {
+            None | Some(Value::Null) => String::new(),
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+        };
+        if condition.op == CompareOp::Contains {
+            return actual.contains(&condition.value);
+        }
+
+        let ordering = match (actual.trim().parse::<f64>(), condition.value.trim().parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(actual.as_str().cmp(condition.value.as_str())),
+        };
+        match condition.op {
+            CompareOp::Eq => ordering == Some(Ordering::Equal),
+            CompareOp::Ne => ordering != Some(Ordering::Equal),
+            CompareOp::Gt => ordering == Some(Ordering::Greater),
+            CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            CompareOp::Lt => ordering == Some(Ordering::Less),
+            CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            CompareOp::Contains => unreachable!("handled above"),
+        }
+    })
+}
+
+/// Checks whether a workbook uses the 1904 date system
+/// 
+/// Set by <workbookPr date1904="1"/> in workbook.xml (typical of older Mac workbooks);
+/// an .xlsb workbook keeps the flag in the BrtWbProp record of workbook.bin instead.
+/// Date serials of such a workbook count from 1904-01-01, so conversions need
+/// ConvertOptions::date1904; convert_bytes() and convert_file() set it themselves.
+/// 
+/// # Arguments
+/// * `workbook` - An .xlsx, .xlsm or .xlsb file
+/// 
+/// # Errors
+/// - Returns error if the file isn't a zip archive
+/// - Returns error if it has no workbook part, or workbook.xml can't be parsed
+pub fn uses_1904_dates(workbook: impl Read + Seek) -> Result<bool> {
+    let mut archive = zip::ZipArchive::new(workbook).context("Not a valid .xlsx archive")?;
+    let mut read_part = |path: &str| -> Result<Option<Vec<u8>>> {
+        let mut entry = match archive.by_name(path) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err).context(format!("Failed to read xlsx part '{}'", path)),
+        };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).context(format!("Failed to read xlsx part '{}'", path))?;
+        Ok(Some(bytes))
+    };
+    if let Some(workbook) = read_part("xl/workbook.bin")? {
+        return Ok(xlsb_uses_1904_dates(&workbook));
+    }
+    let workbook = read_part("xl/workbook.xml")?.context("xlsx archive has no xl/workbook.xml")?;
+
+    let mut reader = quick_xml::Reader::from_reader(workbook.as_slice());
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf).context("Failed to parse xlsx XML")? {
+            quick_xml::events::Event::Start(e) | quick_xml::events::Event::Empty(e)
+                if e.local_name().as_ref() == b"workbookPr" =>
+            {
+                let flag = e.try_get_attribute("date1904").context("Invalid XML attribute")?;
+                return Ok(flag.is_some_and(|attr| matches!(attr.value.as_ref(), b"1" | b"true")));
+            }
+            quick_xml::events::Event::Eof => return Ok(false),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// Reads the 1904 date system flag from the records of an .xlsb workbook.bin part
+/// 
+/// Each record starts with its type and size as variable-length integers (7 bits
+/// per byte, high bit set on all but the last byte). The first 4 bytes of the
+/// BrtWbProp record (type 153) are flags, bit 0 being the 1904 date system.
+fn xlsb_uses_1904_dates(workbook: &[u8]) -> bool {
+    const BRT_WB_PROP: u32 = 153;
+
+    // Reads one variable-length integer of at most `max_bytes` bytes
+    fn read_varint(data: &[u8], pos: &mut usize, max_bytes: usize) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in 0..max_bytes {
+            let byte = *data.get(*pos)?;
+            *pos += 1;
+            value |= ((byte & 0x7f) as u32) << (7 * shift);
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        Some(value)
+    }
+
+    let mut pos = 0;
+    while let (Some(record_type), Some(size)) =
+        (read_varint(workbook, &mut pos, 2), read_varint(workbook, &mut pos, 4))
+    {
+        let end = pos + size as usize;
+        if record_type == BRT_WB_PROP {
+            return workbook.get(pos).is_some_and(|flags| flags & 0x1 != 0);
+        }
+        pos = end;
+    }
+    false
+}
+
+/// Converts one sheet of a workbook file to JSON records
+/// 
+/// Reads the file and hands it to convert_bytes(), picking the format from
+/// the file's extension.
+/// 
+/// # Arguments
+/// * `path` - Path to the workbook (.xlsx, .xlsm, .xlsb, .xls or .ods)
+/// * `sheet` - Name of the sheet to convert
+/// * `opts` - Value conversion options
+/// 
+/// # Errors
+/// - Returns error if the file can't be read
+/// - Returns any error of convert_bytes()
+/// 
+/// # Example
+/// ```rust
+/// use excel2json::{convert_file, ConvertOptions};
+/// use serde_json::json;
+/// 
+/// let opts = ConvertOptions::builder().infer_types(true).build();
+/// let records = convert_file("tests/fixtures/people.xlsx", "Sheet1", &opts)?;
+/// assert_eq!(records[0]["age"], json!(25));
+/// assert_eq!(records[1]["age"], json!(30.5));
+/// # anyhow::Ok(())
+/// ```
+pub fn convert_file(path: impl AsRef<Path>, sheet: &str, opts: &ConvertOptions) -> Result<Vec<Value>> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).context(format!("Failed to read workbook {:?}", path))?;
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    convert_bytes(&data, ext, sheet, opts)
+}
+
+/// Converts one sheet of a workbook held in memory to JSON records
+/// 
+/// The bytes are read through a Cursor, so no file on disk is needed. As with the
+/// command-line tool's defaults, the first row holds the headers and every column
+/// with a non-empty header is included.
+/// 
+/// # Arguments
+/// * `data` - Contents of the workbook file
+/// * `ext` - Extension of the original file name, which selects the reader:
+///   "xlsx", "xlsm", "xlsb", "xls" or "ods" (a leading dot is ignored)
+/// * `sheet` - Name of the sheet to convert
+/// * `opts` - Value conversion options; `date1904` is switched on for .xlsx and
+///   .xlsb workbooks that use the 1904 date system (see uses_1904_dates())
+/// 
+/// # Returns
+/// One JSON object per data row; an empty sheet gives an empty vector
+/// 
+/// # Errors
+/// - Returns error if the extension is not a supported format
+/// - Returns error if the data can't be read as a workbook of that format
+/// - Returns error if the sheet doesn't exist
+/// - Returns any error of convert_sheet_iter()
+/// 
+/// # Example
+/// ```rust
+/// use excel2json::{convert_bytes, ConvertOptions};
+/// 
+/// let upload = std::fs::read("tests/fixtures/people.xlsx")?;
+/// let records = convert_bytes(&upload, "xlsx", "Sheet1", &ConvertOptions::default())?;
+/// assert_eq!(records.len(), 2);
+/// assert_eq!(records[0]["age"], "25");
+/// assert_eq!(records[1]["customer_id"], "C-2");
+/// # anyhow::Ok(())
+/// ```
+pub fn convert_bytes(data: &[u8], ext: &str, sheet: &str, opts: &ConvertOptions) -> Result<Vec<Value>> {
+    let cursor = Cursor::new(data);
+    let ext = ext.trim_start_matches('.').to_lowercase();
+
+    // Date serials of 1904-based workbooks count from another day
+    let zipped = matches!(ext.as_str(), "xlsx" | "xlsm" | "xlam" | "xlsb");
+    let opts = if zipped && !opts.date1904 && uses_1904_dates(Cursor::new(data))? {
+        Cow::Owned(ConvertOptions { date1904: true, ..opts.clone() })
+    } else {
+        Cow::Borrowed(opts)
+    };
+
+    // Pick the reader from the extension, as calamine does for files on disk
+    let mut workbook: Sheets<Cursor<&[u8]>> = match ext.as_str() {
+        "xlsx" | "xlsm" | "xlam" => Sheets::Xlsx(open_workbook_from_rs::<Xlsx<_>, _>(cursor)?),
+        "xlsb" => Sheets::Xlsb(open_workbook_from_rs::<Xlsb<_>, _>(cursor)?),
+        "xls" | "xla" => Sheets::Xls(open_workbook_from_rs::<Xls<_>, _>(cursor)?),
+        "ods" => Sheets::Ods(open_workbook_from_rs::<Ods<_>, _>(cursor)?),
+        other => anyhow::bail!("Unsupported workbook format '{}'", other),
+    };
+    let range = workbook
+        .worksheet_range(sheet)
+        .context(format!("Sheet '{}' not found", sheet))?;
+    convert_sheet_iter(&range, &opts).collect()
+}
+
+/// Converts a sheet's cells to JSON records one at a time
+/// 
+/// The streaming counterpart of convert_bytes(): records are converted only as
+/// the iterator is advanced, so a huge sheet never has to be held as a vector
+/// of records, and each one can go straight into a channel or writer. The first
+/// row holds the headers; by default every column with a non-empty header is
+/// included (see sheet_headers() for the options that change this).
+/// 
+/// # Arguments
+/// * `range` - The sheet's cells, e.g. from calamine's worksheet_range()
+/// * `opts` - Value conversion options; set `date1904` if the workbook uses the
+///   1904 date system (see uses_1904_dates())
+/// 
+/// # Returns
+/// An iterator of JSON objects, one per data row (rows not matching the --where
+/// conditions are left out); an empty sheet gives no items
+/// 
+/// # Errors
+/// - If the sheet lacks one of `opts.columns`, the only item is that error
+/// - With `strict_types`, `strict_numeric` or `strict_bool`, a value that doesn't fit its column
+///   ends the records with one error naming its cell and why it doesn't fit
+/// 
+/// The iterator is Send, so the records can be converted on another thread.
+/// 
+/// # Example
+/// ```rust
+/// use calamine::{Data, Range};
+/// use excel2json::{convert_sheet_iter, ConvertOptions};
+/// 
+/// let mut range = Range::new((0, 0), (2, 1));
+/// for (row, cells) in [["name", "age"], ["John", "25"], ["Jane", "30.5"]].iter().enumerate() {
+///     for (col, text) in cells.iter().enumerate() {
+///         range.set_value((row as u32, col as u32), Data::String(text.to_string()));
+///     }
+/// }
+/// 
+/// let opts = ConvertOptions::default();
+/// let count = std::thread::scope(|scope| {
+///     scope.spawn(|| {
+///         let mut count = 0;
+///         for record in convert_sheet_iter(&range, &opts) {
+///             let record = record?;
+///             assert!(record["name"].is_string());
+///             count += 1;
+///         }
+///         anyhow::Ok(count)
+///     })
+///     .join()
+///     .unwrap()
+/// })?;
+/// assert_eq!(count, 2);
+/// # anyhow::Ok(())
+/// ```
+pub fn convert_sheet_iter<'a>(
+    range: &'a calamine::Range<calamine::Data>,
+    opts: &'a ConvertOptions,
+) -> impl Iterator<Item = Result<Value>> + Send + 'a {
+    // Keys and columns from the first row
+    let mut rows = range.rows();
+    let sheet = rows.next().map(|header_row| {
+        let (headers, column_indices) = sheet_headers(header_row, range.width(), opts)?;
+        let keys = styled_keys(&headers, opts)?;
+        anyhow::Ok((headers, keys, column_indices))
+    });
+    let (headers, keys, column_indices, error) = match sheet {
+        Some(Ok((headers, keys, column_indices))) => (headers, keys, column_indices, None),
+        Some(Err(error)) => (Vec::new(), Vec::new(), Vec::new(), Some(error)),
+        None => (Vec::new(), Vec::new(), Vec::new(), None), // Nothing to convert
+    };
+    // Options name columns by their snake_case keys, so the keys are styled once a record is done
+    let renames: HashMap<String, String> = headers
+        .iter()
+        .zip(&keys)
+        .filter(|(header, key)| header != key)
+        .map(|(header, key)| (header.clone(), key.clone()))
+        .collect();
+
+    // The columns are needed again to describe a strict stop
+    let column_indices: Arc<[usize]> = column_indices.into();
+    let stats = Arc::new(Mutex::new(ConvertStats::default()));
+    let records = convert_rows(
+        rows.enumerate(),
+        headers,
+        Arc::clone(&column_indices),
+        opts,
+        CellAnnotations::default(), // Hyperlinks/comments need the xlsx parts
+        Arc::clone(&stats),
+    )
+    .filter(move |record| !(opts.drop_empty_records && matches!(record, Value::Object(fields) if fields.is_empty())))
+    .map(move |record| if renames.is_empty() { record } else { rename_keys(record, &renames) });
+    SheetRecords {
+        records,
+        error,
+        stats,
+        range,
+        keys,
+        column_indices,
+        opts,
+        done: false,
+    }
+}
+
+/// Builds a sheet's keys and picks its columns from the header row
+/// 
+/// Applies the header options of ConvertOptions in the command line's order:
+/// the header text is cleaned (clean_header_row()), the columns are picked
+/// (header_column_indices(), then `columns`) and their keys built
+/// (header_keys()). The command line does the same, with more ways of
+/// picking columns.
+/// 
+/// # Arguments
+/// * `header_row` - The sheet's first row
+/// * `width` - Number of columns in the sheet
+/// * `opts` - Conversion options
+/// 
+/// # Returns
+/// (keys, column indices), one entry per included column; the keys are
+/// written in key_style by styled_keys()
+/// 
+/// # Errors
+/// Returns error if one of `opts.columns` isn't a header of the sheet
+fn sheet_headers(header_row: &[calamine::Data], width: usize, opts: &ConvertOptions) -> Result<(Vec<String>, Vec<usize>)> {
+    let header_row = clean_header_row(header_row, opts);
+    let visible_indices = header_column_indices(&header_row, width, opts);
+    let names = extract_headers(&header_row, &visible_indices, None);
+    let column_indices: Vec<usize> = if opts.columns.is_empty() {
+        visible_indices
+    } else {
+        opts.columns
+            .iter()
+            .map(|column| match names.iter().position(|name| name == column) {
+                Some(position) => Ok(visible_indices[position]),
+                None => Err(anyhow::anyhow!("Column '{}' not found in the sheet's headers", column)),
+            })
+            .collect::<Result<_>>()?
+    };
+
+    Ok((header_keys(&header_row, &column_indices, opts), column_indices))
+}
+
+/// The records of convert_sheet_iter(), ended by an error if strict conversion stopped them
+struct SheetRecords<'a, I> {
+    /// Records converted from the data rows
+    records: I,
+    /// Why the sheet couldn't be converted, handed out instead of any record
+    error: Option<anyhow::Error>,
+    /// Counters the records update, including the first value that didn't fit its column
+    stats: Arc<Mutex<ConvertStats>>,
+    /// The sheet's cells
+    range: &'a calamine::Range<calamine::Data>,
+    /// Keys of the selected columns, as written
+    keys: Vec<String>,
+    /// (0-based) column index of each key, relative to the range
+    column_indices: Arc<[usize]>,
+    /// Conversion options
+    opts: &'a ConvertOptions,
+    /// Whether the records (and any error) have all been handed out
+    done: bool,
+}
+
+impl<I> SheetRecords<'_, I> {
+    /// Describes the value that stopped the records under strict conversion
+    /// 
+    /// # Returns
+    /// An error naming the cell, its column and why it doesn't fit, or None if
+    /// the records weren't stopped
+    fn strict_error(&self) -> Option<anyhow::Error> {
+        let stats = *self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let (first_row, first_col) = self.range.start().unwrap_or((0, 0));
+        // (row, column) in the sheet of a data row index and a position in the selected columns
+        let position = |(row_idx, header_idx): (usize, usize)| {
+            (first_row + 1 + row_idx as u32, first_col + self.column_indices[header_idx] as u32)
+        };
+        let cell = |(row, col): (u32, u32)| format!("{}{}", column_letters(col as usize), row + 1); // 1-based, as shown in Excel
+        let value = |position| self.range.get_value(position).map(ToString::to_string).unwrap_or_default();
+
+        if let Some(fallback) = stats.first_type_fallback.filter(|_| self.opts.strict_types) {
+            let at = position(fallback);
+            return Some(anyhow::anyhow!(
+                "Cell {}: '{}' in column '{}' doesn't match the type sampled from the first {} row(s) (strict_types)",
+                cell(at),
+                value(at),
+                self.keys[fallback.1],
+                self.opts.sample_size.unwrap_or_default()
+            ));
+        }
+        if let Some(fallback) = stats.first_numeric_fallback.filter(|_| self.opts.strict_numeric) {
+            let at = position(fallback);
+            return Some(anyhow::anyhow!(
+                "Cell {}: '{}' in numeric column '{}' is not a number (strict_numeric)",
+                cell(at),
+                value(at),
+                self.keys[fallback.1]
+            ));
+        }
+        if let Some(fallback) = stats.first_bool_fallback.filter(|_| self.opts.strict_bool) {
+            let at = position(fallback);
+            return Some(anyhow::anyhow!(
+                "Cell {}: '{}' in bool column '{}' is not 1 or 0 (strict_bool)",
+                cell(at),
+                value(at),
+                self.keys[fallback.1]
+            ));
+        }
+        None
+    }
+}
+
+impl<I: Iterator<Item = Value>> Iterator for SheetRecords<'_, I> {
+    type Item = Result<Value>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(error) = self.error.take() {
+            self.done = true;
+            return Some(Err(error));
+        }
+        if let Some(record) = self.records.next() {
+            return Some(Ok(record));
+        }
+        // The records only end before the rows do when strict_types/strict_numeric/strict_bool stopped them
+        self.done = true;
+        self.strict_error().map(Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn where_keeps_and_inside_quoted_values() {
+        let clause = parse_where("name == 'Tom and Jerry' and age > 3 AND note contains \"a and b\"").unwrap();
+        let conditions: Vec<(&str, &str)> = clause.0.iter().map(|c| (c.column.as_str(), c.value.as_str())).collect();
+        assert_eq!(conditions, [("name", "Tom and Jerry"), ("age", "3"), ("note", "a and b")]);
+
+        // An apostrophe inside an unquoted value doesn't open a quote
+        let clause = parse_where("name == O'Brien and age > 3").unwrap();
+        assert_eq!(clause.0.len(), 2);
+        assert_eq!(clause.0[0].value, "O'Brien");
+    }
+
+    #[test]
+    fn replace_rules_keep_the_type_of_unmatched_cells() {
+        let rule = parse_replace_rule("amount:^ID-=>").unwrap();
+        let rules = [&rule];
+        let number = calamine::Data::Float(42.5);
+        assert!(matches!(apply_replace_rules(&number, &rules, true), Cow::Borrowed(_)));
+
+        let opts = ConvertOptions::builder().infer_types(true).replace(rule.clone()).build();
+        let rows = [vec![calamine::Data::Float(42.5)], vec![calamine::Data::String("ID-7".to_string())]];
+        let headers = ["amount".to_string()];
+        let records: Vec<Value> = convert_rows_to_json(
+            rows.iter().enumerate(),
+            &headers[..],
+            &[0][..],
+            &opts,
+            CellAnnotations::default(),
+            ConvertStats::default(),
+        )
+        .collect();
+        assert_eq!(records[0]["amount"], serde_json::json!(42.5));
+        assert_eq!(records[1]["amount"], serde_json::json!("7"));
+    }
+
+    #[test]
+    fn replaced_numbers_are_inferred_again() {
+        let rule = parse_replace_rule("amount:0+$=>").unwrap();
+        let cell = calamine::Data::Float(1200.0);
+        assert_eq!(*apply_replace_rules(&cell, &[&rule], true), calamine::Data::Float(12.0));
+        assert_eq!(*apply_replace_rules(&cell, &[&rule], false), calamine::Data::String("12".to_string()));
+    }
+
+    #[test]
+    fn builder_header_options_shape_the_keys() {
+        let header = ["OrderID", "2023", "3d Model", "", "Note"].map(|text| calamine::Data::String(text.to_string()));
+        let opts = ConvertOptions::builder()
+            .split_case(true)
+            .key_style(KeyStyle::Camel)
+            .numeric_header_prefix("y")
+            .key_prefix("col_")
+            .rename_column("Note", "remark")
+            .build();
+        let (headers, columns) = sheet_headers(&header, header.len(), &opts).unwrap();
+        assert_eq!(headers, ["order_id", "y2023", "col_3d_model", "remark"]);
+        assert_eq!(columns, [0, 1, 2, 4]);
+        assert_eq!(styled_keys(&headers, &opts).unwrap(), ["orderId", "y2023", "col3dModel", "remark"]);
+
+        // Options keep naming the columns in snake_case; only the written keys change
+        let opts = ConvertOptions::builder()
+            .infer_types(true)
+            .key_style(KeyStyle::Camel)
+            .string_column("Order ID")
+            .build();
+        let mut range = calamine::Range::new((0, 0), (1, 1));
+        for (col, (title, value)) in [("Order ID", 42.0), ("Unit Price", 5.0)].into_iter().enumerate() {
+            range.set_value((0, col as u32), calamine::Data::String(title.to_string()));
+            range.set_value((1, col as u32), calamine::Data::Float(value));
+        }
+        let records: Vec<Value> = convert_sheet_iter(&range, &opts).collect::<Result<_>>().unwrap();
+        assert_eq!(records, [serde_json::json!({"orderId": "42", "unitPrice": 5})]);
+
+        let keys = ["column_1".to_string(), "column1".to_string()];
+        let error = styled_keys(&keys, &ConvertOptions::builder().key_style(KeyStyle::Camel).build()).unwrap_err();
+        assert!(error.to_string().contains("'column_1' and 'column1' are both written as 'column1'"));
+
+        let opts = ConvertOptions::builder().include_blank_columns(true).columns(["Note", "Column 4"]).build();
+        let (headers, columns) = sheet_headers(&header, header.len(), &opts).unwrap();
+        assert_eq!((headers, columns), (vec!["note".to_string(), "column_4".to_string()], vec![4, 3]));
+
+        let opts = ConvertOptions::builder().columns(["Missing"]).build();
+        assert!(sheet_headers(&header, header.len(), &opts).is_err());
+    }
+
+    #[test]
+    fn whole_numbers_become_json_integers() {
+        assert!(number_to_json(42.0, None).is_i64());
+        assert_eq!(number_to_json(42.0, None).to_string(), "42");
+        assert_eq!(number_to_json(42.5, None).to_string(), "42.5");
+        assert_eq!(number_to_json(-7.0, None).to_string(), "-7");
+
+        // Beyond the i64 range a whole number stays a float instead of wrapping
+        let large = number_to_json(1e20, None);
+        assert!(large.is_f64());
+        assert_eq!(large.as_f64(), Some(1e20));
+        assert!(number_to_json(i64::MAX as f64, None).is_f64());
+        assert!(number_to_json(f64::NAN, None).is_null());
+
+        let opts = ConvertOptions::builder().infer_types(true).build();
+        let mut stats = ConvertStats::default();
+        let value = convert_cell_to_json(&calamine::Data::Float(42.0), &opts, &mut stats);
+        assert_eq!(serde_json::to_string(&value).unwrap(), "42");
+    }
+
+    #[test]
+    fn date1904_shifts_the_same_serial_by_1462_days() {
+        let cell = calamine::Data::DateTime(ExcelDateTime::new(45000.0, ExcelDateTimeType::DateTime, false));
+        let mut stats = ConvertStats::default();
+        let convert = |opts: ConvertOptions, stats: &mut ConvertStats| convert_cell_to_json(&cell, &opts, stats);
+
+        let iso = ConvertOptions::builder().dates(DateMode::Iso);
+        assert_eq!(convert(iso.clone().build(), &mut stats), "2023-03-15");
+        assert_eq!(convert(iso.date1904(true).build(), &mut stats), "2027-03-16");
+
+        // The serial 1462 days lower is the same day in the 1904 system
+        assert_eq!(excel_serial_to_iso(45000.0 - 1462.0, true).as_deref(), Some("2023-03-15"));
+        assert_eq!(excel_serial_to_iso(0.0, true).as_deref(), Some("1904-01-01"));
+
+        let epoch = ConvertOptions::builder().dates(DateMode::Epoch);
+        assert_eq!(convert(epoch.clone().build(), &mut stats), 1678838400);
+        assert_eq!(convert(epoch.date1904(true).build(), &mut stats), 1678838400 + 1462 * 86_400);
+    }
+
+    #[test]
+    fn unicode_forms_fold_accented_and_full_width_text() {
+        let composed = normalize_column_name("Café");
+        assert_ne!(normalize_column_name("Cafe\u{301}"), composed);
+        assert_eq!(normalize_column_name(&UnicodeForm::Nfc.normalize("Cafe\u{301}")), composed);
+        assert_eq!(normalize_column_name(&UnicodeForm::Nfkc.normalize("ＩＤ（２）")), normalize_column_name("ID(2)"));
+        // NFC leaves full-width characters alone
+        assert_eq!(UnicodeForm::Nfc.normalize("ＩＤ"), "ＩＤ");
+
+        let header = ["Cafe\u{301}", "ＩＤ１"].map(|text| calamine::Data::String(text.to_string()));
+        let opts = ConvertOptions::builder().normalize_keys(UnicodeForm::Nfkc).build();
+        let (headers, _) = sheet_headers(&header, header.len(), &opts).unwrap();
+        assert_eq!(headers, [composed.as_str(), "id1"]);
+
+        // Values only change with normalize_values (--normalize-unicode-values)
+        let cell = calamine::Data::String("ＡＢＣ　１２３".to_string());
+        let mut stats = ConvertStats::default();
+        assert_eq!(convert_cell_to_json(&cell, &opts, &mut stats), "ＡＢＣ　１２３");
+        let opts = ConvertOptions::builder().normalize_values(UnicodeForm::Nfkc).build();
+        assert_eq!(convert_cell_to_json(&cell, &opts, &mut stats), "ABC 123");
+    }
+
+    #[test]
+    fn us_and_eu_separators_parse_the_same_amounts() {
+        use NumberLocale::{Eu, Us};
+        assert_eq!(parse_numeric_text("1,234.56", Us), Some(1234.56));
+        assert_eq!(parse_numeric_text("1.234,56", Eu), Some(1234.56));
+        assert_eq!(parse_numeric_text("-1,234,567.5", Us), Some(-1234567.5));
+        assert_eq!(parse_numeric_text("-1.234.567,5", Eu), Some(-1234567.5));
+        assert_eq!(parse_numeric_text("0.5", Us), Some(0.5));
+        assert_eq!(parse_numeric_text("0,5", Eu), Some(0.5));
+
+        // The other locale's format isn't a number
+        assert_eq!(parse_numeric_text("1.234,56", Us), None);
+        assert_eq!(parse_numeric_text("1,234.56", Eu), None);
+        // A lone separator followed by three digits reads as grouping in one and as decimals in the other
+        assert_eq!(parse_numeric_text("1,234", Us), Some(1234.0));
+        assert_eq!(parse_numeric_text("1,234", Eu), Some(1.234));
+        // Groups must have three digits
+        assert_eq!(parse_numeric_text("12,34", Us), None);
+        assert_eq!(parse_numeric_text("1.23.456", Eu), None);
+
+        assert_eq!(parse_currency_number("$1,234.56", Us), Some(1234.56));
+        assert_eq!(parse_currency_number("€1.234,56", Eu), Some(1234.56));
+        assert_eq!(parse_currency_number("(1.000)", Eu), Some(-1000.0));
+
+        let cell = calamine::Data::String("1.234,5".to_string());
+        let mut stats = ConvertStats::default();
+        let eu = ConvertOptions::builder().infer_types(true).decimal_separator(DecimalSeparator::Comma).build();
+        assert_eq!(convert_cell_to_json(&cell, &eu, &mut stats), serde_json::json!(1234.5));
+        let us = ConvertOptions::builder().infer_types(true).decimal_separator(DecimalSeparator::Dot).build();
+        assert_eq!(convert_cell_to_json(&cell, &us, &mut stats), "1.234,5");
+    }
+
+    #[test]
+    fn epoch_seconds_match_known_timestamps() {
+        assert_eq!(excel_serial_to_epoch(25569.0, false), Some(0)); // 1970-01-01
+        assert_eq!(excel_serial_to_epoch(24107.0, true), Some(0)); // The same day, 1462 days lower
+        assert_eq!(excel_serial_to_epoch(45000.0, false), Some(1_678_838_400)); // 2023-03-15
+        assert_eq!(excel_serial_to_epoch(45000.5, false), Some(1_678_881_600)); // ... at noon
+        assert_eq!(excel_serial_to_epoch(43538.0, true), Some(1_678_838_400));
+        assert_eq!(excel_serial_to_epoch(1.0, false), Some(-2_208_988_800)); // 1900-01-01, before 1970
+        // A hair below midnight rounds to the next whole second rather than 23:59:59
+        assert_eq!(excel_serial_to_epoch(25569.999999999, false), Some(86_400));
+
+        assert_eq!(excel_serial_to_epoch(-1.0, false), None);
+        assert_eq!(excel_serial_to_epoch(f64::NAN, false), None);
+        assert_eq!(excel_serial_to_epoch(f64::INFINITY, true), None);
+    }
+
+    #[test]
+    fn string_columns_stay_text_next_to_inferred_numbers() {
+        let opts = ConvertOptions::builder().infer_types(true).string_column("Zip Code").build();
+        let rows = [
+            vec![calamine::Data::Float(10110.0), calamine::Data::Float(12.5), calamine::Data::Bool(true)],
+            vec![calamine::Data::String("02134".to_string()), calamine::Data::Int(7), calamine::Data::Empty],
+        ];
+        let headers = ["zip_code".to_string(), "amount".to_string(), "active".to_string()];
+        let records: Vec<Value> = convert_rows_to_json(
+            rows.iter().enumerate(),
+            &headers[..],
+            &[0, 1, 2][..],
+            &opts,
+            CellAnnotations::default(),
+            ConvertStats::default(),
+        )
+        .collect();
+        assert_eq!(records[0], serde_json::json!({"zip_code": "10110", "amount": 12.5, "active": true}));
+        assert_eq!(records[1], serde_json::json!({"zip_code": "02134", "amount": 7, "active": null}));
+    }
+
+    /// A one-column range: header "n", then the given data cells
+    fn column_range(cells: &[calamine::Data]) -> calamine::Range<calamine::Data> {
+        let mut range = calamine::Range::new((0, 0), (cells.len() as u32, 0));
+        range.set_value((0, 0), calamine::Data::String("n".to_string()));
+        for (row, cell) in cells.iter().enumerate() {
+            range.set_value((row as u32 + 1, 0), cell.clone());
+        }
+        range
+    }
+
+    #[test]
+    fn strict_stops_name_the_failing_cell_inside_the_sample() {
+        use calamine::Data::{Float, String as Text};
+        let text = |s: &str| Text(s.to_string());
+        let range = column_range(&[Float(1.0), text("oops"), Float(3.0), Float(4.0), Float(5.0), Float(6.0)]);
+        let opts = ConvertOptions::builder().sample_size(5).numeric_column("n").strict_numeric(true).build();
+        let results: Vec<Result<Value>> = convert_sheet_iter(&range, &opts).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap()["n"], 1);
+        let error = results[1].as_ref().unwrap_err().to_string();
+        assert_eq!(error, "Cell A3: 'oops' in numeric column 'n' is not a number (strict_numeric)");
+
+        let range = column_range(&[Float(1.0), Float(0.0), text("maybe")]);
+        let opts = ConvertOptions::builder().sample_size(5).bool_column("n").strict_bool(true).build();
+        let error = convert_sheet_iter(&range, &opts).last().unwrap().unwrap_err().to_string();
+        assert_eq!(error, "Cell A4: 'maybe' in bool column 'n' is not 1 or 0 (strict_bool)");
+
+        let range = column_range(&[Float(1.0), Float(2.0), text("n/a"), Float(4.0)]);
+        let opts = ConvertOptions::builder().infer_types(true).sample_size(2).strict_types(true).build();
+        let results: Vec<Result<Value>> = convert_sheet_iter(&range, &opts).collect();
+        assert_eq!(results.len(), 3);
+        let error = results[2].as_ref().unwrap_err().to_string();
+        assert_eq!(error, "Cell A4: 'n/a' in column 'n' doesn't match the type sampled from the first 2 row(s) (strict_types)");
+
+        // Without strict options the same values are kept as text
+        let opts = ConvertOptions::builder().infer_types(true).sample_size(2).build();
+        assert!(convert_sheet_iter(&range, &opts).all(|record| record.is_ok()));
+    }
+}
@@ -1,368 +1,7057 @@
 // External dependencies
 use anyhow::{Context, Result}; // Error handling with context
-use calamine::{open_workbook, Reader, Xlsx}; // Excel file reading library
-use clap::Parser; // Command-line argument parser
+use calamine::{open_workbook_from_rs, Reader, Sheets, Xlsb, Xlsx}; // Excel file reading library
+use clap::parser::ValueSource; // Telling command-line options from defaults
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum}; // Command-line argument parser
+use comfy_table::{ContentArrangement, Table}; // Text tables for `excel2json preview`
+use excel2json::{
+    clean_header_row, clean_header_text, column_letters, convert_rows_to_json, extract_headers, get_visible_column_indices,
+    header_column_indices, header_keys, normalize_column_name, parse_replace_rule, parse_split_rule, parse_where,
+    rename_keys, split_case_words, styled_keys, uses_1904_dates, CellAnnotations, CellMap, ConvertOptions, ConvertStats, DateMode, DecimalSeparator, EmptyMode, HeaderMap, KeyStyle,
+    NumberLocale, ReplaceRule, SplitEmpty, SplitRule, TrimMode, UnicodeForm, WhereClause,
+}; // Cell and row conversion (lib.rs)
+use indicatif::{ProgressBar, ProgressStyle}; // Progress bar for large sheets
+use regex::Regex; // --columns-regex header patterns
+use serde::Serialize; // --column-types in --stats-json
 use serde_json::{json, Value}; // JSON serialization
-use std::fs::File; // File system operations
-use std::io::Write; // Write trait for file output
-use std::path::PathBuf; // Cross-platform file path handling
+use std::cmp::Ordering; // Comparison results for sorting records
+use std::collections::{BTreeSet, HashMap, HashSet}; // Cell annotation lookups, deduplication, --strict-schema
+use std::fmt; // Display implementation for error classes
+use std::fs::{self, File, OpenOptions}; // File system operations
+use std::io::{BufReader, BufWriter, Cursor, IsTerminal, Read, Seek, Write}; // Reading xlsx parts and zip entries, writing output, TTY detection
+use std::path::{Path, PathBuf}; // Cross-platform file path handling
+use std::process::ExitCode; // Distinct exit codes per failure class
+use std::borrow::Cow; // Data rows borrowed from the sheet or read with --lazy-read
+use std::cell::{Cell, RefCell}; // Handing the --lazy-read rows to the conversion once, noting ragged ones
+use std::sync::mpsc; // Channels for file-change events in --watch mode and --lazy-read rows
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH}; // --watch debouncing/timestamps, --stats-json timing
 
-/// Command-line arguments structure
-/// Defines all parameters that users can pass to the CLI tool
+/// Default filename template used with --output-dir when --name-template is not given
+const DEFAULT_NAME_TEMPLATE: &str = "{stem}.{ext}";
+
+/// Default filename template with --split-sheets, which needs the sheet name in it
+const SPLIT_NAME_TEMPLATE: &str = "{stem}_{sheet}.{ext}";
+
+/// Config file read from the current directory when --config is not given
+const DEFAULT_CONFIG_FILE: &str = ".excel2json.toml";
+
+/// How long --watch waits for further events before re-converting
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How long --watch keeps waiting for the input file to reappear after an atomic save
+const WATCH_MISSING_FILE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sheets with more data rows than this show a progress bar (when stderr is a terminal)
+const PROGRESS_ROW_THRESHOLD: usize = 10_000;
+
+/// Rows read ahead of the conversion with --lazy-read (bounds the memory a huge sheet takes)
+const LAZY_ROW_BUFFER: usize = 1024;
+
+/// Rows and columns of an Excel worksheet (1,048,576 rows, columns A to XFD)
+const SHEET_MAX_ROWS: u32 = 1_048_576;
+const SHEET_MAX_COLUMNS: u32 = 16_384;
+
+/// Failure classes that are reported through distinct process exit codes
+/// 
+/// Errors are still propagated as anyhow::Error; these values are attached
+/// at the failure site and looked up again in main() via downcast_ref().
+/// Exit code 2 is left to clap, which uses it for invalid arguments.
+#[derive(Debug)]
+enum ConversionError {
+    /// The input file couldn't be opened as a workbook (exit code 3)
+    FileOpen(String),
+    /// The requested sheet doesn't exist, is ambiguous or out of range (exit code 4)
+    SheetNotFound(String),
+    /// A column specification such as --columns or --sort-by is invalid (exit code 5)
+    InvalidColumns(String),
+    /// The output couldn't be serialized or written (exit code 6)
+    Write(String),
+    /// No records were produced and --fail-on-empty was given (exit code 7)
+    EmptyOutput(String),
+}
+
+impl ConversionError {
+    /// Process exit code for this failure class
+    fn exit_code(&self) -> u8 {
+        match self {
+            ConversionError::FileOpen(_) => 3,
+            ConversionError::SheetNotFound(_) => 4,
+            ConversionError::InvalidColumns(_) => 5,
+            ConversionError::Write(_) => 6,
+            ConversionError::EmptyOutput(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversionError::FileOpen(msg)
+            | ConversionError::SheetNotFound(msg)
+            | ConversionError::InvalidColumns(msg)
+            | ConversionError::Write(msg)
+            | ConversionError::EmptyOutput(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+/// The command line: a conversion, or a subcommand that inspects a workbook
+/// 
+/// Without a subcommand the arguments are those of a conversion (see Args).
+/// A subcommand name is only recognized as the first argument; an input file
+/// that happens to be named like one can be given as "./preview".
 #[derive(Parser, Debug)]
 #[command(name = "excel2json")]
 #[command(about = "Convert Excel files to JSON format", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true, disable_help_subcommand = true)]
+struct Cli {
+    /// Subcommand to run instead of a conversion
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Conversion arguments; given whenever there is no subcommand
+    #[command(flatten)]
+    convert: Option<Args>,
+}
+
+/// Subcommands of excel2json
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Print the header and first rows of a sheet as a text table
+    #[command(about = "Print the header and first rows of a sheet as a table", long_about = None)]
+    Preview(PreviewArgs),
+
+    /// List every commented cell of a workbook as JSON
+    #[command(about = "List every commented cell of a workbook as JSON: [{sheet, cell, value, comment}]", long_about = None)]
+    Comments(CommentsArgs),
+
+    /// Print each header of a sheet next to the JSON key it becomes
+    #[command(about = "Print each header of a sheet next to the JSON key it becomes", long_about = None)]
+    Headers(Box<HeadersArgs>),
+}
+
+/// Command-line arguments of a conversion
+/// Defines all parameters that users can pass to the CLI tool
+#[derive(clap::Args, Debug, Clone)]
+#[command(group(clap::ArgGroup::new("destination").required(true).args(["output", "output_dir"])))]
+#[command(group(clap::ArgGroup::new("sheet_source").args(["sheet", "sheet_index", "table", "merge_sheets", "sheets"])))]
 struct Args {
-    /// Path to the input Excel file (.xlsx format)
-    #[arg(help = "Input Excel file path (.xlsx)")]
+    /// Path to the input Excel file (.xlsx, or .xlsb for binary workbooks)
+    /// A workbook inside a zip archive can be given as "archive.zip!inner.xlsx"
+    #[arg(help = "Input Excel file path (.xlsx or .xlsb), or archive.zip!inner.xlsx for a workbook inside a zip")]
+    file: PathBuf,
+
+    /// Optional: Treat the input file as a zip archive and convert this workbook inside it
+    /// The entry is read into memory, nothing is extracted to disk
+    /// Example: --zip-entry "2024/march.xlsx"
+    #[arg(long, value_name = "NAME", help = "Read the workbook from this entry of the input zip archive")]
+    zip_entry: Option<String>,
+
+    /// Optional: Try opening the input file up to N more times when that fails, e.g. while
+    /// another process is still writing it. Only failures to open or read the file are
+    /// retried - a missing sheet fails right away
+    #[arg(long, value_name = "N", default_value_t = 0, help = "Retry opening the input file N times if it can't be opened (e.g. still being written)")]
+    retry: usize,
+
+    /// Optional: Milliseconds to wait before the first --retry; the wait doubles after each one
+    #[arg(long, value_name = "MS", default_value_t = 500, help = "Wait before the first --retry in milliseconds, doubling after each attempt")]
+    retry_delay: u64,
+
+    /// Name of the sheet within the Excel file to convert
+    /// Either this, --sheet-index, --table, --merge-sheets, --sheets or a sheet-qualified
+    /// --range must be given (only one of the first five)
+    #[arg(required_unless_present_any = ["sheet_index", "table", "merge_sheets", "sheets", "range"], help = "Sheet name to convert")]
+    sheet: Option<String>,
+
+    /// Optional: Further input files, converted with the same options as FILE and their
+    /// records concatenated into one output, e.g. --merge south.xlsx east.xlsx
+    /// Files may have different columns - the output simply holds the records of each.
+    /// Each may use the "archive.zip!inner.xlsx" shorthand (see input_files())
+    #[arg(long, value_name = "FILE", num_args = 1.., conflicts_with_all = ["zip_entry", "watch"], help = "Convert these input files too and concatenate their records into one output (see --with-source-file)")]
+    merge: Vec<PathBuf>,
+
+    /// Optional: With --merge, fail unless every input file has the same set of columns
+    #[arg(long, help = "With --merge, fail (exit code 5) if the input files don't all have the same columns")]
+    strict_schema: bool,
+
+    /// What to do when an input file lacks the requested sheet (or a --sheets name or --table)
+    /// error: stop the run (exit code 4) / skip: leave it out and list it in the summary /
+    /// warn: like skip, plus a warning on stderr. The run still fails if nothing is left
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OnMissingSheet::Error, help = "Missing sheet in an input file: fail, or skip it (listed in the summary) and continue")]
+    on_missing_sheet: OnMissingSheet,
+
+    /// Optional: 1-based position of the sheet to convert instead of its name
+    /// Example: 1 selects the first sheet in the workbook
+    #[arg(long, value_parser = clap::value_parser!(usize), help = "Sheet position to convert (1-based), instead of the sheet name")]
+    sheet_index: Option<usize>,
+
+    /// Optional: Name of an Excel Table (ListObject) to convert instead of a whole sheet
+    /// The table's own header row is used, wherever the table sits on its sheet
+    #[arg(long, help = "Excel Table name to convert, instead of a sheet (uses the table's header row)")]
+    table: Option<String>,
+
+    /// Optional: Convert every sheet and concatenate their records into one flat array
+    /// Each sheet uses its own header row; --columns applies to each sheet separately
+    #[arg(long, help = "Convert all sheets and concatenate their records into one array (see --with-sheet-name)")]
+    merge_sheets: bool,
+
+    /// Optional: Comma-separated names of the sheets to convert, e.g. "Summary,Details,Notes"
+    /// With --with-sheet-name their records are concatenated into one array like --merge-sheets;
+    /// otherwise the output is a JSON object with one array per sheet: {"Summary": [...], ...}
+    #[arg(long, value_name = "NAMES", help = "Convert these sheets (comma-separated): one array with --with-sheet-name, otherwise an object keyed by sheet name")]
+    sheets: Option<String>,
+
+    /// Optional: Treat the sheets of --merge-sheets/--sheets (and --merge files) as one table
+    /// The first sheet's columns are used for every sheet: a column another sheet lacks
+    /// is null, a column only another sheet has is left out, and either is reported in
+    /// a warning. Records are tagged with their sheet as with --with-sheet-name
+    #[arg(long, help = "Concatenate sheets with the same layout into one array, using the first sheet's columns (implies --with-sheet-name)")]
+    consolidate: bool,
+
+    /// Optional: Top-level structure of the output
+    /// auto: an object keyed by sheet name for --sheets without --with-sheet-name, an array otherwise
+    /// object: always {"Sheet1": [...], ...}, even for a single sheet or --merge-sheets
+    /// array: always one array of records, even for --sheets
+    #[arg(long, value_enum, value_name = "SHAPE", default_value_t = OutputShape::Auto, help = "Top-level output structure: one array, an object keyed by sheet name, or auto")]
+    output_shape: OutputShape,
+
+    /// Optional: How sheet names become the keys of output keyed by sheet
+    /// raw: the tab name as is / snake: normalized like headers ("Sheet 1" -> "sheet_1") /
+    /// slug: lowercase words joined by hyphens ("Q1 Sales" -> "q1-sales")
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = SheetKeyStyle::Raw, help = "Keys of output keyed by sheet: raw sheet names, snake_case or slug")]
+    sheet_key_style: SheetKeyStyle,
+
+    /// Optional: Comma-separated "sheet=key" renames for output keyed by sheet
+    /// Used as written and ahead of --sheet-key-style, which still applies to other sheets
+    /// Example: "Sheet 1=primary,Sheet 2=secondary"
+    #[arg(long, value_name = "RENAMES", value_parser = parse_sheet_rename, help = "Rename sheets in output keyed by sheet: \"Sheet 1=primary,Sheet 2=secondary\"")]
+    sheet_rename: Option<SheetRename>,
+
+    /// Number of rows at the top of the sheet that make up the header
+    /// With more than one, each column's parts are joined with --header-join
+    /// Example: "Q1" above "Revenue" becomes "Q1 / Revenue" (key "q1_revenue")
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), help = "Number of header rows combined into each column's name")]
+    header_rows: usize,
+
+    /// Separator placed between the parts of a multi-row header
+    #[arg(long, value_name = "SEP", default_value = " / ", help = "Separator used to join multi-row headers")]
+    header_join: String,
+
+    /// Optional: Build each key of a multi-row header from the normalized key of every part
+    /// A merged parent header is filled forward over its sub-columns, so "Address" over
+    /// "Street" and "City" gives "address_street" and "address_city"; sub-columns without
+    /// a parent keep their own key
+    #[arg(long, help = "With --header-rows, prefix each column's key with its (merged) parent header's key")]
+    merge_header_with_parent: bool,
+
+    /// Separator placed between the parent and child keys of --merge-header-with-parent
+    #[arg(long, value_name = "SEP", default_value = "_", requires = "merge_header_with_parent", help = "Separator between parent and child keys for --merge-header-with-parent")]
+    parent_separator: String,
+
+    /// Optional: Also convert columns with an empty header, named "column_N"
+    /// Every column up to the last populated cell counts, so --columns numbers
+    /// then refer to all columns rather than only the ones with headers
+    #[arg(long, help = "Include columns with empty headers (named column_N); --columns then counts every column")]
+    include_blank_columns: bool,
+
+    /// How columns whose headers normalize to the same key are written
+    /// overwrite: one key, the rightmost column's value wins /
+    /// column-number: every colliding key gets its 1-based visible column number (e.g. name_c4)
+    #[arg(long, value_enum, default_value_t = OnDuplicate::Overwrite, help = "Handling of columns whose headers give the same key")]
+    on_duplicate: OnDuplicate,
+
+    /// Optional: Treat keys that differ only in case ("Status"/"status", e.g. from
+    /// --header-map) as colliding, and report the original headers involved
+    /// suffix (default): later columns get "_2", "_3", ... / error: stop (exit code 5)
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "suffix", help = "Detect keys that collide ignoring case: suffix later ones with _2, _3, ... or error")]
+    dedup_headers_case_insensitive: Option<CaseCollision>,
+
+    /// Optional: Fail unless every output key is a plain identifier: a lowercase letter
+    /// followed by lowercase letters, digits or underscores (for generating code from the JSON)
+    #[arg(long, help = "Fail (exit code 5) if an output key isn't an identifier like \"first_name\" (^[a-z][a-z0-9_]*$)")]
+    validate_json_keys: bool,
+
+    /// Optional: Prepended to keys that start with a digit, e.g. "col_" turns "3d_model" into "col_3d_model"
+    /// Other options that name columns (--replace, --defaults, ...) then use the prefixed key
+    #[arg(long, value_name = "PREFIX", help = "Prefix for keys that start with a digit, e.g. \"col_\"")]
+    key_prefix: Option<String>,
+
+    /// Optional: How keys are written, after --key-prefix and --numeric-header-prefix:
+    /// snake ("unit_price") or camel ("unitPrice"); --header-map keys are written as given
+    /// Options that name columns (--columns, --replace, --sort-by, ...) still take the snake_case names
+    #[arg(long, value_enum, value_name = "STYLE", default_value_t = KeyStyle::Snake, help = "Write keys in snake_case or camelCase (unit_price -> unitPrice)")]
+    key_style: KeyStyle,
+
+    /// Optional: Prepended to keys made only of digits, e.g. "col_" turns a "2023" header into "col_2023"
+    /// Applied before --key-prefix; the prefix can't produce "column_N", the name of columns without a header
+    #[arg(long, value_name = "PREFIX", help = "Prefix for purely numeric keys such as years, e.g. \"col_\" (2023 -> col_2023)")]
+    numeric_header_prefix: Option<String>,
+
+    /// Optional: Unicode normalization form applied to header text before it becomes a key
+    /// nfc composes accents ("e" + combining acute -> "é"); nfkc also folds full-width and
+    /// compatibility characters ("ＩＤ１" -> "ID1")
+    #[arg(long, value_enum, value_name = "FORM", help = "Normalize header text to nfc or nfkc before building keys (default: off)")]
+    normalize_unicode: Option<UnicodeForm>,
+
+    /// Optional: Also apply the --normalize-unicode form to text cell values
+    #[arg(long, help = "Also apply --normalize-unicode to text values (requires --normalize-unicode)")]
+    normalize_unicode_values: bool,
+
+    /// Optional: Split camelCase/PascalCase headers into words before building keys
+    /// "CustomerID" -> customer_id, "HTTPStatus" -> http_status, "UserIDs" -> user_ids;
+    /// without it such headers become one word ("customerid")
+    #[arg(long, help = "Split camelCase/PascalCase headers into words (CustomerID -> customer_id)")]
+    split_case: bool,
+
+    /// Optional: Comma-separated columns that must be present among the selected headers
+    /// Conversion stops with an error listing the missing ones (exit code 5)
+    /// Example: "name,email,id"
+    #[arg(long, value_name = "COLUMNS", help = "Fail if any of these columns (comma-separated) is missing from the headers")]
+    required_columns: Option<String>,
+
+    /// Optional: Fail instead of warning when a data row has values beyond the header's last column
+    /// A row's width counts up to its last non-empty cell, so shorter rows are fine
+    #[arg(long, help = "Fail (exit code 5) when a data row is wider than the header instead of warning")]
+    strict_width: bool,
+
+    /// Optional: Fail instead of silently dropping or coercing data
+    /// Implies --strict-width and (with --sample-size) --strict-types, and also fails on
+    /// --numeric-columns values that aren't numbers, on columns whose keys collide
+    /// (unless --on-duplicate column-number) and on columns keyed "column_N" for lack of a header
+    #[arg(long, help = "Fail on any silent data loss or coercion: values beyond the header, type fallbacks, duplicate keys, column_N keys")]
+    strict: bool,
+
+    /// Optional: File renaming output keys, applied after header normalization
+    /// Either a JSON object {"from": "to", ...} or "from=to" lines ("#" starts a comment);
+    /// "from" may be the header as written or its normalized form
+    #[arg(long, value_name = "PATH", value_parser = parse_header_map, help = "Rename output keys from a file: JSON object or \"from=to\" lines")]
+    header_map: Option<HeaderMap>,
+
+    /// Optional: Turn headers containing --nested-delimiter into nested objects
+    /// "address.city" and "address.zip" become {"address": {"city": ..., "zip": ...}};
+    /// each part is normalized like a header. Applied last, so --sort-by, --keep-keys,
+    /// --group-by, ... use the flat keys ("address.city")
+    #[arg(long, conflicts_with_all = ["with_hyperlinks", "with_comments", "with_formats"], help = "Write headers like \"address.city\" as nested objects: {\"address\": {\"city\": ...}}")]
+    nested: bool,
+
+    /// Optional: Character separating the levels of a nested header (default ".")
+    /// Example: "|" for "address|city", "/" for "address/city"
+    #[arg(long, value_name = "CHAR", default_value_t = '.', requires = "nested", help = "Separator between the levels of a --nested header, e.g. \"|\" or \"/\"")]
+    nested_delimiter: char,
+
+    /// Optional: Comma-separated list of visible column numbers to include
+    /// Only columns with non-empty headers are counted
+    /// Ranges ("5-8") and open-ended ranges ("5-") are also accepted
+    /// Example: "1,3,5-8,12" or "1-3"
+    #[arg(short, long, help = "Visible column numbers to include (comma-separated, e.g., 1,2,3 or 1,3,5-8,12 or 5-). Only counts columns with non-empty headers. If not specified, all visible columns are included")]
+    columns: Option<String>,
+
+    /// Optional: Read the column list from a file instead of --columns
+    /// Numbers, ranges or header names, one per line and/or comma-separated;
+    /// lines starting with "#" are comments
+    #[arg(long, value_name = "PATH", conflicts_with = "columns", help = "Read the --columns list from a file (one per line or comma-separated, # comments)")]
+    columns_file: Option<PathBuf>,
+
+    /// Optional: Include the visible columns whose header matches this regex
+    /// Tried against the header as written and as normalized ("Metric 2021" / "metric_2021");
+    /// with --columns (or --columns-file) the matches are added after the listed columns
+    /// Example: "^metric_\d+$"
+    #[arg(long, value_name = "PATTERN", value_parser = parse_columns_regex, help = "Include visible columns whose header (as written or normalized) matches this regex")]
+    columns_regex: Option<Regex>,
+
+    /// Optional: Let --columns-regex match no column instead of failing
+    #[arg(long, requires = "columns_regex", help = "Don't fail when --columns-regex matches no column")]
+    allow_empty_match: bool,
+
+    /// Path where the output JSON file will be saved, or "-" for standard output
+    /// (the summary is then left out; a file named "-" can be given as "./-")
+    /// Either this or --output-dir must be given
+    #[arg(short, long, help = "Output JSON file path, or - for standard output")]
+    output: Option<PathBuf>,
+
+    /// Optional: Directory where output files are written, named by --name-template
+    /// The directory is created if it doesn't exist yet
+    #[arg(long, conflicts_with = "output", help = "Output directory; file names are generated from --name-template")]
+    output_dir: Option<PathBuf>,
+
+    /// Optional: Template for generated output file names (used with --output-dir)
+    /// Supports {stem} (input file name without extension), {sheet} and {ext} placeholders
+    /// Example: "{stem}_{sheet}.json" -> "data_Sheet1.json"
+    #[arg(long, requires = "output_dir", help = "Output file name template for --output-dir. Placeholders: {stem}, {sheet}, {ext} [default: {stem}.{ext}]")]
+    name_template: Option<String>,
+
+    /// Optional: With --merge-sheets or --sheets, write every sheet to its own file in
+    /// --output-dir instead of combining them; the name template must contain {sheet}
+    /// (default: "{stem}_{sheet}.{ext}")
+    #[arg(long, requires = "output_dir", conflicts_with_all = ["merge", "group_by"], help = "Write each sheet to its own file in --output-dir (with --merge-sheets or --sheets)")]
+    split_sheets: bool,
+
+    /// Optional: Only keep rows matching a condition such as "status == Active"
+    /// Operators: ==, !=, >, >=, <, <=, contains; combine conditions with "and"
+    /// Numeric comparison when both sides are numbers, text comparison otherwise
+    #[arg(long = "where", value_name = "EXPR", value_parser = parse_where, help = "Only keep rows matching e.g. \"status == Active and age > 30\" (==, !=, >, >=, <, <=, contains)")]
+    where_clause: Option<WhereClause>,
+
+    /// Optional: Values for empty cells, per column: "header=value,header=value"
+    /// With --infer-types, "true"/"false" and numbers are typed like real cell values
+    /// Example: "country=Unknown,active=false"
+    #[arg(long, value_name = "DEFAULTS", value_parser = parse_defaults, help = "Default values for empty cells, e.g. \"country=Unknown,active=false\"")]
+    defaults: Option<HashMap<String, String>>,
+
+    /// Optional: Comma-separated columns whose values are written as numbers, even without --infer-types
+    /// Text such as "1,234" is parsed with the --locale separators; values that aren't
+    /// numbers stay text and are reported in a warning
+    /// Example: "amount,total"
+    #[arg(long, value_name = "COLUMNS", help = "Write these columns (comma-separated) as numbers, e.g. \"amount,total\"")]
+    numeric_columns: Option<String>,
+
+    /// Optional: Comma-separated columns whose values are always written as strings
+    /// Keeps identifiers such as phone numbers or zip codes as text while --infer-types
+    /// converts the other columns; the cells are written as without --infer-types
+    /// Example: "phone,ssn,zip"
+    #[arg(long, value_name = "COLUMNS", help = "Write these columns (comma-separated) as strings even with --infer-types, e.g. \"phone,zip\"")]
+    string_columns: Option<String>,
+
+    /// Optional: Comma-separated columns whose 1/0 values are written as true/false
+    /// Other values (2, "yes", ...) are converted as usual and reported in a warning,
+    /// or stop the conversion with --strict-bool-columns
+    /// Example: "active,deleted"
+    #[arg(long, value_name = "COLUMNS", help = "Write 1/0 in these columns (comma-separated) as true/false, e.g. \"active,deleted\"")]
+    bool_columns: Option<String>,
+
+    /// Optional: Stop at the first --bool-columns value that isn't 1, 0 or a boolean (exit code 1)
+    #[arg(long, requires = "bool_columns", help = "Fail on --bool-columns values other than 1/0 instead of keeping them")]
+    strict_bool_columns: bool,
+
+    /// Optional: Comma-separated columns holding dates stored as text, e.g. "03/15/2023"
+    /// Text matching --date-input-format is written in the --dates format like a date cell;
+    /// other text stays as it is and is reported in a warning
+    /// Example: "created,updated"
+    #[arg(long, value_name = "COLUMNS", help = "Parse text in these columns (comma-separated) as dates, e.g. \"created,updated\"")]
+    date_columns: Option<String>,
+
+    /// Optional: chrono format of the text dates in --date-columns (default: "%Y-%m-%d")
+    /// Example: "%m/%d/%Y" for "03/15/2023", "%d.%m.%Y %H:%M" for dates with a time
+    #[arg(long, value_name = "FORMAT", requires = "date_columns", value_parser = parse_date_input_format, help = "Format of the text dates in --date-columns, e.g. \"%m/%d/%Y\" [default: %Y-%m-%d]")]
+    date_input_format: Option<String>,
+
+    /// Optional: Comma-separated list of columns to sort records by
+    /// Each key may end with ":desc" (or ":asc") to choose the direction
+    /// Example: "region,amount:desc" sorts by region, then by amount descending
+    #[arg(long, help = "Sort records by column(s), e.g. region,amount:desc. Numeric when both values are numbers, nulls/empty last")]
+    sort_by: Option<String>,
+
+    /// Optional: Emit native JSON types instead of stringifying every cell
+    /// Numbers become JSON numbers, booleans become true/false, empty cells become null
+    #[arg(long, help = "Infer JSON types from cell values (numbers, booleans, null) instead of converting everything to strings")]
+    infer_types: bool,
+
+    /// Optional: During type inference, parse text like "$1,234.56" as a number
+    /// Currency symbols and thousands separators are removed before parsing
+    #[arg(long, requires = "infer_types", help = "With --infer-types, strip currency symbols and thousands separators from text and emit numbers")]
+    strip_currency: bool,
+
+    /// Number format convention used by --strip-currency
+    /// us: 1,234.56 (comma groups, dot decimal) / eu: 1.234,56 (dot groups, comma decimal)
+    #[arg(long, value_enum, default_value_t = NumberLocale::Us, requires = "strip_currency", help = "Thousands/decimal separator convention for --strip-currency")]
+    locale: NumberLocale,
+
+    /// Optional: With --infer-types, parse numeric text such as "1.234,56" as numbers
+    /// dot: 1,234.56 (comma groups) / comma: 1.234,56 (dot groups)
+    /// Also sets the convention for --strip-currency and --numeric-columns (instead of --locale)
+    #[arg(long, value_enum, value_name = "SEPARATOR", conflicts_with = "locale", help = "Parse numeric text with this decimal separator (dot: 1,234.56 / comma: 1.234,56); needs --infer-types or --numeric-columns")]
+    decimal_separator: Option<DecimalSeparator>,
+
+    /// Optional: Fix each column's type from its first N data rows instead of inferring
+    /// every cell on its own; later cells of another type are kept as text (with a warning)
+    /// Without it every cell is inferred separately, as if all rows were sampled
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), requires = "infer_types", help = "With --infer-types, decide each column's type from the first N data rows; later mismatches stay text")]
+    sample_size: Option<usize>,
+
+    /// Optional: Fail at the first cell that doesn't match its column's sampled type
+    #[arg(long, requires = "sample_size", help = "With --sample-size, fail instead of keeping mismatching values as text")]
+    strict_types: bool,
+
+    /// Optional: Round numbers written as JSON numbers to N decimal places
+    /// Only affects --infer-types/--numeric-columns numbers, never text; exact halves
+    /// round to the even digit. Without it numbers keep every stored digit
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(0..=15), help = "Round JSON numbers to N decimal places (0-15, needs --infer-types or --numeric-columns)")]
+    float_precision: Option<u32>,
+
+    /// Optional: Regex substitution applied to a column's values before type inference
+    /// Format: "header:pattern=>replacement", repeatable; rules for the same column apply in order
+    /// Example: --replace "code:^ID-=>" strips an "ID-" prefix from the code column
+    #[arg(long = "replace", value_name = "RULE", value_parser = parse_replace_rule, help = "Regex replace in a column before type inference: \"header:pattern=>replacement\" (repeatable)")]
+    replace: Vec<ReplaceRule>,
+
+    /// Optional: Read a key-value layout: the first column holds the field names
+    /// and every following column is one record
+    #[arg(long, conflicts_with_all = ["only_visible_rows", "ignore_hidden_columns", "with_hyperlinks", "with_comments", "with_formats"], help = "Treat the first column as keys and each following column as a record")]
+    transpose: bool,
+
+    /// Optional: Convert only the sheet's print area (the _xlnm.Print_Area defined name)
+    /// Cells outside it, such as notes beside the table, are ignored; the print area's
+    /// first row is the header. A sheet without a print area is converted whole, with a warning
+    #[arg(long, conflicts_with = "table", help = "Convert only the cells inside the sheet's print area (whole sheet with a warning if none is set)")]
+    use_print_area: bool,
+
+    /// Optional: Convert only the cells inside an A1-style rectangle; its first row is the header
+    /// A sheet qualifier ("Details!A1:C50", "'Q1 Sales'!B3:F40") reads that sheet
+    /// instead of the sheet argument. Whole columns ("A:D") and rows ("3:40") work too
+    #[arg(long, value_name = "RANGE", value_parser = parse_sheet_range, conflicts_with_all = ["table", "use_print_area"], help = "Convert only this cell range, e.g. A1:C50 or Details!A1:C50 (a sheet qualifier overrides the sheet argument)")]
+    range: Option<SheetRange>,
+
+    /// Optional: Crop rows with no values (empty or whitespace-only cells) from the top
+    /// and bottom of the sheet before the header is read, e.g. rows Excel counts as used
+    /// because of stray formatting. Empty rows between data rows are kept
+    #[arg(long, help = "Drop empty rows above the header and below the last data row")]
+    trim_sheet_whitespace_rows: bool,
+
+    /// Optional: Read the sheet row by row while converting instead of loading it whole first
+    /// Keeps memory flat for huge sheets. Options that need every cell up front
+    /// (--transpose, --use-print-area, --column-types, ...) can't be combined with it
+    #[arg(long, conflicts_with_all = ["table", "transpose", "use_print_area", "range", "trim_sheet_whitespace_rows", "include_blank_columns", "column_types", "strict", "strict_types", "strict_bool_columns", "strict_width"], help = "Stream the sheet's rows from the file instead of loading the whole sheet into memory")]
+    lazy_read: bool,
+
+    /// Optional: Skip rows that are hidden in Excel (by hand or by an AutoFilter)
+    /// By default hidden rows are converted like any other row
+    #[arg(long, help = "Exclude rows that are hidden in the xlsx file")]
+    only_visible_rows: bool,
+
+    /// Optional: Leave out columns that are hidden in Excel, as if they had no header
+    /// Column numbers given to --columns then only count the columns that are shown
+    /// By default hidden columns are converted like any other column
+    #[arg(long, help = "Exclude columns that are hidden in the xlsx file")]
+    ignore_hidden_columns: bool,
+
+    /// Optional: Split a column's values on a delimiter into arrays of strings
+    /// Format: "header:delimiter", repeatable; elements are trimmed and empty ones dropped
+    /// Example: --split-cells "tags:;" turns "red; green;blue" into ["red", "green", "blue"]
+    #[arg(long, value_name = "RULE", value_parser = parse_split_rule, help = "Split a column's values into a JSON array: \"header:delimiter\" (repeatable)")]
+    split_cells: Vec<SplitRule>,
+
+    /// What an empty cell becomes in a --split-cells column
+    #[arg(long, value_enum, default_value_t = SplitEmpty::Array, requires = "split_cells", help = "Value for empty cells in --split-cells columns")]
+    split_empty: SplitEmpty,
+
+    /// Optional: Also emit each cell's hyperlink URL under "<key>_url"
+    /// Cells without a hyperlink only get their display text
+    #[arg(long, help = "Emit cell hyperlink URLs as \"<key>_url\" fields next to the display text")]
+    with_hyperlinks: bool,
+
+    /// Optional: Also emit each cell's comment (note) under "<key>_comment"
+    /// Cells without a comment get no extra field
+    #[arg(long, help = "Emit cell comments/notes as \"<key>_comment\" fields next to the value")]
+    with_comments: bool,
+
+    /// Optional: Also emit each cell's Excel number format (e.g. "0.00%") under "<key>_format"
+    /// Cells with the General format get no extra field
+    #[arg(long, help = "Emit cell number formats as \"<key>_format\" fields next to the value")]
+    with_formats: bool,
+
+    /// Optional: Add the sheet each record came from as its first field
+    /// The field is named "_sheet" unless a name is given; it must not clash with a column
+    /// Example: --with-sheet-name=source adds "source": "Sheet1" to each record
+    #[arg(long, value_name = "FIELD", num_args = 0..=1, require_equals = true, default_missing_value = "_sheet", help = "Add the originating sheet name to each record as its first field [default field: _sheet]")]
+    with_sheet_name: Option<String>,
+
+    /// Optional: Add the input file each record came from as its first field
+    /// The field is named "_source" unless a name is given; it must not clash with a column
+    /// Example: --with-source-file=region adds "region": "north.xlsx" to each record
+    #[arg(long, value_name = "FIELD", num_args = 0..=1, require_equals = true, default_missing_value = "_source", help = "Add the originating input file to each record as its first field [default field: _source]")]
+    with_source_file: Option<String>,
+
+    /// Optional: Number the output records and add the number as their first field
+    /// The numbers run without gaps over the records actually written - after --where,
+    /// --unique and --sort-by - so they don't follow the sheet's row numbers
+    /// The field is named "id" unless a name is given; it must not clash with a column
+    #[arg(long, value_name = "FIELD", num_args = 0..=1, require_equals = true, default_missing_value = "id", help = "Add a sequential record number as the first field [default field: id]")]
+    index_key: Option<String>,
+
+    /// Number of the first record for --index-key, e.g. 0 for 0-based numbering
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "index_key", help = "First number used by --index-key")]
+    index_start: u64,
+
+    /// How date/time cells are written
+    /// serial: Excel's serial number (e.g. 45000) / iso: ISO 8601 text (e.g. 2023-03-15) /
+    /// time: iso plus "HH:MM:SS" for time-only and elapsed-time values (e.g. 25:30:00) /
+    /// epoch: seconds since 1970-01-01 UTC as a number (e.g. 1678838400)
+    #[arg(long, value_enum, default_value_t = DateMode::Serial, help = "Output form of date/time cells")]
+    dates: DateMode,
+
+    /// What formula cells contribute to the output
+    /// value: the result cached in the file / text: the formula itself (e.g. "=A1+B1")
+    /// Cells without a formula are unaffected either way
+    #[arg(long, value_enum, default_value_t = FormulaMode::Value, help = "Output the cached result or the formula text of formula cells")]
+    formulas: FormulaMode,
+
+    /// Whitespace handling for text cells
+    /// none: keep as-is / ends: trim leading and trailing whitespace /
+    /// collapse: also turn inner runs of whitespace into single spaces
+    #[arg(long, value_enum, default_value_t = TrimMode::None, help = "Whitespace trimming of text cell values")]
+    trim: TrimMode,
+
+    /// Optional: Cut text cells to at most this many characters
+    /// Counts Unicode characters, not bytes; numbers and booleans are never cut
+    #[arg(long, value_name = "N", help = "Truncate text cell values to at most N characters")]
+    max_cell_length: Option<usize>,
+
+    /// Optional: Text appended to values cut by --max-cell-length (default "…" when given without a value)
+    #[arg(long, value_name = "MARKER", num_args = 0..=1, require_equals = true, default_missing_value = "…", requires = "max_cell_length", help = "Append a marker to truncated values [default marker: …]")]
+    truncate_marker: Option<String>,
+
+    /// Output file format
+    /// json: pretty-printed array of objects / toml: array of tables under --toml-key /
+    /// ndjson: one compact JSON object per line / xml: <record> elements under --xml-root /
+    /// msgpack: binary MessagePack array of maps
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json, help = "Output format")]
+    format: OutputFormat,
+
+    /// Name of the top-level key that holds the records in TOML output
+    /// TOML documents can't be a bare array, so records become [[records]] tables
+    #[arg(long, default_value = "records", help = "Top-level key for the array of tables in --format toml output")]
+    toml_key: String,
+
+    /// Name of the root element in XML output
+    /// Each record becomes a <record> child with one element per field
+    #[arg(long, default_value = "records", help = "Root element name for --format xml output")]
+    xml_root: String,
+
+    /// Optional: Write the JSON array as the value of this key in a top-level object
+    /// Example: --wrap-key data gives {"data": [...]}; only with --format json
+    #[arg(long, value_name = "KEY", conflicts_with = "group_by", help = "Wrap the JSON array in an object under this key, e.g. {\"data\": [...]} (json only)")]
+    wrap_key: Option<String>,
+
+    /// Optional: With --wrap-key, also write the number of records as "count"
+    #[arg(long, requires = "wrap_key", help = "With --wrap-key, add the record count: {\"data\": [...], \"count\": N}")]
+    wrap_count: bool,
+
+    /// Optional: Write non-ASCII characters as \uXXXX escapes in JSON/NDJSON output
+    /// Characters outside the Basic Multilingual Plane become surrogate pairs
+    #[arg(long, help = "Escape non-ASCII characters as \\uXXXX in json/ndjson output (default: raw UTF-8)")]
+    ensure_ascii: bool,
+
+    /// Optional: Write JSON/NDJSON numbers in plain decimal notation, never as 1e-7 or 1e21
+    /// Digits past a 64-bit float's precision (about 15-17 significant digits) come out as
+    /// zeros, and extreme magnitudes give long numbers (1e300 has 301 digits)
+    #[arg(long, help = "Write numbers in decimal notation instead of scientific (1e-7 -> 0.0000001) in json/ndjson output")]
+    no_scientific: bool,
+
+    /// Optional: Start text output files with a UTF-8 byte order mark (EF BB BF)
+    /// Some Windows tools need it to detect the encoding; ignored for msgpack
+    #[arg(long, help = "Write a UTF-8 byte order mark at the start of text output (no effect on msgpack)")]
+    bom: bool,
+
+    /// Optional: Start the output with comments saying where it came from
+    /// (source file, sheet and conversion time). TOML gets "#" lines and XML an
+    /// <!-- --> comment; JSON has no comments, so with --wrap-key the same
+    /// details go into a "_meta" object, and otherwise the flag has no effect
+    #[arg(long, help = "Start toml/xml output with source file, sheet and time comments (json: \"_meta\" key with --wrap-key)")]
+    comment_header: bool,
+
+    /// Optional: Write each record's keys in alphabetical order instead of column order
+    /// Keeps diffs stable when columns are reordered in the source sheet
+    #[arg(long, help = "Sort each record's keys alphabetically (default: column order)")]
+    sort_keys: bool,
+
+    /// Optional: Leave out keys whose value is null or an empty string
+    /// Checked after type inference and --defaults, so records can have different keys
+    #[arg(long, help = "Omit keys whose value is null or an empty string")]
+    compact_empty: bool,
+
+    /// Optional: Leave out records that have no keys left ({})
+    /// Checked after --compact-empty and --keep-keys/--drop-keys, so it catches rows
+    /// whose only content is in columns that aren't selected
+    #[arg(long, help = "Leave out records that end up with no keys ({})")]
+    drop_empty_records: bool,
+
+    /// Optional: Comma-separated keys to keep in each record; all other keys are removed
+    /// Applied last, to the final keys (after --with-sheet-name, --with-hyperlinks, ...)
+    /// Example: "name,email"
+    #[arg(long, value_name = "KEYS", value_parser = parse_key_list, conflicts_with = "drop_keys", help = "Keep only these keys (comma-separated) in each output record")]
+    keep_keys: Option<KeyList>,
+
+    /// Optional: Comma-separated keys to remove from each record
+    /// Example: "internal_id,_sheet"
+    #[arg(long, value_name = "KEYS", value_parser = parse_key_list, help = "Remove these keys (comma-separated) from each output record")]
+    drop_keys: Option<KeyList>,
+
+    /// Optional: Add the records to the end of an existing output file instead of overwriting it
+    /// Only valid with --format ndjson, where every line is a standalone record
+    #[arg(long, help = "Append records to the output file instead of overwriting it (requires --format ndjson)")]
+    append: bool,
+
+    /// Optional: Split the records into files of at most N records each
+    /// The files are named after the output path: result.json -> result_0001.json, result_0002.json, ...
+    /// The last file may hold fewer records
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..), conflicts_with_all = ["append", "group_by"], help = "Write the records to numbered files of at most N records each (output_0001.json, ...)")]
+    chunk_size: Option<usize>,
+
+    /// Optional: Size of the output write buffer, in bytes or with a K/M suffix (1024-based)
+    /// Records are collected in the buffer and written in large blocks, which matters
+    /// for ndjson output of millions of small records
+    #[arg(long, value_name = "SIZE", default_value = "64K", value_parser = parse_buffer_size, help = "Output write buffer size, e.g. 65536, 256K or 4M")]
+    buffer_size: usize,
+
+    /// Optional: Drop records that are exact duplicates of an earlier record
+    #[arg(long, help = "Remove duplicate records, keeping the first occurrence")]
+    unique: bool,
+
+    /// Optional: Drop records whose value in this column was already seen
+    /// Example: "email" keeps only the first record for each email address
+    #[arg(long, conflicts_with = "unique", help = "Remove records with a duplicate value in this column, keeping the first occurrence")]
+    unique_by: Option<String>,
+
+    /// Optional: Write an object of arrays keyed by this column's values instead of one array
+    /// Example: "region" gives {"North": [...], "South": [...]}; only with --format json
+    #[arg(long, value_name = "COLUMN", help = "Group records into an object keyed by this column's values (json only)")]
+    group_by: Option<String>,
+
+    /// Optional: Remove the --group-by column from the grouped records
+    #[arg(long, requires = "group_by", help = "Remove the --group-by column from each grouped record")]
+    drop_group_key: bool,
+
+    /// Key of the group holding records whose --group-by value is empty or null
+    #[arg(long, value_name = "KEY", default_value = "", requires = "group_by", help = "Group key for records with an empty --group-by value")]
+    group_empty_key: String,
+
+    /// Optional: Treat an empty result as an error instead of writing "[]"
+    /// Catches wrong sheets or over-eager filters in automated pipelines
+    #[arg(long, help = "Exit with an error (code 7) instead of writing output when no records are produced")]
+    fail_on_empty: bool,
+
+    /// Optional: Don't print the conversion summary or progress bar
+    /// Errors are still reported on stderr
+    #[arg(short, long, help = "Suppress the conversion summary and progress bar (errors are still shown)")]
+    quiet: bool,
+
+    /// Optional: Print the summary as one JSON object on stderr instead of text lines
+    /// Includes input, sheets, output, column/record counts and read/convert timings
+    #[arg(long, help = "Print conversion statistics (counts and timings) as a single JSON object on stderr")]
+    stats_json: bool,
+
+    /// Optional: Count the cell types (numeric, string, boolean, date, empty, error) of each column
+    /// Shown in the summary and as "column_types" in --stats-json; counts the cells as stored
+    /// in the file, before --replace, --trim or type inference
+    #[arg(long, help = "Add a per-column breakdown of cell types to the summary / --stats-json")]
+    column_types: bool,
+
+    /// Optional: Also write how each sheet header became an output key, as a JSON file
+    /// One entry per selected column: {sheet, original, normalized, visible_number, actual_index}
+    #[arg(long, value_name = "PATH", help = "Write the header-to-key mapping of the selected columns to this JSON file")]
+    emit_mapping: Option<PathBuf>,
+
+    /// Optional: Also write the list of files --split-sheets produced, as a JSON file
+    /// One entry per output file: {sheet, file, records}; with --chunk-size every chunk is listed
+    #[arg(long, value_name = "PATH", requires = "split_sheets", help = "Write a JSON manifest of the files written by --split-sheets ({sheet, file, records})")]
+    emit_manifest: Option<PathBuf>,
+
+    /// Optional: Keep running and re-convert whenever the input file changes
+    #[arg(long, conflicts_with = "dry_run", help = "Watch the input file and re-convert it whenever it changes")]
+    watch: bool,
+
+    /// Optional: Report what would be converted without writing any files
+    /// Prints the resolved headers, column indices and record count instead
+    #[arg(long, help = "Show resolved headers, column indices and record count without writing any output")]
+    dry_run: bool,
+
+    /// Optional: TOML file with default values for options (see CONFIG_OPTIONS)
+    /// Without it, .excel2json.toml in the current directory is used if it exists
+    #[arg(long, value_name = "PATH", help = "Read default option values from this TOML file [default: ./.excel2json.toml if present]")]
+    config: Option<PathBuf>,
+}
+
+/// Arguments of `excel2json preview`, which prints the top of a sheet as a text table
+#[derive(clap::Args, Debug, Clone)]
+struct PreviewArgs {
+    /// Path to the input Excel file (.xlsx or .xlsb), or archive.zip!inner.xlsx
+    #[arg(help = "Input Excel file path (.xlsx or .xlsb), or archive.zip!inner.xlsx for a workbook inside a zip")]
+    file: PathBuf,
+
+    /// Name of the sheet to preview
+    #[arg(help = "Sheet name to preview")]
+    sheet: String,
+
+    /// Number of data rows shown below the header
+    #[arg(long, value_name = "N", default_value_t = 10, help = "Number of data rows to show")]
+    rows: usize,
+}
+
+/// Arguments of `excel2json headers`, which shows the key each header of a sheet becomes
+/// 
+/// The sheet is chosen and read with the conversion options, so every option
+/// that shapes the keys (--header-rows, --header-map, --key-prefix, ...) works
+/// as in a conversion. Nothing is written, so no --output is needed.
+#[derive(clap::Args, Debug, Clone)]
+#[command(mut_group("destination", |group| group.required(false)))]
+struct HeadersArgs {
+    /// Optional: Also flag headers whose key loses information: headers that normalize to
+    /// the same key, "#" dropped from a header, camelCase words run together, empty keys.
+    /// Exits with code 5 if any header is flagged, so templates can be checked in CI
+    #[arg(long, help = "Flag headers that collide or lose information when normalized (exit code 5 if any)")]
+    audit: bool,
+
+    /// The conversion options; the config file applies too
+    #[command(flatten)]
+    convert: Args,
+}
+
+/// Arguments of `excel2json comments`, which lists the commented cells of every sheet
+#[derive(clap::Args, Debug, Clone)]
+struct CommentsArgs {
+    /// Path to the input Excel file (.xlsx), or archive.zip!inner.xlsx
+    #[arg(help = "Input Excel file path (.xlsx), or archive.zip!inner.xlsx for a workbook inside a zip")]
     file: PathBuf,
 
-    /// Name of the sheet within the Excel file to convert
-    #[arg(help = "Sheet name to convert")]
-    sheet: String,
+    /// Optional: Where to write the report; printed to standard output if not given
+    #[arg(short, long, help = "Output JSON file path (default: standard output)")]
+    output: Option<PathBuf>,
+}
+
+/// What is read from cells that contain a formula
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum FormulaMode {
+    /// The result Excel cached when the file was last saved
+    Value,
+    /// The formula string with a leading "=" (e.g. "=SUM(A1:A3)")
+    Text,
+}
+
+/// Top-level structure of the output (--output-shape)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputShape {
+    /// Object for --sheets without --with-sheet-name, array otherwise
+    Auto,
+    /// One array with the records of every sheet
+    Array,
+    /// One array per sheet, keyed by sheet name
+    Object,
+}
+
+/// How sheet names become keys of output keyed by sheet (--sheet-key-style)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum SheetKeyStyle {
+    /// The sheet name as written on its tab
+    Raw,
+    /// Normalized like a column header: "Sheet 1" -> "sheet_1"
+    Snake,
+    /// Lowercase letters and digits joined by hyphens: "Q1 Sales" -> "q1-sales"
+    Slug,
+}
+
+impl SheetKeyStyle {
+    /// Applies the style to a sheet name
+    fn apply(self, sheet_name: &str) -> String {
+        match self {
+            SheetKeyStyle::Raw => sheet_name.to_string(),
+            SheetKeyStyle::Snake => normalize_column_name(sheet_name),
+            SheetKeyStyle::Slug => sheet_name
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>()
+                .join("-"),
+        }
+    }
+}
+
+/// What happens when several columns' headers normalize to the same key
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OnDuplicate {
+    /// Keep a single key; the value of the rightmost column wins
+    Overwrite,
+    /// Suffix every colliding key with its visible column number ("_c4")
+    ColumnNumber,
+}
+
+/// What happens when keys collide ignoring case (--dedup-headers-case-insensitive)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CaseCollision {
+    /// Keep the first key; later ones get "_2", "_3", ...
+    Suffix,
+    /// Stop with an error listing the colliding headers
+    Error,
+}
+
+/// What happens when an input file lacks a requested sheet (--on-missing-sheet)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OnMissingSheet {
+    /// Stop with "sheet not found" (exit code 4)
+    Error,
+    /// Leave the sheet out; it is listed in the summary
+    Skip,
+    /// Leave the sheet out and print a warning
+    Warn,
+}
+
+/// Supported output file formats
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// Pretty-printed JSON array of objects
+    Json,
+    /// TOML document with the records as an array of tables
+    Toml,
+    /// Newline-delimited JSON: one compact object per line
+    Ndjson,
+    /// XML document with one <record> element per record under --xml-root
+    Xml,
+    /// Binary MessagePack: an array of maps mirroring the JSON array of objects
+    Msgpack,
+}
+
+impl OutputFormat {
+    /// File extension (without the dot) used for {ext} in --name-template
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Msgpack => "msgpack",
+        }
+    }
+}
+
+/// Options that control how the records are serialized and written
+#[derive(Debug, Clone)]
+struct OutputOptions {
+    /// Output file format (--format)
+    format: OutputFormat,
+    /// Top-level key for TOML output (--toml-key)
+    toml_key: String,
+    /// Add to the end of an existing output file instead of replacing it (--append)
+    append: bool,
+    /// Root element name for XML output (--xml-root)
+    xml_root: String,
+    /// Key of the top-level object holding the JSON array (--wrap-key)
+    wrap_key: Option<String>,
+    /// Add the record count next to the wrapped array (--wrap-count)
+    wrap_count: bool,
+    /// Escape non-ASCII characters as \uXXXX in JSON output (--ensure-ascii)
+    ensure_ascii: bool,
+    /// Write floats in decimal instead of scientific notation in JSON output (--no-scientific)
+    no_scientific: bool,
+    /// Start text output with a UTF-8 byte order mark (--bom)
+    bom: bool,
+    /// Maximum number of records per output file (--chunk-size)
+    chunk_size: Option<usize>,
+    /// Capacity of the output write buffer in bytes (--buffer-size)
+    buffer_size: usize,
+    /// Where the records came from, written as a comment header (--comment-header);
+    /// filled in once the inputs are read
+    provenance: Option<Provenance>,
+}
+
+impl OutputOptions {
+    /// Collects the output options from the command-line arguments
+    fn from_args(args: &Args) -> Self {
+        OutputOptions {
+            format: args.format,
+            toml_key: args.toml_key.clone(),
+            append: args.append,
+            xml_root: args.xml_root.clone(),
+            wrap_key: args.wrap_key.clone(),
+            wrap_count: args.wrap_count,
+            ensure_ascii: args.ensure_ascii,
+            no_scientific: args.no_scientific,
+            bom: args.bom,
+            chunk_size: args.chunk_size,
+            buffer_size: args.buffer_size,
+            provenance: None,
+        }
+    }
+}
+
+/// Where the output came from, for --comment-header
+#[derive(Debug, Clone)]
+struct Provenance {
+    /// Input file(s), comma-separated
+    source: String,
+    /// Converted sheet(s), comma-separated
+    sheet: String,
+    /// Conversion time as an ISO 8601 UTC timestamp
+    generated: String,
+}
+
+impl Provenance {
+    /// The details as "Label: value" comment lines
+    fn lines(&self) -> [String; 3] {
+        [
+            format!("Source: {}", self.source),
+            format!("Sheet: {}", self.sheet),
+            format!("Generated: {} by excel2json {}", self.generated, env!("CARGO_PKG_VERSION")),
+        ]
+    }
+
+    /// The details as the JSON "_meta" object, the same as the comment lines
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "source": self.source,
+            "sheet": self.sheet,
+            "generated": self.generated,
+            "generator": format!("excel2json {}", env!("CARGO_PKG_VERSION")),
+        })
+    }
+}
+
+/// Reads a --header-map file of header renames
+/// 
+/// The file is either a JSON object of strings or "from=to" lines; blank lines
+/// and lines starting with "#" are skipped. Sources are normalized like headers,
+/// so "First Name" and "first_name" are the same entry; target keys are used as
+/// written. Used as a clap value parser, so a bad file is rejected at startup.
+/// 
+/// # Example
+/// "First Name=given_name\nE-mail=email" -> {"first_name": "given_name", "e_mail": "email"}
+/// 
+/// # Errors
+/// - Returns error if the file can't be read or a line/value is malformed
+/// - Returns error if two sources map to the same key
+fn parse_header_map(path: &str) -> std::result::Result<HeaderMap, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+
+    // (source, target) pairs in file order
+    let pairs: Vec<(String, String)> = if text.trim_start().starts_with('{') {
+        let object: serde_json::Map<String, Value> =
+            serde_json::from_str(&text).map_err(|e| format!("invalid JSON in {}: {}", path, e))?;
+        object
+            .into_iter()
+            .map(|(from, to)| match to {
+                Value::String(to) => Ok((from, to)),
+                other => Err(format!("value for '{}' must be a string, got {}", from, other)),
+            })
+            .collect::<std::result::Result<_, _>>()?
+    } else {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.split_once('=')
+                    .map(|(from, to)| (from.to_string(), to.trim().to_string()))
+                    .ok_or_else(|| format!("expected \"from=to\", got '{}'", line))
+            })
+            .collect::<std::result::Result<_, _>>()?
+    };
+
+    let mut map = HeaderMap::new();
+    for (from, to) in pairs {
+        if to.is_empty() {
+            return Err(format!("empty key for '{}'", from.trim()));
+        }
+        let from = normalize_column_name(&from);
+        // Two columns renamed to one key would overwrite each other in every record
+        if let Some((other, _)) = map.iter().find(|(source, key)| **key == to && **source != from) {
+            return Err(format!("'{}' and '{}' both map to '{}'", other, from, to));
+        }
+        map.insert(from, to);
+    }
+    Ok(map)
+}
+
+/// Parses a --buffer-size value: a number of bytes, optionally with a K or M suffix
+/// 
+/// Used as a clap value parser.
+/// 
+/// # Examples
+/// - "65536" -> 65536
+/// - "256K" -> 262144
+/// - "4m" -> 4194304
+fn parse_buffer_size(value: &str) -> std::result::Result<usize, String> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&value[..at], 1024),
+        Some((at, 'm' | 'M')) => (&value[..at], 1024 * 1024),
+        _ => (value, 1),
+    };
+    number
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit))
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("expected a positive number of bytes like 65536, 256K or 4M, got '{}'", value))
+}
+
+/// Checks a --date-input-format value is a valid chrono format
+/// 
+/// Used as a clap value parser.
+/// 
+/// # Examples
+/// - "%m/%d/%Y" -> Ok
+/// - "%Q" -> Err (unknown specifier)
+fn parse_date_input_format(value: &str) -> std::result::Result<String, String> {
+    let invalid = chrono::format::StrftimeItems::new(value).any(|item| item == chrono::format::Item::Error);
+    if invalid || value.trim().is_empty() {
+        return Err(format!("'{}' is not a valid date format, expected e.g. \"%m/%d/%Y\"", value));
+    }
+    Ok(value.to_string())
+}
+
+/// Parses a --defaults list of the form "header=value,header=value"
+/// 
+/// Header names are normalized like the JSON keys; values are kept as written
+/// (an empty value is allowed). Used as a clap value parser.
+/// 
+/// # Example
+/// "Country=Unknown, active=false" -> {"country": "Unknown", "active": "false"}
+fn parse_defaults(spec: &str) -> std::result::Result<HashMap<String, String>, String> {
+    spec.split(',')
+        .map(|item| {
+            let (header, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected \"header=value\", got '{}'", item.trim()))?;
+            let column = normalize_column_name(header);
+            if column.is_empty() {
+                return Err(format!("missing header name in '{}'", item.trim()));
+            }
+            Ok((column, value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Output keys named by --keep-keys or --drop-keys
+/// 
+/// Each name is stored as written and normalized like a header, so both
+/// "Internal ID" and "internal_id" name the key "internal_id".
+#[derive(Debug, Clone)]
+struct KeyList(Vec<String>);
+
+impl KeyList {
+    /// Whether `key` is one of the listed keys
+    fn contains(&self, key: &str) -> bool {
+        self.0.iter().any(|name| name == key)
+    }
+}
+
+/// Sheet renames for output keyed by sheet (--sheet-rename), as (sheet, key) pairs
+#[derive(Debug, Clone)]
+struct SheetRename(Vec<(String, String)>);
+
+impl SheetRename {
+    /// The key a sheet is renamed to; sheet names are matched ignoring case, as Excel does
+    fn get(&self, sheet_name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(sheet, _)| sheet.to_lowercase() == sheet_name.to_lowercase())
+            .map(|(_, key)| key.as_str())
+    }
+}
+
+/// Parses a comma-separated --sheet-rename list of "sheet=key" pairs
+/// 
+/// # Errors
+/// - Returns error text if a pair has no "=" or an empty side, or the list is empty
+/// - Returns error text if a sheet is listed twice or two sheets get the same key
+/// 
+/// # Example
+/// "Sheet 1=primary, Sheet 2=secondary" -> [("Sheet 1", "primary"), ("Sheet 2", "secondary")]
+fn parse_sheet_rename(spec: &str) -> std::result::Result<SheetRename, String> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    for pair in spec.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let Some((sheet, key)) = pair.split_once('=') else {
+            return Err(format!("expected \"sheet=key\", got '{}'", pair));
+        };
+        let (sheet, key) = (sheet.trim(), key.trim());
+        if sheet.is_empty() || key.is_empty() {
+            return Err(format!("expected \"sheet=key\", got '{}'", pair));
+        }
+        if pairs.iter().any(|(other, _)| other.to_lowercase() == sheet.to_lowercase()) {
+            return Err(format!("sheet '{}' is renamed twice", sheet));
+        }
+        if let Some((other, _)) = pairs.iter().find(|(_, other_key)| other_key == key) {
+            return Err(format!("'{}' and '{}' both map to '{}'", other, sheet, key));
+        }
+        pairs.push((sheet.to_string(), key.to_string()));
+    }
+    if pairs.is_empty() {
+        return Err("expected a comma-separated list of \"sheet=key\" pairs".to_string());
+    }
+    Ok(SheetRename(pairs))
+}
+
+/// Parses a comma-separated --keep-keys/--drop-keys list
+/// 
+/// # Errors
+/// Returns error text if the list names no key
+/// 
+/// # Example
+/// "Name, email" -> KeyList(["Name", "name", "email"])
+fn parse_key_list(spec: &str) -> std::result::Result<KeyList, String> {
+    let mut names = Vec::new();
+    for name in spec.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        for form in [name.to_string(), normalize_column_name(name)] {
+            if !names.contains(&form) {
+                names.push(form);
+            }
+        }
+    }
+    if names.is_empty() {
+        return Err("expected a comma-separated list of keys".to_string());
+    }
+    Ok(KeyList(names))
+}
+
+/// Removes the keys of a record that --keep-keys or --drop-keys filter out
+/// 
+/// Records that aren't objects are left alone.
+fn filter_record_keys(record: &mut Value, args: &Args) {
+    let Value::Object(fields) = record else {
+        return;
+    };
+    if let Some(keep) = &args.keep_keys {
+        fields.retain(|key, _| keep.contains(key));
+    } else if let Some(drop) = &args.drop_keys {
+        fields.retain(|key, _| !drop.contains(key));
+    }
+}
+
+/// Checks whether a record is an object without keys, left out by --drop-empty-records
+fn is_empty_record(record: &Value) -> bool {
+    matches!(record, Value::Object(fields) if fields.is_empty())
+}
+
+/// Adds the record's sequential number as its first key (--index-key)
+/// 
+/// # Arguments
+/// * `record` - Record about to be written
+/// * `field` - Name of the field to add (e.g. "id")
+/// * `index` - The record's number
+/// 
+/// # Errors
+/// Returns error if the record already has a field with this name
+fn add_index_key(record: Value, field: &str, index: u64) -> Result<Value> {
+    let Value::Object(mut fields) = record else {
+        return Ok(record);
+    };
+    if fields.contains_key(field) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--index-key field '{}' collides with another field of record {}",
+            field, index
+        ))
+        .into());
+    }
+
+    // Rebuild the object so the number comes first
+    let mut numbered = serde_json::Map::with_capacity(fields.len() + 1);
+    numbered.insert(field.to_string(), Value::from(index));
+    numbered.append(&mut fields);
+    Ok(Value::Object(numbered))
+}
+
+/// Path of each nested column's value in the record (--nested), by flat output key
+/// 
+/// Only columns whose header contains the --nested-delimiter are listed.
+type NestPaths = HashMap<String, Vec<String>>;
+
+/// Works out where --nested puts each column and rejects ambiguous structures
+/// 
+/// A header containing the delimiter is split into its parts, each normalized
+/// like a header ("Address|Postal Code" -> ["address", "postal_code"]). Other
+/// columns, and columns renamed by --header-map, stay at their key.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `sheets` - The prepared sheets, whose columns all go into the output
+/// 
+/// # Errors
+/// - Returns error if a header has an empty part ("address..city")
+/// - Returns error if two columns end up at the same path
+/// - Returns error if a path runs through another column's value ("address" next to "address.city")
+fn nest_paths<'a>(args: &Args, sheets: impl Iterator<Item = &'a PreparedSheet>) -> Result<NestPaths> {
+    let delimiter = args.nested_delimiter;
+    // Header text is cleaned as prepare_sheet() cleans it (--normalize-unicode, --split-case)
+    let opts = convert_options(args);
+    let normalize = |part: &str| normalize_column_name(&clean_header_text(part, &opts));
+
+    // (header, flat key, path) of every distinct column of every sheet
+    let mut columns: Vec<(&str, &str, Vec<String>)> = Vec::new();
+    for sheet in sheets {
+        for (original, key) in sheet.original_headers.iter().zip(&sheet.headers) {
+            let renamed = args
+                .header_map
+                .as_ref()
+                .is_some_and(|map| map.contains_key(&normalize(original)));
+            let path = if original.contains(delimiter) && !renamed {
+                let parts: Vec<String> = original.split(delimiter).map(normalize).collect();
+                if parts.iter().any(String::is_empty) {
+                    return Err(ConversionError::InvalidColumns(format!(
+                        "--nested: header '{}' has an empty part between '{}' delimiters",
+                        original, delimiter
+                    ))
+                    .into());
+                }
+                parts
+            } else {
+                vec![key.clone()]
+            };
+            match columns.iter().find(|(_, other_key, _)| *other_key == key.as_str()) {
+                Some((_, _, other_path)) if *other_path == path => {} // Same column in another sheet
+                Some((other, _, _)) => {
+                    return Err(ConversionError::InvalidColumns(format!(
+                        "--nested: headers '{}' and '{}' give the key '{}' but nest differently",
+                        other, original, key
+                    ))
+                    .into());
+                }
+                None => columns.push((original.as_str(), key.as_str(), path)),
+            }
+        }
+    }
+
+    // A value and an object can't share a key, nor can two values
+    for (position, (header, _, path)) in columns.iter().enumerate() {
+        for (other, _, other_path) in &columns[position + 1..] {
+            let (shorter, longer) = if path.len() <= other_path.len() { (path, other_path) } else { (other_path, path) };
+            if longer.starts_with(shorter) {
+                let shown = |path: &[String]| path.join(&delimiter.to_string());
+                let problem = if shorter.len() == longer.len() {
+                    format!("both go to '{}'", shown(path))
+                } else {
+                    format!("'{}' holds a value where '{}' needs an object", shown(shorter), shown(longer))
+                };
+                return Err(ConversionError::InvalidColumns(format!(
+                    "--nested: headers '{}' and '{}' are ambiguous: {}",
+                    header, other, problem
+                ))
+                .into());
+            }
+        }
+    }
+
+    Ok(columns
+        .into_iter()
+        .filter(|(_, _, path)| path.len() > 1)
+        .map(|(_, key, path)| (key.to_string(), path))
+        .collect())
+}
+
+/// Moves the values of nested columns into objects along their --nested path
+/// 
+/// Keys without a path (other columns, --with-sheet-name, --index-key, ...) stay
+/// where they are; an object takes the place of the first of its columns.
+/// 
+/// # Errors
+/// Returns error if a path runs into a field that isn't part of it, e.g. an
+/// --index-key "id" next to an "id.source" column
+/// 
+/// # Example
+/// {"id": 1, "address.city": "Bangkok", "address.zip": "10110"}
+/// -> {"id": 1, "address": {"city": "Bangkok", "zip": "10110"}}
+fn nest_record(record: Value, paths: &NestPaths) -> Result<Value> {
+    let Value::Object(fields) = record else {
+        return Ok(record);
+    };
+    let mut nested = serde_json::Map::with_capacity(fields.len());
+    for (key, value) in fields {
+        let Some((last, parents)) = paths.get(&key).and_then(|path| path.split_last()) else {
+            if nested.contains_key(&key) {
+                anyhow::bail!("--nested: field '{}' collides with a nested object of the same name", key);
+            }
+            nested.insert(key, value);
+            continue;
+        };
+        let mut target = &mut nested;
+        for part in parents {
+            let entry = target
+                .entry(part.clone())
+                .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            let Value::Object(object) = entry else {
+                anyhow::bail!("--nested: field '{}' collides with the nested column '{}'", part, key);
+            };
+            target = object;
+        }
+        target.insert(last.clone(), value);
+    }
+    Ok(Value::Object(nested))
+}
+
+/// Collects the keys that --key-style writes differently, over all sheets
+/// 
+/// The parts of --nested paths are styled too, so nested objects get the same
+/// style as the top-level keys.
+/// 
+/// # Returns
+/// Key -> written key, empty with the default snake_case style
+fn key_renames<'a>(
+    args: &Args,
+    sheets: impl Iterator<Item = &'a PreparedSheet>,
+    paths: Option<&NestPaths>,
+) -> HashMap<String, String> {
+    let mut renames = HashMap::new();
+    for sheet in sheets {
+        for (header, key) in sheet.headers.iter().zip(&sheet.keys).filter(|(header, key)| header != key) {
+            renames.insert(header.clone(), key.clone());
+        }
+    }
+    for part in paths.into_iter().flat_map(NestPaths::values).flatten() {
+        let styled = args.key_style.apply(part);
+        if styled != *part {
+            renames.insert(part.clone(), styled);
+        }
+    }
+    renames
+}
+
+/// Gives a record its written shape: nested along the --nested paths, then
+/// keyed in --key-style
+/// 
+/// Done last, so everything before (--sort-by, --group-by, ...) sees the flat
+/// snake_case keys the options name.
+fn shape_record(record: Value, paths: Option<&NestPaths>, renames: &HashMap<String, String>) -> Result<Value> {
+    let record = match paths {
+        Some(paths) => nest_record(record, paths)?,
+        None => record,
+    };
+    Ok(if renames.is_empty() { record } else { rename_keys(record, renames) })
+}
+
+/// Parses the --columns-regex pattern
+/// 
+/// # Errors
+/// Returns error text if the pattern isn't a valid regex
+fn parse_columns_regex(pattern: &str) -> std::result::Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("invalid regex '{}': {}", pattern, e))
+}
+
+/// Checks the --numeric-columns, --string-columns, --bool-columns and --date-columns lists
+/// 
+/// # Errors
+/// - Returns error if a listed column isn't among the selected columns
+/// - Returns error if a column is in more than one of the lists
+fn check_typed_columns(opts: &ConvertOptions, headers: &[String]) -> Result<()> {
+    let typed_columns = [
+        ("--numeric-columns", &opts.numeric_columns),
+        ("--string-columns", &opts.string_columns),
+        ("--bool-columns", &opts.bool_columns),
+        ("--date-columns", &opts.date_columns),
+    ];
+    for (option, columns) in typed_columns {
+        if let Some(column) = columns.iter().find(|column| !headers.contains(column)) {
+            return Err(ConversionError::InvalidColumns(format!(
+                "{} column '{}' is not among the selected columns",
+                option, column
+            ))
+            .into());
+        }
+    }
+    // A column can only be given one type
+    for (i, (option, columns)) in typed_columns.iter().enumerate() {
+        for (other_option, other_columns) in &typed_columns[i + 1..] {
+            if let Some(column) = columns.iter().find(|column| other_columns.contains(column)) {
+                return Err(ConversionError::InvalidColumns(format!(
+                    "Column '{}' is in both {} and {}",
+                    column, option, other_option
+                ))
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Splits a comma-separated column list (--numeric-columns, ...) into normalized header names
+/// 
+/// # Examples
+/// - Some("Price, Unit Cost") -> ["price", "unit_cost"]
+/// - Some("a,,b") -> ["a", "b"]
+/// - None -> []
+fn parse_column_list(list: &Option<String>) -> Vec<String> {
+    list.iter()
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(normalize_column_name)
+        .collect()
+}
+
+/// Collects the value-conversion options from the command-line arguments
+fn convert_options(args: &Args) -> ConvertOptions {
+    ConvertOptions {
+        infer_types: args.infer_types,
+        strip_currency: args.strip_currency,
+        parse_numeric_text: args.decimal_separator.is_some(),
+        locale: args.decimal_separator.map_or(args.locale, DecimalSeparator::locale),
+        replace_rules: args.replace.clone(),
+        trim: args.trim,
+        max_cell_length: args.max_cell_length,
+        truncate_marker: args.truncate_marker.clone().unwrap_or_default(),
+        dates: args.dates,
+        date1904: false, // Workbook property - filled in once the file is opened
+        split_rules: args.split_cells.clone(),
+        defaults: args.defaults.clone().unwrap_or_default(),
+        conditions: args.where_clause.clone().map(|w| w.0).unwrap_or_default(),
+        split_empty: args.split_empty,
+        sort_keys: args.sort_keys,
+        empty: if args.compact_empty { EmptyMode::Omit } else { EmptyMode::Keep },
+        normalize_values: args.normalize_unicode.filter(|_| args.normalize_unicode_values),
+        sample_size: args.sample_size,
+        strict_types: args.strict_types || args.strict,
+        strict_numeric: args.strict,
+        float_precision: args.float_precision,
+        numeric_columns: parse_column_list(&args.numeric_columns),
+        string_columns: parse_column_list(&args.string_columns),
+        bool_columns: parse_column_list(&args.bool_columns),
+        strict_bool: args.strict_bool_columns || args.strict,
+        date_columns: parse_column_list(&args.date_columns),
+        date_input_format: args.date_input_format.clone().unwrap_or_else(|| "%Y-%m-%d".to_string()),
+        // The header options, which prepare_sheet() applies through the library's header functions
+        columns: Vec::new(), // --columns also takes column numbers, resolved per sheet
+        key_style: args.key_style,
+        split_case: args.split_case,
+        normalize_keys: args.normalize_unicode,
+        header_map: args.header_map.clone().unwrap_or_default(),
+        key_prefix: args.key_prefix.clone(),
+        numeric_header_prefix: args.numeric_header_prefix.clone(),
+        include_blank_columns: args.include_blank_columns,
+        drop_empty_records: args.drop_empty_records,
+    }
+}
+
+/// Validates a single 1-based visible column number
+/// 
+/// # Errors
+/// - Returns error if the number is 0
+/// - Returns error if the number exceeds the count of visible columns
+fn validate_column_number(n: usize, visible_count: usize) -> Result<usize> {
+    if n == 0 {
+        anyhow::bail!("Column numbers must be greater than 0")
+    }
+    if n > visible_count {
+        anyhow::bail!(
+            "Column number {} exceeds visible column count ({})",
+            n,
+            visible_count
+        )
+    }
+    Ok(n)
+}
+
+/// Parses one item of a column list: a single number ("3"), a range ("5-8")
+/// or an open-ended range ("5-", through the last visible column)
+/// 
+/// # Returns
+/// The 1-based visible column numbers covered by the item, in order
+/// 
+/// # Errors
+/// - Returns error if a bound isn't a valid number or is out of range
+/// - Returns error if the range start is greater than its end
+fn parse_column_item(item: &str, visible_count: usize) -> Result<Vec<usize>> {
+    let parse_number = |s: &str| -> Result<usize> {
+        let n = s
+            .trim() // Remove whitespace
+            .parse::<usize>() // Parse string to number
+            .context(format!("Invalid column number '{}'", s.trim()))?;
+        validate_column_number(n, visible_count)
+    };
+
+    match item.split_once('-') {
+        // Single column number
+        None => Ok(vec![parse_number(item)?]),
+        // Range: "start-end" or open-ended "start-"
+        Some((start, end)) => {
+            let start = parse_number(start)?;
+            let end = if end.trim().is_empty() {
+                visible_count // Open end runs through the last visible column
+            } else {
+                parse_number(end)?
+            };
+            if start > end {
+                anyhow::bail!("Invalid column range '{}': start is greater than end", item.trim())
+            }
+            Ok((start..=end).collect())
+        }
+    }
+}
+
+/// Parses user-specified column numbers and maps them to actual visible column indices
+/// 
+/// Users specify columns using 1-based numbering (1, 2, 3, ...)
+/// This function converts those to 0-based indices and validates them.
+/// Ranges ("5-8") and open-ended ranges ("5-") are accepted alongside single
+/// numbers; a column listed more than once is only included the first time.
+/// An item that isn't a valid number or range is looked up as a header name,
+/// compared in its normalized form (so "First Name" finds "first_name").
+/// 
+/// # Arguments
+/// * `columns_str` - Comma-separated column numbers, ranges and header names (e.g., "1,3,5-8,Email")
+/// * `visible_indices` - Vector of actual column indices that have non-empty headers
+/// * `visible_headers` - Normalized header names of the visible columns, in the same order
+/// 
+/// # Returns
+/// A Result containing a vector of actual column indices to use
+/// 
+/// # Errors
+/// - Returns error if column number is 0 or negative
+/// - Returns error if column number exceeds the count of visible columns
+/// - Returns error if an item is neither a valid number/range nor a header name
+/// - Returns error if a range's start is greater than its end
+/// 
+/// # Example
+/// If visible_indices = [0, 2, 5, 7] and columns_str = "1,3-"
+/// Returns: Ok([0, 5, 7]) - maps user's 1st, 3rd and 4th visible columns to actual indices
+fn parse_visible_column_numbers(
+    columns_str: &str,
+    visible_indices: &[usize],
+    visible_headers: &[String],
+) -> Result<Vec<usize>> {
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+
+    for item in columns_str.split(',') {
+        let numbers = match parse_column_item(item, visible_indices.len()) {
+            Ok(numbers) => numbers,
+            // Not a usable number or range - try it as a header name
+            Err(err) => {
+                let name = normalize_column_name(item);
+                let numeric = item.trim().chars().all(|c| c.is_ascii_digit() || c == '-' || c.is_whitespace());
+                match visible_headers.iter().position(|header| *header == name) {
+                    Some(position) if !name.is_empty() => vec![position + 1],
+                    // Out-of-range numbers keep their own, more specific error
+                    _ if numeric => return Err(err),
+                    _ => anyhow::bail!("Unknown column '{}': not a column number, range or header name", item.trim()),
+                }
+            }
+        };
+        for n in numbers {
+            // Overlapping items (e.g. "1-5,3") keep only the first occurrence
+            if seen.insert(n) {
+                // Convert 1-based user input to 0-based array index
+                // Then map to actual column index in the Excel sheet
+                result.push(visible_indices[n - 1]);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Finds the visible columns whose header matches --columns-regex
+/// 
+/// A column matches if either its header as written (trimmed) or its normalized
+/// key matches, so "^metric_\d+$" finds a column headed "Metric 2021".
+/// 
+/// # Arguments
+/// * `pattern` - The --columns-regex pattern
+/// * `header_row` - The (combined) header row
+/// * `visible_indices` - Actual column indices of the visible columns
+/// 
+/// # Returns
+/// Actual column indices of the matching columns, in sheet order
+fn match_columns_regex(pattern: &Regex, header_row: &[calamine::Data], visible_indices: &[usize]) -> Vec<usize> {
+    visible_indices
+        .iter()
+        .copied()
+        .filter(|&index| {
+            let raw = header_row.get(index).map(|cell| cell.to_string()).unwrap_or_default();
+            let raw = raw.trim();
+            pattern.is_match(raw) || pattern.is_match(&normalize_column_name(raw))
+        })
+        .collect()
+}
+
+/// Suffixes headers that occur more than once with their visible column number
+/// 
+/// All occurrences are renamed, not just the later ones, so a key depends only on
+/// the column's position and not on which duplicate comes first.
+/// 
+/// # Arguments
+/// * `headers` - Normalized headers of the selected columns, renamed in place
+/// * `column_indices` - Actual column indices of the headers
+/// * `visible_indices` - All visible columns, in the order --columns numbers them
+/// 
+/// # Example
+/// ["name", "email", "name"] at visible columns 1, 2, 4 -> ["name_c1", "email", "name_c4"]
+fn suffix_duplicate_headers(headers: &mut [String], column_indices: &[usize], visible_indices: &[usize]) {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for header in headers.iter() {
+        *counts.entry(header.clone()).or_default() += 1;
+    }
+
+    for (header, col_idx) in headers.iter_mut().zip(column_indices) {
+        if counts[header.as_str()] > 1 {
+            // 1-based position among the visible columns, as used by --columns
+            let number = visible_indices
+                .iter()
+                .position(|idx| idx == col_idx)
+                .unwrap_or(*col_idx)
+                + 1;
+            *header = format!("{}_c{}", header, number);
+        }
+    }
+}
+
+/// Finds keys that are equal when case is ignored (--dedup-headers-case-insensitive)
+/// 
+/// # Returns
+/// The positions of each group of colliding keys, in column order
+/// 
+/// # Example
+/// ["status", "name", "Status", "STATUS"] -> [[0, 2, 3]]
+fn case_insensitive_collisions(headers: &[String]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for (position, header) in headers.iter().enumerate() {
+        let folded = header.to_lowercase();
+        match groups.iter_mut().find(|(key, _)| *key == folded) {
+            Some((_, positions)) => positions.push(position),
+            None => groups.push((folded, vec![position])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, positions)| positions)
+        .filter(|positions| positions.len() > 1)
+        .collect()
+}
+
+/// Renames every key after the first of a collision group with "_2", "_3", ...
+/// 
+/// A suffix already taken by another key (ignoring case) is skipped.
+/// 
+/// # Example
+/// ["status", "Status", "status_2"] with group [0, 1] -> ["status", "Status_3", "status_2"]
+fn suffix_case_collisions(headers: &mut [String], group: &[usize]) {
+    for (n, &position) in group.iter().enumerate().skip(1) {
+        let mut number = n + 1;
+        let key = loop {
+            let candidate = format!("{}_{}", headers[position], number);
+            if !headers.iter().any(|header| header.to_lowercase() == candidate.to_lowercase()) {
+                break candidate;
+            }
+            number += 1;
+        };
+        headers[position] = key;
+    }
+}
+
+/// Checks whether a key is a plain identifier: ^[a-z][a-z0-9_]*$
+/// 
+/// # Examples
+/// - "first_name" -> true
+/// - "3d_model" -> false (starts with a digit)
+/// - "_sheet" -> false
+fn is_identifier_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Checks that every column listed in --required-columns is among the headers
+/// 
+/// Names match a header as written or in normalized form, so "First Name"
+/// finds "first_name".
+/// 
+/// # Arguments
+/// * `required` - Comma-separated column names
+/// * `headers` - Resolved output keys of the selected columns
+/// 
+/// # Errors
+/// Returns error listing all missing columns and the available headers
+fn check_required_columns(required: &str, headers: &[String]) -> Result<()> {
+    let missing: Vec<&str> = required
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter(|name| {
+            let normalized = normalize_column_name(name);
+            !headers.iter().any(|header| header == name || *header == normalized)
+        })
+        .collect();
+
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Missing required column(s): {}. Available headers: {}",
+            missing.join(", "),
+            headers.join(", ")
+        )
+    }
+    Ok(())
+}
+
+/// Maximum number of ragged rows listed individually before summarizing the rest
+const RAGGED_ROW_REPORT_LIMIT: usize = 10;
+
+/// Returns the number of cells up to and including the last non-empty one
+/// 
+/// Cells holding only whitespace count as empty, matching how headers are read.
+fn effective_width(row: &[calamine::Data]) -> usize {
+    row.iter()
+        .rposition(|cell| !cell.to_string().trim().is_empty())
+        .map_or(0, |idx| idx + 1)
+}
+
+/// Finds data rows with values beyond the header's last column
+/// 
+/// Only rows wider than the header are reported: calamine ranges are rectangular,
+/// so a row whose last cells are blank is indistinguishable from one that is
+/// legitimately sparse, and narrower rows are normal. A value under a column
+/// without a header, on the other hand, would be silently dropped.
+/// 
+/// # Arguments
+/// * `range` - Sheet range including the header row(s)
+/// * `header_rows` - Number of header rows at the top of the range
+/// * `header_width` - Effective width of the (combined) header
+/// 
+/// # Returns
+/// (1-based sheet row number, effective width) for each row wider than the header
+fn find_ragged_rows(range: &calamine::Range<calamine::Data>, header_rows: usize, header_width: usize) -> Vec<(u32, usize)> {
+    let first_data_row = range.start().map_or(0, |(row, _)| row) + header_rows as u32;
+    range
+        .rows()
+        .skip(header_rows)
+        .enumerate()
+        .filter_map(|(row_idx, row)| {
+            let width = effective_width(row);
+            (width > header_width).then_some((first_data_row + row_idx as u32 + 1, width))
+        })
+        .collect()
+}
+
+/// Describes ragged rows for a warning or error message, listing the first few
+/// 
+/// # Arguments
+/// * `ragged` - Ragged rows as returned by `find_ragged_rows`
+/// * `header_width` - Effective width of the header
+fn describe_ragged_rows(ragged: &[(u32, usize)], header_width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = ragged
+        .iter()
+        .take(RAGGED_ROW_REPORT_LIMIT)
+        .map(|(row, width)| format!("row {} has {} cells, header has {}", row, width, header_width))
+        .collect();
+    if ragged.len() > RAGGED_ROW_REPORT_LIMIT {
+        lines.push(format!("... and {} more", ragged.len() - RAGGED_ROW_REPORT_LIMIT));
+    }
+    lines
+}
+
+/// Warns about rows with values beyond the header's last column, or fails with --strict-width/--strict
+/// 
+/// Such values usually mean shifted cells, and would be left out of the output.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments (--strict-width, --strict, --quiet)
+/// * `sheet_name` - Name of the sheet (for messages)
+/// * `range` - Sheet range including the header row(s)
+/// * `header_row` - The (combined) header row
+/// 
+/// # Errors
+/// Returns an InvalidColumns error listing the rows if --strict-width or --strict is given
+fn check_row_widths(
+    args: &Args,
+    sheet_name: &str,
+    range: &calamine::Range<calamine::Data>,
+    header_row: &[calamine::Data],
+) -> Result<()> {
+    let header_width = effective_width(header_row);
+    report_ragged_rows(args, sheet_name, &find_ragged_rows(range, args.header_rows, header_width), header_width)
+}
+
+/// Reports rows found by find_ragged_rows(): a warning, or an error with --strict-width/--strict
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments (--strict-width, --strict, --quiet)
+/// * `sheet_name` - Name of the sheet (for messages)
+/// * `ragged` - (1-based sheet row number, effective width) of each row wider than the header
+/// * `header_width` - Effective width of the header
+/// 
+/// # Errors
+/// Returns an InvalidColumns error listing the rows if --strict-width or --strict is given
+fn report_ragged_rows(args: &Args, sheet_name: &str, ragged: &[(u32, usize)], header_width: usize) -> Result<()> {
+    if ragged.is_empty() {
+        return Ok(());
+    }
+    let details = describe_ragged_rows(ragged, header_width);
+    if args.strict_width || args.strict {
+        return Err(ConversionError::InvalidColumns(format!(
+            "{} row(s) in sheet '{}' have values beyond the header: {}",
+            ragged.len(),
+            sheet_name,
+            details.join("; ")
+        ))
+        .into());
+    }
+    if !args.quiet {
+        eprintln!("Warning: {} row(s) in sheet '{}' have values beyond the header", ragged.len(), sheet_name);
+        for line in details {
+            eprintln!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Checks the keys of a sheet for --strict: no two columns may share a key and
+/// no column may be keyed "column_N" because it has no usable header
+/// 
+/// # Arguments
+/// * `sheet_name` - Name of the sheet (for error messages)
+/// * `header_row` - The (combined) header row
+/// * `headers` - Final keys of the selected columns
+/// * `column_indices` - Column indices of the selected columns, relative to the range
+/// * `range_start` - Absolute (row, column) where the range starts, for column letters
+/// 
+/// # Errors
+/// Returns an InvalidColumns error naming the columns by letter
+fn check_strict_headers(
+    sheet_name: &str,
+    header_row: &[calamine::Data],
+    headers: &[String],
+    column_indices: &[usize],
+    range_start: Option<(u32, u32)>,
+) -> Result<()> {
+    let first_col = range_start.map_or(0, |(_, col)| col as usize);
+    let letters = |index: usize| column_letters(first_col + index);
+
+    for (position, (key, &index)) in headers.iter().zip(column_indices).enumerate() {
+        // A key used by an earlier column would overwrite its values
+        if let Some(earlier) = headers[..position].iter().position(|other| other == key) {
+            return Err(ConversionError::InvalidColumns(format!(
+                "Columns {} and {} of sheet '{}' both give the key '{}' (--strict). \
+                 Use --on-duplicate column-number or rename one with --header-map",
+                letters(column_indices[earlier]),
+                letters(index),
+                sheet_name,
+                key
+            ))
+            .into());
+        }
+        // A blank (or unusable) header only got a made-up name
+        let header_key = header_row
+            .get(index)
+            .map(|cell| normalize_column_name(&cell.to_string()))
+            .unwrap_or_default();
+        if header_key.is_empty() && *key == format!("column_{}", index + 1) {
+            return Err(ConversionError::InvalidColumns(format!(
+                "Column {} of sheet '{}' has no header and would be keyed '{}' (--strict). \
+                 Give it a header or rename the key with --header-map",
+                letters(index),
+                sheet_name,
+                key
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a user-supplied sheet name against the sheets in a workbook
+/// 
+/// An exact match always wins. Otherwise the names are compared
+/// case-insensitively with surrounding whitespace trimmed, so "sheet1 "
+/// finds a tab called "Sheet1".
+/// 
+/// # Arguments
+/// * `sheet_names` - Names of all sheets in the workbook
+/// * `requested` - Sheet name given by the user
+/// 
+/// # Returns
+/// The actual sheet name as stored in the workbook
+/// 
+/// # Errors
+/// - Returns error if no sheet matches, listing the available sheets
+/// - Returns error if several sheets match the relaxed comparison, listing them
+fn resolve_sheet_name(sheet_names: &[String], requested: &str) -> Result<String> {
+    // Exact match - nothing to resolve
+    if sheet_names.iter().any(|name| name == requested) {
+        return Ok(requested.to_string());
+    }
+
+    // Fall back to a case-insensitive, whitespace-trimmed comparison
+    let wanted = requested.trim().to_lowercase();
+    let matches: Vec<&String> = sheet_names
+        .iter()
+        .filter(|name| name.trim().to_lowercase() == wanted)
+        .collect();
+
+    match matches.as_slice() {
+        [single] => Ok((*single).clone()),
+        [] => Err(ConversionError::SheetNotFound(format!(
+            "Sheet '{}' not found. Available sheets: {}",
+            requested,
+            sheet_names.join(", ")
+        ))
+        .into()),
+        _ => Err(ConversionError::SheetNotFound(format!(
+            "Sheet '{}' is ambiguous, it matches: {}",
+            requested,
+            matches
+                .iter()
+                .map(|name| format!("'{}'", name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+        .into()),
+    }
+}
+
+/// Resolves a 1-based sheet position to the sheet's name
+/// 
+/// # Arguments
+/// * `sheet_names` - Names of all sheets in the workbook, in workbook order
+/// * `index` - 1-based sheet position given by the user
+/// 
+/// # Returns
+/// The name of the sheet at that position
+/// 
+/// # Errors
+/// - Returns error if the index is 0 or exceeds the number of sheets
+fn resolve_sheet_index(sheet_names: &[String], index: usize) -> Result<String> {
+    if index == 0 || index > sheet_names.len() {
+        return Err(ConversionError::SheetNotFound(format!(
+            "Sheet index {} is out of range, the workbook has {} sheet(s)",
+            index,
+            sheet_names.len()
+        ))
+        .into());
+    }
+    // Convert 1-based user input to 0-based array index
+    Ok(sheet_names[index - 1].clone())
+}
+
+/// How the user chose which sheet to convert
+#[derive(Debug, Clone)]
+enum SheetSelector {
+    /// Sheet name (matched via resolve_sheet_name())
+    Name(String),
+    /// 1-based sheet position (resolved via resolve_sheet_index())
+    Index(usize),
+    /// Named Excel Table (read via read_excel_table())
+    Table(String),
+}
+
+impl fmt::Display for SheetSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SheetSelector::Name(name) => write!(f, "sheet '{}'", name),
+            SheetSelector::Index(index) => write!(f, "sheet #{}", index),
+            SheetSelector::Table(table) => write!(f, "table '{}'", table),
+        }
+    }
+}
+
+impl SheetSelector {
+    /// Builds the selector from the positional sheet name or --sheet-index
+    /// 
+    /// The sheet of a sheet-qualified --range ("Details!A1:C50") takes precedence over both.
+    fn from_args(args: &Args) -> Result<Self> {
+        if let Some(sheet) = args.range.as_ref().and_then(|range| range.sheet.as_ref()) {
+            return Ok(SheetSelector::Name(sheet.clone()));
+        }
+        match (&args.sheet, args.sheet_index, &args.table) {
+            (Some(name), _, _) => Ok(SheetSelector::Name(name.clone())),
+            (None, Some(index), _) => Ok(SheetSelector::Index(index)),
+            (None, None, Some(table)) => Ok(SheetSelector::Table(table.clone())),
+            (None, None, None) => {
+                anyhow::bail!("Either a sheet name, --sheet-index or --table must be specified")
+            }
+        }
+    }
+}
+
+/// Reads a named Excel Table (ListObject) as a Range with its header row on top
+/// 
+/// The table's column names become the first row, followed by the table body,
+/// so the rest of the pipeline can treat it exactly like a sheet.
+/// 
+/// # Arguments
+/// * `workbook` - The opened workbook
+/// * `table_name` - Name of the table to read
+/// 
+/// # Returns
+/// A Result containing the table's sheet name and its header + body as a Range
+/// 
+/// # Errors
+/// - Returns error if the table definitions can't be read
+/// - Returns error if no table has this name, listing the available tables
+fn read_excel_table<RS: Read + Seek>(
+    workbook: &mut Xlsx<RS>,
+    table_name: &str,
+) -> Result<(String, calamine::Range<calamine::Data>)> {
+    workbook
+        .load_tables()
+        .context(ConversionError::FileOpen("Failed to read table definitions".to_string()))?;
+
+    // Check the name first so we can list the alternatives
+    let table_names: Vec<String> = workbook.table_names().into_iter().cloned().collect();
+    if !table_names.iter().any(|name| name == table_name) {
+        let available = if table_names.is_empty() {
+            "(none)".to_string()
+        } else {
+            table_names.join(", ")
+        };
+        return Err(ConversionError::SheetNotFound(format!(
+            "Table '{}' not found. Available tables: {}",
+            table_name, available
+        ))
+        .into());
+    }
+
+    let table = workbook
+        .table_by_name(table_name)
+        .context(ConversionError::SheetNotFound(format!("Failed to read table '{}'", table_name)))?;
+
+    // Rebuild a Range at the table's position on the sheet (so cell
+    // coordinates stay absolute): the column names go in the row above the body
+    let columns = table.columns();
+    let body = table.data();
+    let width = columns.len().max(body.width()).max(1) as u32;
+    let (body_row, first_col) = body.start().unwrap_or((1, 0));
+    let header_row = body_row.saturating_sub(1);
+    let mut range = calamine::Range::new(
+        (header_row, first_col),
+        (header_row + body.height() as u32, first_col + width - 1),
+    );
+    for (col, name) in columns.iter().enumerate() {
+        range.set_value((header_row, first_col + col as u32), calamine::Data::String(name.clone()));
+    }
+    for (row_idx, row) in body.rows().enumerate() {
+        for (col, cell) in row.iter().enumerate() {
+            range.set_value((body_row + row_idx as u32, first_col + col as u32), cell.clone());
+        }
+    }
+
+    Ok((table.sheet_name().to_string(), range))
+}
+
+/// Readable and seekable workbook data: a file on disk or a zip entry held in memory
+trait WorkbookInput: Read + Seek {}
+
+impl<T: Read + Seek> WorkbookInput for T {}
+
+/// Opens the input file, or with --zip-entry the named workbook inside it
+/// 
+/// A zip entry is decompressed into memory, so nothing is extracted to disk.
+/// 
+/// # Arguments
+/// * `file` - Path to the Excel file, or to the zip archive holding it
+/// * `zip_entry` - Name of the workbook inside the archive (--zip-entry)
+/// 
+/// # Errors
+/// - Returns error if the file can't be opened or isn't a zip archive
+/// - Returns error if the archive has no such entry, listing the entries it has
+fn open_input(file: &Path, zip_entry: Option<&str>) -> Result<Box<dyn WorkbookInput>> {
+    let handle = File::open(file)
+        .context(ConversionError::FileOpen(format!("Failed to open Excel file: {:?}", file)))?;
+    let Some(entry_name) = zip_entry else {
+        return Ok(Box::new(BufReader::new(handle)));
+    };
+
+    let mut archive = zip::ZipArchive::new(handle)
+        .context(ConversionError::FileOpen(format!("Not a valid zip archive: {:?}", file)))?;
+    if archive.index_for_name(entry_name).is_none() {
+        // List the files (not directories) so the right name is easy to spot
+        let mut entries: Vec<&str> = archive.file_names().filter(|name| !name.ends_with('/')).collect();
+        entries.sort_unstable();
+        let available = if entries.is_empty() {
+            "(none)".to_string()
+        } else {
+            entries.join(", ")
+        };
+        return Err(ConversionError::FileOpen(format!(
+            "Entry '{}' not found in {:?}. Available entries: {}",
+            entry_name, file, available
+        ))
+        .into());
+    }
+    let mut entry = archive.by_name(entry_name).context(ConversionError::FileOpen(format!(
+        "Failed to read entry '{}' from {:?}",
+        entry_name, file
+    )))?;
+
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut data).context(ConversionError::FileOpen(format!(
+        "Failed to read entry '{}' from {:?}",
+        entry_name, file
+    )))?;
+    Ok(Box::new(Cursor::new(data)))
+}
+
+/// Opens the input as a workbook (see open_input())
+/// 
+/// .xlsb files are read as binary workbooks, everything else as xlsx.
+fn open_workbook_input(file: &Path, zip_entry: Option<&str>) -> Result<Sheets<Box<dyn WorkbookInput>>> {
+    let input = open_input(file, zip_entry)?;
+    let name = match zip_entry {
+        Some(entry) => format!("{:?}!{}", file, entry),
+        None => format!("{:?}", file),
+    };
+    let context = || ConversionError::FileOpen(format!("Failed to open Excel file: {}", name));
+    if is_xlsb(file, zip_entry) {
+        Ok(Sheets::Xlsb(open_workbook_from_rs::<Xlsb<_>, _>(input).with_context(context)?))
+    } else {
+        Ok(Sheets::Xlsx(open_workbook_from_rs::<Xlsx<_>, _>(input).with_context(context)?))
+    }
+}
+
+/// Whether the workbook (the --zip-entry if given, else the file) is a binary .xlsb workbook
+fn is_xlsb(file: &Path, zip_entry: Option<&str>) -> bool {
+    let name = zip_entry.map_or(file, Path::new);
+    name.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("xlsb"))
+}
+
+/// Splits an "archive.zip!inner.xlsx" input path into the archive and the entry name
+/// 
+/// Only the first "!" following a ".zip" extension counts, so other paths
+/// containing "!" are left alone.
+/// 
+/// # Examples
+/// - "monthly.zip!2024/march.xlsx" -> Some(("monthly.zip", "2024/march.xlsx"))
+/// - "report!.xlsx" -> None
+fn split_zip_path(path: &Path) -> Option<(PathBuf, String)> {
+    let text = path.to_str()?;
+    let split_at = text.to_ascii_lowercase().find(".zip!")? + ".zip".len();
+    let (archive, entry) = (&text[..split_at], &text[split_at + 1..]);
+    if entry.is_empty() {
+        return None;
+    }
+    Some((PathBuf::from(archive), entry.to_string()))
+}
+
+/// Opens an Excel file and reads a specific worksheet
+/// 
+/// # Arguments
+/// * `file` - Path to the Excel file (.xlsx or .xlsb)
+/// * `zip_entry` - Workbook inside `file` to read instead, if `file` is a zip archive
+/// * `sheet` - Which worksheet to read: by name, by position, or a named table
+/// 
+/// # Returns
+/// A Result containing the actual sheet name and the Range of cells from that worksheet
+/// (for a table, the sheet it lives on and the table's header + body)
+/// 
+/// # Errors
+/// - Returns error if the file cannot be opened
+/// - Returns error if the specified sheet name doesn't exist in the workbook
+/// - Returns error if the specified sheet index is out of range
+fn read_excel_sheet(
+    file: &Path,
+    zip_entry: Option<&str>,
+    sheet: &SheetSelector,
+    formulas: FormulaMode,
+) -> Result<(String, calamine::Range<calamine::Data>)> {
+    // Open the Excel workbook
+    let mut workbook = open_workbook_input(file, zip_entry)?;
+
+    // Find the sheet by name (tolerating case and whitespace) or by position
+    let sheet_names = workbook.sheet_names();
+    let sheet_name = match sheet {
+        SheetSelector::Name(name) => resolve_sheet_name(&sheet_names, name)?,
+        SheetSelector::Index(index) => resolve_sheet_index(&sheet_names, *index)?,
+        SheetSelector::Table(table) => {
+            // Table definitions are only read from xlsx (rejected earlier for .xlsb)
+            let Sheets::Xlsx(xlsx) = &mut workbook else {
+                anyhow::bail!("--table needs an .xlsx workbook");
+            };
+            let (sheet_name, mut range) = read_excel_table(xlsx, table)?;
+            if formulas == FormulaMode::Text {
+                overlay_formulas(&mut workbook, &sheet_name, &mut range)?;
+            }
+            return Ok((sheet_name, range));
+        }
+    };
+
+    // Get the specified worksheet range (all cells with data)
+    let mut range = workbook
+        .worksheet_range(&sheet_name)
+        .context(ConversionError::SheetNotFound(format!("Sheet '{}' not found", sheet_name)))?;
+
+    // --formulas text: swap cached results for the formulas that produced them
+    if formulas == FormulaMode::Text {
+        overlay_formulas(&mut workbook, &sheet_name, &mut range)?;
+    }
+
+    Ok((sheet_name, range))
+}
+
+/// A row read with --lazy-read: its absolute (0-based) index and its cells,
+/// starting at the sheet's first column
+type LazyRow = (u32, Vec<calamine::Data>);
+
+/// Data rows of a sheet still being read on a background thread (--lazy-read)
+struct LazyRows {
+    /// Rows in sheet order, taken once by PreparedSheet::records()
+    receiver: Cell<Option<mpsc::Receiver<LazyRow>>>,
+    /// The reading thread, which tells whether the rest of the sheet could be read
+    reader: std::thread::JoinHandle<Result<()>>,
+    /// Last (0-based) row of the sheet's declared dimension, for the progress bar
+    last_row: u32,
+    /// Effective width of the (combined) header, set once the header is known
+    header_width: usize,
+    /// (1-based sheet row number, effective width) of each streamed row wider than
+    /// the header, noted while converting since the rows aren't kept
+    ragged: RefCell<Vec<(u32, usize)>>,
+}
+
+impl LazyRows {
+    /// Waits for the reading thread after the rows were converted
+    /// 
+    /// # Errors
+    /// Returns the error that cut the sheet short, if any
+    fn finish(self) -> Result<()> {
+        drop(self.receiver); // Unblocks the thread if the rows weren't all taken
+        self.reader
+            .join()
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("The thread reading the sheet stopped unexpectedly")))
+    }
+}
+
+/// Opens an Excel file and starts reading a worksheet row by row (--lazy-read)
+/// 
+/// Instead of loading the whole sheet like read_excel_sheet(), the cells are
+/// read on a background thread with calamine's worksheet_cells_reader() and
+/// handed over through a bounded channel, so only LAZY_ROW_BUFFER rows are held
+/// at a time. Only the header row(s) are read before this returns.
+/// 
+/// The sheet's first column is taken from its declared dimension (the
+/// `<dimension>` element Excel keeps up to date) rather than from the cells.
+/// 
+/// # Arguments
+/// * `file` - Path to the Excel file (.xlsx or .xlsb)
+/// * `zip_entry` - Workbook inside `file` to read instead, if `file` is a zip archive
+/// * `sheet` - Which worksheet to read: by name or by position (tables aren't supported)
+/// * `header_rows` - Number of header rows to read up front
+/// 
+/// # Returns
+/// The actual sheet name, a Range holding just the header row(s), and the data rows
+/// still to come (None if the sheet ended within the header)
+/// 
+/// # Errors
+/// - Returns error if the file cannot be opened
+/// - Returns error if the specified sheet doesn't exist in the workbook
+/// - Returns error if the header row(s) can't be read
+fn read_sheet_lazily(
+    file: &Path,
+    zip_entry: Option<&str>,
+    sheet: &SheetSelector,
+    header_rows: usize,
+) -> Result<(String, calamine::Range<calamine::Data>, Option<LazyRows>)> {
+    let (file, zip_entry, sheet) = (file.to_path_buf(), zip_entry.map(str::to_string), sheet.clone());
+    let (sheet_sender, sheet_receiver) = mpsc::channel();
+    let (row_sender, row_receiver) = mpsc::sync_channel(LAZY_ROW_BUFFER);
+    let reader = std::thread::spawn(move || -> Result<()> {
+        let mut workbook = open_workbook_input(&file, zip_entry.as_deref())?;
+        let sheet_names = workbook.sheet_names();
+        let sheet_name = match &sheet {
+            SheetSelector::Name(name) => resolve_sheet_name(&sheet_names, name)?,
+            SheetSelector::Index(index) => resolve_sheet_index(&sheet_names, *index)?,
+            SheetSelector::Table(_) => anyhow::bail!("--lazy-read can't read a --table"),
+        };
+        let context = || ConversionError::SheetNotFound(format!("Failed to read sheet '{}'", sheet_name));
+        match &mut workbook {
+            Sheets::Xlsx(xlsx) => {
+                let mut cells = xlsx.worksheet_cells_reader(&sheet_name).with_context(context)?;
+                let dimensions = cells.dimensions();
+                let _ = sheet_sender.send((sheet_name.clone(), dimensions.start.1, dimensions.end.0));
+                let first_col = dimensions.start.1;
+                send_rows(first_col, || cells.next_cell(), &row_sender).with_context(context)
+            }
+            Sheets::Xlsb(xlsb) => {
+                let mut cells = xlsb.worksheet_cells_reader(&sheet_name).with_context(context)?;
+                let dimensions = cells.dimensions();
+                let _ = sheet_sender.send((sheet_name.clone(), dimensions.start.1, dimensions.end.0));
+                let first_col = dimensions.start.1;
+                send_rows(first_col, || cells.next_cell(), &row_sender).with_context(context)
+            }
+            _ => anyhow::bail!("--lazy-read needs an .xlsx or .xlsb workbook"),
+        }
+    });
+
+    // Nothing arrives if the workbook or sheet couldn't be opened - the thread tells why
+    let new_lazy_rows = |row_receiver, reader, last_row| LazyRows {
+        receiver: Cell::new(Some(row_receiver)),
+        reader,
+        last_row,
+        header_width: 0,
+        ragged: RefCell::new(Vec::new()),
+    };
+    let Ok((sheet_name, first_col, last_row)) = sheet_receiver.recv() else {
+        let lazy = new_lazy_rows(row_receiver, reader, 0);
+        return Err(lazy.finish().err().unwrap_or_else(|| anyhow::anyhow!("Failed to read the sheet")));
+    };
+
+    // Build a range of just the header row(s); it fixes where the data starts and how wide it is
+    let header: Vec<LazyRow> = row_receiver.iter().take(header_rows).collect();
+    let width = header.iter().map(|(_, cells)| cells.len()).max().unwrap_or(0);
+    let range = match (header.first(), header.last()) {
+        (Some(&(first_row, _)), Some(&(last_row, _))) if width > 0 => {
+            let mut range = calamine::Range::new((first_row, first_col), (last_row, first_col + width as u32 - 1));
+            for (row, cells) in header.iter() {
+                for (offset, value) in cells.iter().enumerate() {
+                    range.set_value((*row, first_col + offset as u32), value.clone());
+                }
+            }
+            range
+        }
+        _ => calamine::Range::empty(),
+    };
+
+    let lazy = new_lazy_rows(row_receiver, reader, last_row);
+    if header.len() < header_rows {
+        // The sheet ended within the header, so the thread is done (or failed)
+        lazy.finish()?;
+        return Ok((sheet_name, range, None));
+    }
+    Ok((sheet_name, range, Some(lazy)))
+}
+
+/// Groups the cells of a worksheet into rows and sends them in order (--lazy-read)
+/// 
+/// Like Range::rows(), rows without cells between two rows that have some are
+/// sent too (empty), and empty cells don't count. Sending stops quietly once
+/// the receiver is gone, e.g. because the conversion failed.
+/// 
+/// # Arguments
+/// * `first_col` - Column the sent rows start at; cells left of it are skipped
+/// * `next_cell` - Reads the next cell of the sheet (calamine's cells reader)
+/// * `sender` - Receives every row
+/// 
+/// # Errors
+/// Returns error if a cell can't be read
+fn send_rows<'a, E: std::error::Error + Send + Sync + 'static>(
+    first_col: u32,
+    mut next_cell: impl FnMut() -> std::result::Result<Option<calamine::Cell<calamine::DataRef<'a>>>, E>,
+    sender: &mpsc::SyncSender<LazyRow>,
+) -> Result<()> {
+    let mut current: Option<LazyRow> = None;
+    while let Some(cell) = next_cell()? {
+        let (row, col) = cell.get_position();
+        if matches!(cell.get_value(), calamine::DataRef::Empty) || col < first_col {
+            continue;
+        }
+        // Cells arrive in row order - a new row index completes the current row
+        if let Some((current_row, _)) = current
+            && current_row != row
+        {
+            let gap = (current_row + 1..row).map(|empty_row| (empty_row, Vec::new()));
+            for completed in current.take().into_iter().chain(gap) {
+                if sender.send(completed).is_err() {
+                    return Ok(()); // Nobody is converting any more
+                }
+            }
+        }
+        let (_, cells) = current.get_or_insert_with(|| (row, Vec::new()));
+        let index = (col - first_col) as usize;
+        if cells.len() <= index {
+            cells.resize(index + 1, calamine::Data::Empty);
+        }
+        cells[index] = calamine::Data::from(cell.get_value().clone());
+    }
+    if let Some(last) = current {
+        let _ = sender.send(last);
+    }
+    Ok(())
+}
+
+/// Replaces the cached value of every formula cell in a range with its formula text
+/// 
+/// calamine returns formulas without the leading "=", so it is added back.
+/// Only cells inside the range are touched - for a table, formulas elsewhere
+/// on the sheet are ignored.
+/// 
+/// # Arguments
+/// * `workbook` - Open workbook the range was read from
+/// * `sheet_name` - Sheet holding the range
+/// * `range` - Cells to update in place
+/// 
+/// # Errors
+/// Returns error if the sheet's formulas cannot be read
+fn overlay_formulas<RS: Read + Seek>(
+    workbook: &mut Sheets<RS>,
+    sheet_name: &str,
+    range: &mut calamine::Range<calamine::Data>,
+) -> Result<()> {
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return Ok(()); // Empty range - nothing to replace
+    };
+    let formulas = workbook
+        .worksheet_formula(sheet_name)
+        .context(ConversionError::SheetNotFound(format!("Failed to read formulas of sheet '{}'", sheet_name)))?;
+    let (first_row, first_col) = formulas.start().unwrap_or((0, 0));
+
+    for (row, col, formula) in formulas.used_cells() {
+        let pos = (first_row + row as u32, first_col + col as u32);
+        let inside = (start.0..=end.0).contains(&pos.0) && (start.1..=end.1).contains(&pos.1);
+        if inside && !formula.is_empty() {
+            range.set_value(pos, calamine::Data::String(format!("={}", formula)));
+        }
+    }
+    Ok(())
+}
+
+/// Converts an A1-style cell reference to a 0-based (row, column) pair
+/// 
+/// # Examples
+/// - "A1" -> Some((0, 0))
+/// - "$C$12" -> Some((11, 2))
+/// - "1A" -> None
+fn parse_cell_ref(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.replace('$', ""); // Absolute markers don't matter here
+    let letters: String = reference.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    let digits = &reference[letters.len()..];
+    if letters.is_empty() || digits.is_empty() {
+        return None;
+    }
+
+    // Column letters are a bijective base-26 number: A=1 ... Z=26, AA=27
+    let col = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0u32, |acc, b| acc * 26 + (b - b'A' + 1) as u32);
+    let row: u32 = digits.parse().ok()?;
+    if row == 0 {
+        return None;
+    }
+    Some((row - 1, col - 1))
+}
+
+/// Expands an A1-style reference or range ("B2" or "B2:C4") to every cell it covers
+fn expand_cell_range(reference: &str) -> Vec<(u32, u32)> {
+    let (start, end) = match reference.split_once(':') {
+        Some((a, b)) => (parse_cell_ref(a), parse_cell_ref(b)),
+        None => (parse_cell_ref(reference), parse_cell_ref(reference)),
+    };
+    match (start, end) {
+        (Some((r1, c1)), Some((r2, c2))) => (r1.min(r2)..=r1.max(r2))
+            .flat_map(|r| (c1.min(c2)..=c1.max(c2)).map(move |c| (r, c)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A rectangular area of a sheet as (row, column) corners, 0-based and inclusive
+/// 
+/// A missing row or column means the area spans all of them, as in "$A:$D"
+/// (whole columns) or "$1:$20" (whole rows).
+type SheetArea = ((Option<u32>, Option<u32>), (Option<u32>, Option<u32>));
+
+/// Reads the print area Excel has defined for a sheet (--use-print-area)
+/// 
+/// The print area is the "_xlnm.Print_Area" defined name whose formula refers
+/// to this sheet, e.g. "'Sales Data'!$A$3:$F$40". When it has several areas,
+/// the first one on the sheet is used.
+/// 
+/// # Arguments
+/// * `file` - Path to the Excel file (.xlsx or .xlsb)
+/// * `zip_entry` - Workbook inside `file` to read instead, if `file` is a zip archive
+/// * `sheet_name` - Actual name of the sheet
+/// 
+/// # Returns
+/// The area, or None if the sheet has no print area
+fn read_print_area(file: &Path, zip_entry: Option<&str>, sheet_name: &str) -> Result<Option<SheetArea>> {
+    let workbook = open_workbook_input(file, zip_entry)?;
+    let area = workbook
+        .defined_names()
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("_xlnm.Print_Area"))
+        .flat_map(|(_, formula)| split_formula_areas(formula))
+        .find_map(|area| {
+            let (sheet, reference) = area.rsplit_once('!')?;
+            if !unquote_sheet_name(sheet).eq_ignore_ascii_case(sheet_name) {
+                return None;
+            }
+            let (start, end) = reference.split_once(':').unwrap_or((reference, reference));
+            Some((parse_area_ref(start)?, parse_area_ref(end)?))
+        });
+    Ok(area)
+}
+
+/// Removes the quotes around a sheet name in a reference ('Sales Data' -> Sales Data)
+/// 
+/// Inside the quotes a doubled quote stands for one ('Bob''s' -> Bob's).
+fn unquote_sheet_name(sheet: &str) -> String {
+    match sheet.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        Some(quoted) => quoted.replace("''", "'"),
+        None => sheet.to_string(),
+    }
+}
+
+/// Splits a defined name's formula into its comma-separated areas, minding quoted sheet names
+/// 
+/// # Examples
+/// - "Sheet1!$A$1:$B$2,Sheet1!$D$1:$E$2" -> ["Sheet1!$A$1:$B$2", "Sheet1!$D$1:$E$2"]
+/// - "'Q1, Q2'!$A:$C" -> ["'Q1, Q2'!$A:$C"]
+fn split_formula_areas(formula: &str) -> Vec<&str> {
+    let mut areas = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in formula.char_indices() {
+        match c {
+            '\'' => quoted = !quoted, // '' inside a quoted name toggles twice
+            ',' if !quoted => {
+                areas.push(&formula[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    areas.push(&formula[start..]);
+    areas
+}
+
+/// Parses one corner of an area reference, which may be a whole column or row
+/// 
+/// # Examples
+/// - "$B$3" -> Some((Some(2), Some(1)))
+/// - "$D" -> Some((None, Some(3)))
+/// - "$20" -> Some((Some(19), None))
+fn parse_area_ref(reference: &str) -> Option<(Option<u32>, Option<u32>)> {
+    let reference = reference.trim().replace('$', "");
+    if let Some(cell) = parse_cell_ref(&reference) {
+        return Some((Some(cell.0), Some(cell.1)));
+    }
+    if reference.chars().all(|c| c.is_ascii_digit()) {
+        let row: u32 = reference.parse().ok()?;
+        return row.checked_sub(1).map(|row| (Some(row), None));
+    }
+    // "D" -> "D1" to reuse the column letter parsing
+    let (_, col) = parse_cell_ref(&format!("{}1", reference))?;
+    Some((None, Some(col)))
+}
+
+/// A cell rectangle to convert, optionally on another sheet (--range)
+#[derive(Debug, Clone)]
+struct SheetRange {
+    /// Sheet named by the "Sheet!" qualifier, read instead of the sheet argument
+    sheet: Option<String>,
+    /// The rectangle itself
+    area: SheetArea,
+    /// The rectangle as written, without the qualifier (for messages)
+    reference: String,
+}
+
+/// Parses a --range value: an A1-style rectangle with an optional sheet qualifier
+/// 
+/// # Examples
+/// - "A1:C50" -> A1:C50 on the sheet given by the sheet argument
+/// - "Details!A1:C50" -> A1:C50 on sheet "Details"
+/// - "'Q1 Sales'!$B$3:$F$40" -> quoted names may contain spaces; "$" is ignored
+/// - "A:D" -> columns A to D, all rows
+/// 
+/// # Errors
+/// - Returns error if the value is not a rectangle of two corners ("A1" alone is rejected)
+/// - Returns error if a corner is past the last row or column of a worksheet
+/// - Returns error if the rectangle starts after it ends
+fn parse_sheet_range(value: &str) -> std::result::Result<SheetRange, String> {
+    let (sheet, reference) = match value.rsplit_once('!') {
+        Some((sheet, reference)) => {
+            let sheet = unquote_sheet_name(sheet.trim());
+            if sheet.is_empty() {
+                return Err("the sheet name before '!' is empty".to_string());
+            }
+            (Some(sheet), reference.trim())
+        }
+        None => (None, value.trim()),
+    };
+    let corners = reference
+        .split_once(':')
+        .and_then(|(start, end)| Some((parse_area_ref(start)?, parse_area_ref(end)?)));
+    let Some((start, end)) = corners else {
+        return Err(format!("'{}' is not a cell range like A1:C50", reference));
+    };
+    // Both corners must be cells, whole columns ("A:D") or whole rows ("3:40")
+    if start.0.is_some() != end.0.is_some() || start.1.is_some() != end.1.is_some() {
+        return Err(format!("'{}' mixes a cell with a whole row or column", reference));
+    }
+    for (row, col) in [start, end] {
+        if row.is_some_and(|row| row >= SHEET_MAX_ROWS) || col.is_some_and(|col| col >= SHEET_MAX_COLUMNS) {
+            return Err(format!(
+                "'{}' is outside the worksheet (at most {} rows and columns A to {})",
+                reference,
+                SHEET_MAX_ROWS,
+                column_letters(SHEET_MAX_COLUMNS as usize - 1)
+            ));
+        }
+    }
+    if start.0 > end.0 || start.1 > end.1 {
+        return Err(format!("'{}' starts after it ends; write the top-left cell first", reference));
+    }
+    Ok(SheetRange { sheet, area: (start, end), reference: reference.to_string() })
+}
+
+/// Cuts a sheet's range down to an area, keeping absolute cell positions
+/// 
+/// The area is clipped to the cells the sheet actually has, so a print area
+/// (or --range) of whole columns doesn't allocate a million rows.
+fn slice_to_area(range: &calamine::Range<calamine::Data>, area: SheetArea) -> calamine::Range<calamine::Data> {
+    let (Some(used_start), Some(used_end)) = (range.start(), range.end()) else {
+        return range.clone();
+    };
+    let ((start_row, start_col), (end_row, end_col)) = area;
+    let start = (
+        start_row.map_or(used_start.0, |row| row.max(used_start.0)),
+        start_col.map_or(used_start.1, |col| col.max(used_start.1)),
+    );
+    let end = (
+        end_row.map_or(used_end.0, |row| row.min(used_end.0)),
+        end_col.map_or(used_end.1, |col| col.min(used_end.1)),
+    );
+    if start.0 > end.0 || start.1 > end.1 {
+        return calamine::Range::empty(); // The print area holds no cells
+    }
+    range.range(start, end)
+}
+
+/// Crops rows without a value from the top and bottom of a range (--trim-sheet-whitespace-rows)
+/// 
+/// A row counts as empty when each of its cells is empty or only whitespace.
+/// Cell positions stay absolute, so annotations and hidden rows still line up.
+/// 
+/// # Example
+/// Rows 1-2 blank, header in row 3, data in rows 4-9, rows 10-12 blank -> rows 3-9
+fn crop_empty_rows(range: &calamine::Range<calamine::Data>) -> calamine::Range<calamine::Data> {
+    let (Some(start), Some(end)) = (range.start(), range.end()) else {
+        return range.clone();
+    };
+    let is_empty_row = |row: &[calamine::Data]| {
+        row.iter().all(|cell| match cell {
+            calamine::Data::Empty => true,
+            calamine::Data::String(text) => text.trim().is_empty(),
+            _ => false,
+        })
+    };
+    let Some(first) = range.rows().position(|row| !is_empty_row(row)) else {
+        return calamine::Range::empty(); // Nothing but blank rows
+    };
+    let last = range.rows().rposition(|row| !is_empty_row(row)).unwrap_or(first);
+    range.range((start.0 + first as u32, start.1), (start.0 + last as u32, end.1))
+}
+
+/// Reads the raw parts of an .xlsx file (a zip archive of XML documents)
+/// 
+/// calamine's Range only carries cell values, so features that need more
+/// (hyperlinks, ...) read the underlying XML parts through this type.
+struct XlsxParts {
+    /// The opened .xlsx zip archive
+    archive: zip::ZipArchive<Box<dyn WorkbookInput>>,
+}
+
+/// A relationship entry from a part's .rels file
+struct Relationship {
+    /// Relationship type URI (e.g. ".../relationships/hyperlink")
+    rel_type: String,
+    /// Target path or URL
+    target: String,
+    /// Whether the target is outside the package (e.g. a web URL)
+    external: bool,
+}
+
+impl XlsxParts {
+    /// Opens the .xlsx file (or --zip-entry inside it) as a zip archive
+    /// 
+    /// # Errors
+    /// - Returns error if the file can't be opened or isn't a zip archive
+    fn open(file: &Path, zip_entry: Option<&str>) -> Result<Self> {
+        let handle = open_input(file, zip_entry)?;
+        let archive = zip::ZipArchive::new(handle)
+            .context(ConversionError::FileOpen(format!("Not a valid .xlsx archive: {:?}", file)))?;
+        Ok(XlsxParts { archive })
+    }
+
+    /// Reads a part as text
+    /// 
+    /// # Returns
+    /// Some(xml) if the part exists, None if it doesn't
+    fn read_part(&mut self, path: &str) -> Result<Option<String>> {
+        self.read_binary_part(path)?
+            .map(|bytes| String::from_utf8(bytes).context(format!("xlsx part '{}' is not UTF-8 text", path)))
+            .transpose()
+    }
+
+    /// Reads a part as raw bytes (e.g. the records of an .xlsb workbook)
+    /// 
+    /// # Returns
+    /// Some(bytes) if the part exists, None if it doesn't
+    fn read_binary_part(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let mut entry = match self.archive.by_name(path) {
+            Ok(entry) => entry,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err).context(format!("Failed to read xlsx part '{}'", path)),
+        };
+        let mut bytes = Vec::with_capacity(entry.size() as usize);
+        entry
+            .read_to_end(&mut bytes)
+            .context(format!("Failed to read xlsx part '{}'", path))?;
+        Ok(Some(bytes))
+    }
+
+    /// Reads the relationships of a part, keyed by relationship id
+    /// 
+    /// Internal targets are resolved to full part paths (e.g. "xl/comments1.xml").
+    fn relationships(&mut self, part: &str) -> Result<HashMap<String, Relationship>> {
+        // "xl/worksheets/sheet1.xml" -> "xl/worksheets/_rels/sheet1.xml.rels"
+        let (dir, name) = part.rsplit_once('/').unwrap_or(("", part));
+        let rels_path = if dir.is_empty() {
+            format!("_rels/{}.rels", name)
+        } else {
+            format!("{}/_rels/{}.rels", dir, name)
+        };
+        let Some(xml) = self.read_part(&rels_path)? else {
+            return Ok(HashMap::new());
+        };
+
+        let mut rels = HashMap::new();
+        for attrs in xml_elements(&xml, b"Relationship")? {
+            let external = attrs.get("TargetMode").is_some_and(|m| m == "External");
+            let target = attrs.get("Target").cloned().unwrap_or_default();
+            let target = if external { target } else { resolve_part_path(dir, &target) };
+            rels.insert(
+                attrs.get("Id").cloned().unwrap_or_default(),
+                Relationship {
+                    rel_type: attrs.get("Type").cloned().unwrap_or_default(),
+                    target,
+                    external,
+                },
+            );
+        }
+        Ok(rels)
+    }
+
+    /// Finds the worksheet part (e.g. "xl/worksheets/sheet1.xml") for a sheet name
+    /// 
+    /// # Errors
+    /// - Returns error if the workbook doesn't contain a sheet with this name
+    fn sheet_part(&mut self, sheet_name: &str) -> Result<String> {
+        let workbook = self
+            .read_part("xl/workbook.xml")?
+            .context("xlsx archive has no xl/workbook.xml")?;
+        let rel_id = xml_elements(&workbook, b"sheet")?
+            .into_iter()
+            .find(|attrs| attrs.get("name").is_some_and(|n| n == sheet_name))
+            .and_then(|attrs| attrs.get("id").cloned()) // r:id (namespace prefix dropped)
+            .context(ConversionError::SheetNotFound(format!("Sheet '{}' not found", sheet_name)))?;
+
+        self.relationships("xl/workbook.xml")?
+            .remove(&rel_id)
+            .map(|rel| rel.target)
+            .context(format!("Sheet '{}' has no worksheet part", sheet_name))
+    }
+
+    /// Reads the hyperlinks of a sheet, keyed by absolute (row, column)
+    /// 
+    /// External links resolve to their URL (plus "#location" if present);
+    /// links within the workbook resolve to "#Sheet!A1" style locations.
+    fn sheet_hyperlinks(&mut self, sheet_name: &str) -> Result<CellMap> {
+        let part = self.sheet_part(sheet_name)?;
+        let xml = self.read_part(&part)?.unwrap_or_default();
+        let rels = self.relationships(&part)?;
+
+        let mut links = HashMap::new();
+        for attrs in xml_elements(&xml, b"hyperlink")? {
+            // The URL lives in the sheet's relationships; "location" is an in-document anchor
+            let target = attrs
+                .get("id")
+                .and_then(|id| rels.get(id))
+                .filter(|rel| rel.rel_type.ends_with("/hyperlink") || rel.external)
+                .map(|rel| rel.target.clone());
+            let url = match (target, attrs.get("location")) {
+                (Some(url), Some(loc)) => format!("{}#{}", url, loc),
+                (Some(url), None) => url,
+                (None, Some(loc)) => format!("#{}", loc),
+                (None, None) => continue,
+            };
+            for cell in expand_cell_range(attrs.get("ref").map(String::as_str).unwrap_or("")) {
+                links.insert(cell, url.clone());
+            }
+        }
+        Ok(links)
+    }
+
+    /// Reads the comments (notes) of a sheet, keyed by absolute (row, column)
+    /// 
+    /// The comments live in separate comments{n}.xml parts linked from the sheet's relationships.
+    fn sheet_comments(&mut self, sheet_name: &str) -> Result<CellMap> {
+        let part = self.sheet_part(sheet_name)?;
+        let comment_parts: Vec<String> = self
+            .relationships(&part)?
+            .into_values()
+            .filter(|rel| rel.rel_type.ends_with("/comments") && !rel.external)
+            .map(|rel| rel.target)
+            .collect();
+
+        let mut comments = HashMap::new();
+        for comment_part in comment_parts {
+            let xml = self.read_part(&comment_part)?.unwrap_or_default();
+            for (reference, text) in comment_texts(&xml)? {
+                if let Some(cell) = parse_cell_ref(&reference) {
+                    comments.insert(cell, text);
+                }
+            }
+        }
+        Ok(comments)
+    }
+
+    /// Reads the number format code of every styled cell, keyed by absolute (row, column)
+    /// 
+    /// A cell's "s" attribute indexes the cellXfs list in styles.xml, whose numFmtId
+    /// is either a custom <numFmt> or one of Excel's built-in formats.
+    /// Cells with the General format and empty styled cells are left out.
+    fn sheet_formats(&mut self, sheet_name: &str) -> Result<CellMap> {
+        let styles = self.read_part("xl/styles.xml")?.unwrap_or_default();
+        let custom: HashMap<u32, String> = xml_elements(&styles, b"numFmt")?
+            .into_iter()
+            .filter_map(|attrs| Some((attrs.get("numFmtId")?.parse().ok()?, attrs.get("formatCode")?.clone())))
+            .collect();
+        let style_formats: Vec<Option<String>> = cell_xf_format_ids(&styles)?
+            .into_iter()
+            .map(|id| match id {
+                0 => None, // General
+                id => custom.get(&id).cloned().or_else(|| builtin_number_format(id).map(String::from)),
+            })
+            .collect();
+
+        let part = self.sheet_part(sheet_name)?;
+        let xml = self.read_part(&part)?.unwrap_or_default();
+        let mut formats = HashMap::new();
+        for (cell, style) in styled_cells(&xml)? {
+            if let Some(Some(format)) = style_formats.get(style) {
+                formats.insert(cell, format.clone());
+            }
+        }
+        Ok(formats)
+    }
+
+    /// Reads which columns of a sheet are hidden, as absolute 0-based column indices
+    /// 
+    /// Hidden columns are listed in the sheet's <cols> section as <col min=".." max=".."
+    /// hidden="1"/>, where min and max are 1-based and cover a run of columns.
+    fn sheet_hidden_columns(&mut self, sheet_name: &str) -> Result<HashSet<u32>> {
+        let part = self.sheet_part(sheet_name)?;
+        let xml = self.read_part(&part)?.unwrap_or_default();
+
+        let mut hidden = HashSet::new();
+        for attrs in xml_elements(&xml, b"col")? {
+            if !matches!(attrs.get("hidden").map(String::as_str), Some("1" | "true")) {
+                continue;
+            }
+            let bound = |name: &str| attrs.get(name).and_then(|n| n.parse::<u32>().ok());
+            if let (Some(min), Some(max)) = (bound("min"), bound("max")) {
+                hidden.extend(min.saturating_sub(1)..max);
+            }
+        }
+        Ok(hidden)
+    }
+
+    /// Reads which rows of a sheet are hidden, as absolute 0-based row indices
+    /// 
+    /// Rows hidden by hand or by an AutoFilter carry hidden="1" on their <row> element.
+    fn sheet_hidden_rows(&mut self, sheet_name: &str) -> Result<HashSet<u32>> {
+        let part = self.sheet_part(sheet_name)?;
+        let xml = self.read_part(&part)?.unwrap_or_default();
+
+        let hidden = xml_elements(&xml, b"row")?
+            .into_iter()
+            .filter(|attrs| matches!(attrs.get("hidden").map(String::as_str), Some("1" | "true")))
+            .filter_map(|attrs| attrs.get("r")?.parse::<u32>().ok()) // "r" is the 1-based row number
+            .filter_map(|row| row.checked_sub(1))
+            .collect();
+        Ok(hidden)
+    }
+}
+
+/// Resolves a relationship target against the directory of its source part
+/// 
+/// # Examples
+/// - ("xl", "worksheets/sheet1.xml") -> "xl/worksheets/sheet1.xml"
+/// - ("xl/worksheets", "../comments1.xml") -> "xl/comments1.xml"
+/// - ("xl", "/xl/styles.xml") -> "xl/styles.xml"
+fn resolve_part_path(base_dir: &str, target: &str) -> String {
+    // Absolute targets are relative to the package root
+    if let Some(absolute) = target.strip_prefix('/') {
+        return absolute.to_string();
+    }
+
+    let mut segments: Vec<&str> = base_dir.split('/').filter(|s| !s.is_empty()).collect();
+    for segment in target.split('/') {
+        match segment {
+            ".." => {
+                segments.pop();
+            }
+            "." | "" => {}
+            other => segments.push(other),
+        }
+    }
+    segments.join("/")
+}
+
+/// Collects the attributes of every element with the given local name
+/// 
+/// Attribute keys are stored by local name, so "r:id" is available as "id".
+/// 
+/// # Arguments
+/// * `xml` - XML document text
+/// * `element` - Local name of the elements to collect (e.g. b"hyperlink")
+/// 
+/// # Returns
+/// One attribute map per matching element, in document order
+fn xml_elements(xml: &str, element: &[u8]) -> Result<Vec<HashMap<String, String>>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut found = Vec::new();
+    loop {
+        match reader.read_event().context("Failed to parse xlsx XML")? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == element => {
+                let mut attrs = HashMap::new();
+                for attr in e.attributes().flatten() {
+                    let key = String::from_utf8_lossy(attr.key.local_name().as_ref()).into_owned();
+                    let value = attr
+                        .decode_and_unescape_value(&reader)
+                        .context("Invalid XML attribute")?
+                        .into_owned();
+                    attrs.insert(key, value);
+                }
+                found.push(attrs);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(found)
+}
+
+/// Lists the numFmtId of every cell format (<xf>) in the cellXfs of styles.xml
+/// 
+/// The position in the list is the style index a cell's "s" attribute refers to.
+/// The <xf> elements of cellStyleXfs (named styles) are skipped.
+fn cell_xf_format_ids(xml: &str) -> Result<Vec<u32>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut ids = Vec::new();
+    let mut in_cell_xfs = false;
+    loop {
+        match reader.read_event().context("Failed to parse xlsx XML")? {
+            Event::Start(e) if e.local_name().as_ref() == b"cellXfs" => in_cell_xfs = true,
+            Event::End(e) if e.local_name().as_ref() == b"cellXfs" => in_cell_xfs = false,
+            Event::Start(e) | Event::Empty(e) if in_cell_xfs && e.local_name().as_ref() == b"xf" => {
+                let id = e
+                    .attributes()
+                    .flatten()
+                    .find(|attr| attr.key.local_name().as_ref() == b"numFmtId")
+                    .and_then(|attr| String::from_utf8_lossy(&attr.value).parse().ok())
+                    .unwrap_or(0); // No numFmtId means General
+                ids.push(id);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(ids)
+}
+
+/// Finds the (absolute position, style index) of every non-empty cell with a style
+/// 
+/// Cells without an "r" reference take the position after the previous cell.
+/// Empty <c/> elements only carry formatting, so they're skipped.
+fn styled_cells(xml: &str) -> Result<Vec<((u32, u32), usize)>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut found = Vec::new();
+    let mut row: Option<u32> = None; // Current 0-based row
+    let mut next_col = 0u32; // Column of the next cell without a reference
+    loop {
+        let (e, has_value) = match reader.read_event().context("Failed to parse xlsx XML")? {
+            Event::Start(e) => (e, true),
+            Event::Empty(e) => (e, false),
+            Event::Eof => break,
+            _ => continue,
+        };
+        let attr = |name: &[u8]| {
+            e.attributes()
+                .flatten()
+                .find(|attr| attr.key.local_name().as_ref() == name)
+                .map(|attr| String::from_utf8_lossy(&attr.value).into_owned())
+        };
+        match e.local_name().as_ref() {
+            b"row" => {
+                // "r" is the 1-based row number; rows without it follow the previous row
+                row = Some(match attr(b"r").and_then(|r| r.parse::<u32>().ok()) {
+                    Some(r) => r.saturating_sub(1),
+                    None => row.map_or(0, |r| r + 1),
+                });
+                next_col = 0;
+            }
+            b"c" => {
+                let cell = attr(b"r").and_then(|r| parse_cell_ref(&r)).unwrap_or((row.unwrap_or(0), next_col));
+                next_col = cell.1 + 1;
+                if let Some(style) = attr(b"s").and_then(|s| s.parse().ok())
+                    && has_value
+                {
+                    found.push((cell, style));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(found)
+}
+
+/// Returns the format code of one of Excel's built-in number formats
+/// 
+/// Only the locale-independent ids are listed; the rest (e.g. 27-36, which
+/// depend on the East Asian locale) return None.
+fn builtin_number_format(id: u32) -> Option<&'static str> {
+    Some(match id {
+        0 => "General",
+        1 => "0",
+        2 => "0.00",
+        3 => "#,##0",
+        4 => "#,##0.00",
+        9 => "0%",
+        10 => "0.00%",
+        11 => "0.00E+00",
+        12 => "# ?/?",
+        13 => "# ??/??",
+        14 => "mm-dd-yy",
+        15 => "d-mmm-yy",
+        16 => "d-mmm",
+        17 => "mmm-yy",
+        18 => "h:mm AM/PM",
+        19 => "h:mm:ss AM/PM",
+        20 => "h:mm",
+        21 => "h:mm:ss",
+        22 => "m/d/yy h:mm",
+        37 => "#,##0 ;(#,##0)",
+        38 => "#,##0 ;[Red](#,##0)",
+        39 => "#,##0.00;(#,##0.00)",
+        40 => "#,##0.00;[Red](#,##0.00)",
+        45 => "mm:ss",
+        46 => "[h]:mm:ss",
+        47 => "mmss.0",
+        48 => "##0.0E+0",
+        49 => "@",
+        _ => return None,
+    })
+}
+
+/// Extracts the (cell reference, text) of every <comment> in a comments part
+/// 
+/// The text of all rich-text runs is concatenated; phonetic hints (<rPh>) are skipped.
+/// 
+/// # Example
+/// <comment ref="B2"><text><r><t>Checked</t></r><r><t> twice</t></r></text></comment>
+/// -> ("B2", "Checked twice")
+fn comment_texts(xml: &str) -> Result<Vec<(String, String)>> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut found = Vec::new();
+    let mut current: Option<(String, String)> = None; // (ref, text so far) inside a <comment>
+    let mut in_text = false; // Inside a <t> element
+    let mut phonetic_depth = 0; // Inside <rPh> phonetic hints
+    loop {
+        match reader.read_event().context("Failed to parse xlsx XML")? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"comment" => {
+                    let reference = e
+                        .attributes()
+                        .flatten()
+                        .find(|attr| attr.key.local_name().as_ref() == b"ref")
+                        .map(|attr| attr.decode_and_unescape_value(&reader).map(|v| v.into_owned()))
+                        .transpose()
+                        .context("Invalid XML attribute")?
+                        .unwrap_or_default();
+                    current = Some((reference, String::new()));
+                }
+                b"rPh" => phonetic_depth += 1,
+                b"t" => in_text = true,
+                _ => {}
+            },
+            Event::Text(e) if in_text && phonetic_depth == 0 => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&e.unescape().context("Invalid XML text")?);
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"comment" => found.extend(current.take()),
+                b"rPh" => phonetic_depth -= 1,
+                b"t" => in_text = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(found)
+}
+
+/// Combines several header rows into one, joining each column's parts
+/// 
+/// Merged cells in the upper rows only hold their value in the first column, so
+/// blanks there are filled forward from the left - but only for columns that have
+/// a header in a lower row, so data-less columns don't get a spurious header.
+/// Empty parts are skipped when joining.
+/// 
+/// With `normalize_parts` every part is turned into a key first, so the separator
+/// ends up in the final key as given (--merge-header-with-parent).
+/// 
+/// # Arguments
+/// * `header_rows` - The header rows, top to bottom
+/// * `join` - Separator placed between the parts (--header-join or --parent-separator)
+/// * `normalize_parts` - Normalize each part on its own before joining
+/// 
+/// # Returns
+/// One header row with a text cell per column (Empty where every part is blank)
+/// 
+/// # Examples
+/// Rows: ["Name", "Q1", ""], ["", "Revenue", "Cost"] with " / "
+/// Result: ["Name", "Q1 / Revenue", "Q1 / Cost"]
+/// 
+/// Rows: ["", "Home Address", ""], ["ID", "Street", "Zip Code"] with "_", normalized
+/// Result: ["id", "home_address_street", "home_address_zip_code"]
+fn combine_header_rows(header_rows: &[&[calamine::Data]], join: &str, normalize_parts: bool) -> Vec<calamine::Data> {
+    let width = header_rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let text_at = |row: &[calamine::Data], col: usize| {
+        let text = row.get(col).map(|cell| cell.to_string().trim().to_string()).unwrap_or_default();
+        if normalize_parts { normalize_column_name(&text) } else { text }
+    };
+
+    // Parts per column, one entry per header row
+    let mut parts: Vec<Vec<String>> = (0..width)
+        .map(|col| header_rows.iter().map(|row| text_at(row, col)).collect())
+        .collect();
+
+    // Fill merged blanks forward in every row but the last
+    for level in 0..header_rows.len().saturating_sub(1) {
+        let mut last_seen = String::new();
+        for column in parts.iter_mut() {
+            if !column[level].is_empty() {
+                last_seen = column[level].clone();
+            } else if column[level + 1..].iter().any(|part| !part.is_empty()) {
+                column[level] = last_seen.clone();
+            }
+        }
+    }
+
+    parts
+        .into_iter()
+        .map(|column| {
+            let joined = column
+                .into_iter()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(join);
+            if joined.is_empty() {
+                calamine::Data::Empty
+            } else {
+                calamine::Data::String(joined)
+            }
+        })
+        .collect()
+}
+
+/// A single sort key parsed from --sort-by
+#[derive(Debug, Clone)]
+struct SortKey {
+    /// Normalized header name (JSON key) to sort by
+    key: String,
+    /// Whether to sort in descending order
+    descending: bool,
+}
+
+/// Parses the --sort-by specification into sort keys
+/// 
+/// Header names are normalized with normalize_column_name() so that both the
+/// original Excel header ("Total Amount") and the JSON key ("total_amount") work.
+/// 
+/// # Arguments
+/// * `spec` - Comma-separated sort keys, each optionally suffixed with ":asc" or ":desc"
+/// * `headers` - Normalized headers of the selected columns
+/// 
+/// # Returns
+/// A Result containing the sort keys in priority order
+/// 
+/// # Errors
+/// - Returns error if a direction other than asc/desc is given
+/// - Returns error if a key doesn't match any selected column
+/// 
+/// # Example
+/// "Region,Amount:desc" -> [SortKey{region, asc}, SortKey{amount, desc}]
+fn parse_sort_keys(spec: &str, headers: &[String]) -> Result<Vec<SortKey>> {
+    spec.split(',') // Split by comma
+        .map(|part| {
+            // Split off an optional ":asc" / ":desc" suffix
+            let (name, descending) = match part.trim().rsplit_once(':') {
+                Some((name, dir)) => match dir.trim().to_lowercase().as_str() {
+                    "asc" => (name, false),
+                    "desc" => (name, true),
+                    other => anyhow::bail!("Invalid sort direction '{}', expected asc or desc", other),
+                },
+                None => (part, false),
+            };
+            let key = normalize_column_name(name);
+            if !headers.contains(&key) {
+                anyhow::bail!("Sort column '{}' is not among the selected columns", name.trim())
+            }
+            Ok(SortKey { key, descending })
+        })
+        .collect() // Collect all results, will fail if any key was invalid
+}
+
+/// Compares two JSON values for sorting
+/// 
+/// # Behavior
+/// - Null and empty-string values always sort last (regardless of direction)
+/// - Numbers and numeric strings sort before other text and compare numerically
+/// - Other text compares lexicographically
+/// 
+/// Numbers and text are ranked as two classes rather than compared pairwise,
+/// so the order stays total ("9" < "10" < "1a") as sort_by() requires.
+fn compare_json_values(a: Option<&Value>, b: Option<&Value>, descending: bool) -> Ordering {
+    // Treat missing, null and empty-string values as "empty"
+    let as_text = |v: Option<&Value>| -> Option<String> {
+        match v {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) if s.is_empty() => None,
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => Some(other.to_string()),
+        }
+    };
+
+    match (as_text(a), as_text(b)) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater, // Empty values go last
+        (Some(_), None) => Ordering::Less,
+        (Some(x), Some(y)) => {
+            // "NaN" and "inf" parse as f64 but are text to a spreadsheet
+            let number = |text: &str| text.trim().parse::<f64>().ok().filter(|n| n.is_finite());
+            let ordering = match (number(&x), number(&y)) {
+                (Some(nx), Some(ny)) => nx.total_cmp(&ny),
+                (Some(_), None) => Ordering::Less, // Numbers before text
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => x.cmp(&y),
+            };
+            if descending { ordering.reverse() } else { ordering }
+        }
+    }
+}
+
+/// Sorts records in place by the given keys (stable, so ties keep source order)
+/// 
+/// # Arguments
+/// * `json_array` - Records produced by convert_rows_to_json()
+/// * `keys` - Sort keys in priority order; later keys break ties of earlier ones
+fn sort_records(json_array: &mut [Value], keys: &[SortKey]) {
+    json_array.sort_by(|a, b| {
+        keys.iter()
+            .map(|k| compare_json_values(a.get(&k.key), b.get(&k.key), k.descending))
+            .find(|ordering| *ordering != Ordering::Equal) // First key that decides
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Removes duplicate records, keeping the first occurrence and preserving order
+/// 
+/// Records are compared by their serialized JSON text, either the whole object
+/// or only the value of a single column.
+/// 
+/// # Arguments
+/// * `json_array` - Records produced by convert_rows_to_json()
+/// * `key` - Optional normalized header name to deduplicate on instead of the full record
+/// 
+/// # Returns
+/// The number of duplicate records that were removed
+fn dedupe_records(json_array: &mut Vec<Value>, key: Option<&str>) -> usize {
+    let before = json_array.len();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    json_array.retain(|record| {
+        // Canonical text of whatever we compare on (missing values compare as null)
+        let canonical = match key {
+            Some(k) => record.get(k).unwrap_or(&Value::Null).to_string(),
+            None => record.to_string(),
+        };
+        seen.insert(canonical) // false if already seen -> record is dropped
+    });
+
+    before - json_array.len()
+}
+
+/// Partitions records into groups keyed by the value of one column (--group-by)
+/// 
+/// Groups appear in the order their key is first seen, and records keep their
+/// order within a group. Text values are used as-is, other values in their JSON
+/// form (e.g. "42", "true"); empty, null and missing values go to `empty_key`.
+/// 
+/// # Arguments
+/// * `json_array` - Records produced by convert_rows_to_json()
+/// * `key` - Normalized header name to group on
+/// * `drop_key` - Remove the grouping column from each record (--drop-group-key)
+/// * `empty_key` - Group for records without a value (--group-empty-key)
+/// 
+/// # Example
+/// [{"region": "North", "n": 1}, {"region": "South", "n": 2}, {"region": "North", "n": 3}]
+/// -> {"North": [{"region": "North", "n": 1}, {"region": "North", "n": 3}], "South": [...]}
+fn group_records(
+    json_array: Vec<Value>,
+    key: &str,
+    drop_key: bool,
+    empty_key: &str,
+) -> serde_json::Map<String, Value> {
+    let mut groups = serde_json::Map::new();
+    for mut record in json_array {
+        let group = match record.get(key) {
+            Some(Value::String(text)) if !text.is_empty() => text.clone(),
+            None | Some(Value::Null) | Some(Value::String(_)) => empty_key.to_string(),
+            Some(other) => other.to_string(),
+        };
+        if drop_key && let Some(fields) = record.as_object_mut() {
+            fields.shift_remove(key);
+        }
+        match groups.entry(group).or_insert_with(|| Value::Array(Vec::new())) {
+            Value::Array(records) => records.push(record),
+            _ => unreachable!("groups only hold arrays"),
+        }
+    }
+    groups
+}
+
+/// Renders an output file name from a template
+/// 
+/// Path separators in the sheet name are replaced with underscores so that
+/// a sheet called "2023/Q1" can't escape the output directory.
+/// 
+/// # Arguments
+/// * `template` - File name template containing {stem}, {sheet} and/or {ext} placeholders
+/// * `stem` - Input file name without its extension
+/// * `sheet` - Name of the sheet being converted
+/// * `ext` - Extension of the output format (without the leading dot)
+/// 
+/// # Returns
+/// The file name with all placeholders substituted
+/// 
+/// # Example
+/// render_output_name("{stem}_{sheet}.{ext}", "data", "Q1/Q2", "json") -> "data_Q1_Q2.json"
+fn render_output_name(template: &str, stem: &str, sheet: &str, ext: &str) -> String {
+    // Sanitize the sheet name so it stays a single path component
+    let safe_sheet = sheet.replace(['/', '\\'], "_");
+
+    template
+        .replace("{stem}", stem) // Input file name without extension
+        .replace("{sheet}", &safe_sheet) // Sanitized sheet name
+        .replace("{ext}", ext) // Output format extension
+}
+
+/// Determines where the output file for a sheet should be written
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `sheet` - Name of the sheet being converted
+/// 
+/// # Returns
+/// The output path: either --output as given, or a path inside --output-dir
+/// generated from --name-template
+/// 
+/// # Errors
+/// - Returns error if neither --output nor --output-dir was given
+fn resolve_output_path(args: &Args, sheet: &str) -> Result<PathBuf> {
+    // An explicit --output path always wins
+    if let Some(ref output) = args.output {
+        return Ok(output.clone());
+    }
+
+    // Otherwise build the path from --output-dir and the name template
+    let dir = args
+        .output_dir
+        .as_ref()
+        .context("Either --output or --output-dir must be specified")?;
+
+    // For a workbook inside a zip archive the entry's name is the one that matters
+    let input_name = args.zip_entry.as_deref().map_or(args.file.as_path(), Path::new);
+    let stem = input_name
+        .file_stem() // Input file name without extension
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "output".to_string());
+    let default_template = if args.split_sheets { SPLIT_NAME_TEMPLATE } else { DEFAULT_NAME_TEMPLATE };
+    let template = args.name_template.as_deref().unwrap_or(default_template);
+
+    Ok(dir.join(render_output_name(template, &stem, sheet, args.format.extension())))
+}
+
+/// Prints the conversion plan for --dry-run instead of writing output
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `sheets` - The converted sheets (one, or several with --merge-sheets)
+/// * `output` - Path the output would have been written to
+/// * `record_count` - Number of records that would be written in total
+fn print_dry_run(args: &Args, sheets: &[SheetConversion], output: &Path, record_count: usize) {
+    println!("Dry run: no files were written");
+    print_inputs(args);
+    println!("Output (not written): {:?}", output);
+    if let Some(size) = args.chunk_size {
+        println!("Chunk files (not written): {}", chunk_count(record_count, size));
+    }
+    for sheet in sheets {
+        println!("Sheet: {}", sheet.sheet_name);
+        println!("  Headers: {}", sheet.headers.join(", "));
+        println!(
+            "  Column indices: {}",
+            sheet
+                .column_indices
+                .iter()
+                .map(|i| i.to_string()) // Show actual 0-based sheet indices
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        println!("  Records: {}", sheet.record_count);
+    }
+    println!("Total records: {}", record_count);
+}
+
+/// Records left out of the output after conversion
+struct RemovedRecords {
+    /// Dropped by --unique/--unique-by, if used
+    duplicates: Option<usize>,
+    /// Dropped by --drop-empty-records, if used
+    empty: Option<usize>,
+}
+
+/// Prints the conversion statistics as a single JSON object on stderr (--stats-json)
+/// 
+/// stdout and the output file are left for data, so an orchestrator can parse
+/// this line instead of scraping the human-readable summary.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `sheets` - The converted sheets
+/// * `skipped` - Sheets left out by --on-missing-sheet
+/// * `output` - Path the output was (or with --dry-run, would have been) written to
+/// * `records` - Number of records written
+/// * `removed` - Records dropped by --unique/--unique-by and --drop-empty-records
+/// * `total_time` - Wall-clock time of the whole conversion
+/// 
+/// # Example
+/// {"input":"data.xlsx","inputs":["data.xlsx"],"sheets":["Sheet1"],"skipped":[],"output":"out.json","dry_run":false,"columns":4,
+///  "records":120,"duplicates_removed":null,"empty_records_dropped":null,"truncated_cells":0,"column_types":null,
+///  "read_ms":35,"convert_ms":4,"total_ms":41}
+fn print_stats_json(
+    args: &Args,
+    sheets: &[SheetConversion],
+    skipped: &[String],
+    output: &Path,
+    records: usize,
+    removed: &RemovedRecords,
+    total_time: Duration,
+) {
+    let columns: HashSet<&String> = sheets.iter().flat_map(|sheet| &sheet.headers).collect();
+    let read_time: Duration = sheets.iter().map(|sheet| sheet.read_time).sum();
+    let convert_time: Duration = sheets.iter().map(|sheet| sheet.convert_time).sum();
+    let stats = json!({
+        "input": args.file.to_string_lossy(),
+        "inputs": input_files(args).iter().map(InputFile::label).collect::<Vec<_>>(),
+        "sheets": sheets.iter().map(|sheet| sheet.sheet_name.as_str()).collect::<Vec<_>>(),
+        "skipped": skipped,
+        "output": output.to_string_lossy(),
+        "dry_run": args.dry_run,
+        "columns": columns.len(),
+        "records": records,
+        "duplicates_removed": removed.duplicates,
+        "empty_records_dropped": removed.empty,
+        "chunks": args.chunk_size.map(|size| chunk_count(records, size)),
+        "truncated_cells": sheets.iter().map(|sheet| sheet.stats.truncated_cells).sum::<usize>(),
+        "column_types": args.column_types.then(|| {
+            column_type_summary(sheets)
+                .into_iter()
+                .map(|(header, counts)| (header, json!(counts)))
+                .collect::<serde_json::Map<String, Value>>()
+        }),
+        "read_ms": read_time.as_millis() as u64,
+        "convert_ms": convert_time.as_millis() as u64,
+        "total_ms": total_time.as_millis() as u64,
+    });
+    eprintln!("{}", stats);
+}
+
+/// Serializes the records as a TOML document
+/// 
+/// TOML has no top-level arrays and no null values, so the records are placed
+/// under `key` as an array of tables (`[[records]]`) and null values are rejected.
+/// 
+/// # Arguments
+/// * `json_array` - Array of JSON objects to serialize
+/// * `key` - Name of the top-level key holding the records
+/// 
+/// # Returns
+/// A Result containing the TOML document text
+/// 
+/// # Errors
+/// - Returns error if any record contains a null value (e.g. an empty cell with --infer-types),
+///   at any depth
+/// - Returns error if TOML serialization fails
+fn to_toml_document(json_array: &[Value], key: &str) -> Result<String> {
+    // TOML can't represent null - report the first offending field clearly
+    for (record_idx, record) in json_array.iter().enumerate() {
+        if let Some(path) = find_null(record) {
+            return Err(ConversionError::Write(format!(
+                "Record {} has a null value for '{}', which TOML can't represent",
+                record_idx + 1,
+                path
+            ))
+            .into());
+        }
+    }
+
+    // Wrap the array under the configured key: { key: [ {...}, {...} ] }
+    let mut document = serde_json::Map::new();
+    document.insert(key.to_string(), Value::Array(json_array.to_vec()));
+
+    toml::to_string(&document)
+        .context(ConversionError::Write("Failed to serialize TOML".to_string()))
+}
+
+/// Finds the first null inside a value, such as a record's nested objects and arrays
+/// 
+/// # Returns
+/// The path of the null from the top of `value` - keys joined with ".", array
+/// elements as "[index]" - or None if there is no null. A bare null has an empty path.
+/// 
+/// # Examples
+/// - {"name": "a", "address": {"city": null}} -> Some("address.city")
+/// - {"tags": ["a", null]} -> Some("tags[1]")
+/// - {"name": "a"} -> None
+fn find_null(value: &Value) -> Option<String> {
+    // An element index follows its parent directly, a key after a "."
+    let join = |parent: String, rest: String| {
+        if rest.is_empty() || rest.starts_with('[') {
+            parent + &rest
+        } else {
+            format!("{}.{}", parent, rest)
+        }
+    };
+    match value {
+        Value::Null => Some(String::new()),
+        Value::Object(fields) => fields
+            .iter()
+            .find_map(|(field, value)| find_null(value).map(|rest| join(field.clone(), rest))),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(index, item)| find_null(item).map(|rest| join(format!("[{}]", index), rest))),
+        _ => None,
+    }
+}
+
+/// Turns a JSON key into a valid XML element name
+/// 
+/// Characters that can't appear in a name become "_", and names that would
+/// start with a digit, "-" or "." (or are empty) get a leading "_".
+/// 
+/// # Examples
+/// - "unit_price" -> "unit_price"
+/// - "2024_sales" -> "_2024_sales"
+/// - "a/b c" -> "a_b_c"
+fn xml_element_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') { c } else { '_' })
+        .collect();
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Serializes the records as an XML document
+/// 
+/// Each record becomes a <record> element under the root element, with one
+/// child element per field. Text is escaped by quick-xml; null values become
+/// empty elements and nested values (not produced by the converter) are written as JSON text.
+/// 
+/// # Arguments
+/// * `json_array` - Array of JSON objects to serialize
+/// * `root` - Name of the root element (sanitized like field names)
+/// 
+/// # Returns
+/// A Result containing the XML document text
+/// 
+/// # Example
+/// <?xml version="1.0" encoding="UTF-8"?>
+/// <records>
+///   <record>
+///     <name>John</name>
+///     <age>25</age>
+///   </record>
+/// </records>
+fn to_xml_document(json_array: &[Value], root: &str, comment: Option<&str>) -> Result<String> {
+    use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+
+    let mut writer = quick_xml::Writer::new_with_indent(Vec::new(), b' ', 2);
+    let mut write = |event: Event| {
+        writer
+            .write_event(event)
+            .context(ConversionError::Write("Failed to serialize XML".to_string()))
+    };
+
+    let root = xml_element_name(root);
+    write(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    if let Some(comment) = comment {
+        // "--" may not appear inside an XML comment
+        let comment = format!(" {} ", comment.replace("--", "- -"));
+        write(Event::Comment(BytesText::from_escaped(comment)))?;
+    }
+    write(Event::Start(BytesStart::new(root.as_str())))?;
+    for record in json_array {
+        write(Event::Start(BytesStart::new("record")))?;
+        for (key, value) in record.as_object().into_iter().flatten() {
+            let name = xml_element_name(key);
+            let text = match value {
+                Value::Null => {
+                    write(Event::Empty(BytesStart::new(name.as_str())))?; // <key/>
+                    continue;
+                }
+                Value::String(s) => s.clone(),
+                other => other.to_string(), // Numbers and booleans (and nested values as JSON)
+            };
+            write(Event::Start(BytesStart::new(name.as_str())))?;
+            write(Event::Text(BytesText::new(&text)))?; // Escapes <, & and friends
+            write(Event::End(BytesEnd::new(name.as_str())))?;
+        }
+        write(Event::End(BytesEnd::new("record")))?;
+    }
+    write(Event::End(BytesEnd::new(root.as_str())))?;
+
+    let mut xml = String::from_utf8(writer.into_inner())
+        .context(ConversionError::Write("Failed to serialize XML".to_string()))?;
+    xml.push('\n');
+    Ok(xml)
+}
+
+/// Replaces every non-ASCII character in serialized JSON with \uXXXX escapes
+/// 
+/// Outside of strings JSON is pure ASCII, so escaping the whole text only touches
+/// keys and string values. Characters beyond U+FFFF are written as a UTF-16
+/// surrogate pair, as JSON requires.
+/// 
+/// # Examples
+/// - "café" -> "caf\u00e9"
+/// - "😀" -> "\ud83d\ude00"
+fn escape_non_ascii(json_text: &str) -> String {
+    let mut escaped = String::with_capacity(json_text.len());
+    for c in json_text.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            // One escape per UTF-16 code unit (two for a surrogate pair)
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+/// Formats a float in decimal notation, as --no-scientific writes numbers
+/// 
+/// Display prints the shortest digits that read back as the same float and never
+/// uses an exponent; whole numbers keep the ".0" serde_json writes for floats.
+/// 
+/// # Examples
+/// - 1e-7 -> "0.0000001"
+/// - 1.5e21 -> "1500000000000000000000.0"
+/// - 2.5 -> "2.5"
+fn plain_decimal(value: f64) -> String {
+    let mut text = value.to_string();
+    if !text.contains('.') {
+        text.push_str(".0");
+    }
+    text
+}
+
+/// serde_json formatter that writes floats with plain_decimal() (--no-scientific)
+/// 
+/// Everything else, including the pretty-printing layout, is left to the wrapped formatter.
+struct PlainFloatFormatter<F>(F);
+
+impl<F: serde_json::ser::Formatter> serde_json::ser::Formatter for PlainFloatFormatter<F> {
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> std::io::Result<()> {
+        writer.write_all(plain_decimal(value).as_bytes())
+    }
+
+    fn write_f32<W: ?Sized + Write>(&mut self, writer: &mut W, value: f32) -> std::io::Result<()> {
+        self.write_f64(writer, value as f64)
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.0.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(&mut self, writer: &mut W, first: bool) -> std::io::Result<()> {
+        self.0.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+        self.0.end_object_value(writer)
+    }
+}
+
+/// Serializes records as a MessagePack array of maps
+/// 
+/// The structure mirrors the JSON output: one map per record with the same keys,
+/// strings stay strings and inferred numbers/booleans/nulls keep their types.
+/// 
+/// # Returns
+/// A Result containing the binary document
+fn to_msgpack_document(json_array: &[Value]) -> Result<Vec<u8>> {
+    rmp_serde::to_vec_named(json_array)
+        .context(ConversionError::Write("Failed to serialize MessagePack".to_string()))
+}
+
+/// Checks whether a file is empty or ends with a newline
+fn ends_with_newline(file: &mut File) -> Result<bool> {
+    let len = file
+        .metadata()
+        .context(ConversionError::Write("Failed to read output file".to_string()))?
+        .len();
+    if len == 0 {
+        return Ok(true); // Nothing to separate from
+    }
+
+    // Look at the last byte only
+    let mut last = [0u8; 1];
+    file.seek(std::io::SeekFrom::End(-1))
+        .and_then(|_| file.read_exact(&mut last))
+        .context(ConversionError::Write("Failed to read output file".to_string()))?;
+    Ok(last[0] == b'\n')
+}
+
+/// Creates a hidden temporary file next to the output path for an atomic write
+/// 
+/// The file gets the permissions a newly created output file would get (or those
+/// of the file it will replace), not the owner-only default of temporary files.
+/// 
+/// # Errors
+/// - Returns error if the file can't be created (e.g. the directory doesn't exist)
+fn create_temp_output(output: &Path) -> Result<tempfile::NamedTempFile> {
+    let dir = match output.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".excel2json-").suffix(".tmp");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o666)); // Narrowed by the umask, like File::create
+    }
+    let temp = builder
+        .tempfile_in(dir)
+        .context(ConversionError::Write(format!("Failed to create output file: {:?}", output)))?;
+
+    // Replacing an existing file keeps its permissions
+    if let Ok(metadata) = fs::metadata(output) {
+        fs::set_permissions(temp.path(), metadata.permissions())
+            .context(ConversionError::Write(format!("Failed to create output file: {:?}", output)))?;
+    }
+    Ok(temp)
+}
+
+/// UTF-8 byte order mark written before text output with --bom
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Whether an output path means standard output (--output -)
+fn is_stdout(output: &Path) -> bool {
+    output == Path::new("-")
+}
+
+/// Writes records to the output file as they are produced
+/// 
+/// JSON arrays and NDJSON are written record by record through a buffered writer,
+/// so the records of a large sheet never have to be held in memory at once; the
+/// JSON array comes out byte-for-byte as serde_json::to_string_pretty() would
+/// print the whole array. TOML, XML and MessagePack are single documents (MessagePack
+/// starts with the record count), so their records are kept until finish().
+struct RecordWriter {
+    /// Buffered output: a temporary file next to the target unless appending, or stdout
+    out: BufWriter<Box<dyn Write>>,
+    /// Temporary file renamed over `output` once everything is written; deleted if dropped before
+    temp_path: Option<tempfile::TempPath>,
+    /// Final output path
+    output: PathBuf,
+    /// Output format options
+    out_opts: OutputOptions,
+    /// Number of records written so far
+    count: usize,
+    /// Records held back for the document formats (TOML, XML, MessagePack)
+    pending: Vec<Value>,
+}
+
+impl RecordWriter {
+    /// Creates (or with --append, opens) the output file
+    /// 
+    /// With --append the records are added to the end of an existing file; if that
+    /// file doesn't end with a newline, one is written first so records don't merge
+    /// onto its last line.
+    /// 
+    /// With --bom a text output file starts with a UTF-8 byte order mark; an
+    /// appended-to file that already has content gets none.
+    /// 
+    /// An output path of "-" writes to standard output instead (see is_stdout()).
+    /// 
+    /// # Errors
+    /// - Returns error if file cannot be created or opened
+    fn create(output: &Path, out_opts: &OutputOptions) -> Result<Self> {
+        if is_stdout(output) {
+            let out: Box<dyn Write> = Box::new(std::io::stdout());
+            return Self::with_output(out, None, true, output, out_opts);
+        }
+        let mut temp_path = None;
+        let file = if out_opts.append {
+            // Open for appending (creates the file if it doesn't exist yet)
+            let mut file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(output)
+                .context(ConversionError::Write(format!("Failed to open output file: {:?}", output)))?;
+            if !ends_with_newline(&mut file)? {
+                file.write_all(b"\n")
+                    .context(ConversionError::Write("Failed to write to output file".to_string()))?;
+            }
+            file
+        } else {
+            // Write to a temporary file in the same directory; finish() renames it over the
+            // target, so readers never see a half-written file and a failed run leaves the old one
+            let temp = create_temp_output(output)?;
+            let (file, path) = temp.into_parts();
+            temp_path = Some(path);
+            file
+        };
+
+        // The byte order mark belongs at the very start of a text file only
+        let is_empty = file
+            .metadata()
+            .context(ConversionError::Write("Failed to read output file".to_string()))?
+            .len()
+            == 0;
+        Self::with_output(Box::new(file), temp_path, is_empty, output, out_opts)
+    }
+
+    /// Wraps an opened output, starting it with the --bom byte order mark if it's empty
+    fn with_output(
+        out: Box<dyn Write>,
+        temp_path: Option<tempfile::TempPath>,
+        is_empty: bool,
+        output: &Path,
+        out_opts: &OutputOptions,
+    ) -> Result<Self> {
+        let mut writer = RecordWriter {
+            out: BufWriter::with_capacity(out_opts.buffer_size, out),
+            temp_path,
+            output: output.to_path_buf(),
+            out_opts: out_opts.clone(),
+            count: 0,
+            pending: Vec::new(),
+        };
+        if out_opts.bom && is_empty && out_opts.format != OutputFormat::Msgpack {
+            writer.write_bytes(UTF8_BOM)?;
+        }
+        Ok(writer)
+    }
+
+    /// Writes raw text to the output
+    fn write_text(&mut self, text: &str) -> Result<()> {
+        self.write_bytes(text.as_bytes())
+    }
+
+    /// Writes raw bytes to the output
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out
+            .write_all(bytes)
+            .context(ConversionError::Write("Failed to write to output file".to_string()))
+    }
+
+    /// Applies --ensure-ascii to serialized JSON text
+    fn ascii_safe(&self, json_text: String) -> String {
+        if self.out_opts.ensure_ascii {
+            escape_non_ascii(&json_text)
+        } else {
+            json_text
+        }
+    }
+
+    /// Serializes a value as JSON text, pretty-printed or compact, applying --no-scientific
+    fn json_text(&self, value: &Value, pretty: bool) -> Result<String> {
+        let text = if !self.out_opts.no_scientific {
+            if pretty {
+                serde_json::to_string_pretty(value)
+            } else {
+                serde_json::to_string(value)
+            }
+        } else {
+            let mut bytes = Vec::new();
+            let written = if pretty {
+                let formatter = PlainFloatFormatter(serde_json::ser::PrettyFormatter::new());
+                value.serialize(&mut serde_json::Serializer::with_formatter(&mut bytes, formatter))
+            } else {
+                let formatter = PlainFloatFormatter(serde_json::ser::CompactFormatter);
+                value.serialize(&mut serde_json::Serializer::with_formatter(&mut bytes, formatter))
+            };
+            written.map(|()| String::from_utf8(bytes).expect("serde_json writes UTF-8"))
+        };
+        text.context(ConversionError::Write("Failed to serialize JSON".to_string()))
+    }
+
+    /// Writes one record (or holds it back for TOML/XML/MessagePack)
+    fn write(&mut self, record: Value) -> Result<()> {
+        match self.out_opts.format {
+            OutputFormat::Json => {
+                // "[" before the first record, "," between records, each indented one level
+                // (two inside the --wrap-key object)
+                let indent = if self.out_opts.wrap_key.is_some() { "\n    " } else { "\n  " };
+                let pretty = self.json_text(&record, true)?;
+                let separator = if self.count == 0 {
+                    format!("{}[{}", self.json_wrapper_start()?, indent)
+                } else {
+                    format!(",{}", indent)
+                };
+                self.write_text(&separator)?;
+                self.write_text(&self.ascii_safe(pretty).replace('\n', indent))?;
+            }
+            OutputFormat::Ndjson => {
+                // One compact object per line, each line terminated by a newline
+                let line = self.json_text(&record, false)?;
+                self.write_text(&self.ascii_safe(line))?;
+                self.write_text("\n")?;
+            }
+            OutputFormat::Toml | OutputFormat::Xml | OutputFormat::Msgpack => self.pending.push(record),
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Opening of the --wrap-key object up to the array: "{\n  \"data\": " (nothing without it),
+    /// with the --comment-header "_meta" object first
+    fn json_wrapper_start(&self) -> Result<String> {
+        let Some(key) = &self.out_opts.wrap_key else {
+            return Ok(String::new());
+        };
+        let key = serde_json::to_string(key).context(ConversionError::Write("Failed to serialize JSON".to_string()))?;
+        let meta = match &self.out_opts.provenance {
+            Some(provenance) => {
+                let pretty = serde_json::to_string_pretty(&provenance.to_json())
+                    .context(ConversionError::Write("Failed to serialize JSON".to_string()))?;
+                format!("\n  \"_meta\": {},", self.ascii_safe(pretty).replace('\n', "\n  "))
+            }
+            None => String::new(),
+        };
+        Ok(format!("{{{}\n  {}: ", meta, self.ascii_safe(key)))
+    }
+
+    /// End of the JSON document after the last record, as serde_json would pretty-print it
+    fn json_tail(&self) -> Result<String> {
+        if self.out_opts.wrap_key.is_none() {
+            return Ok(if self.count == 0 { "[]" } else { "\n]" }.to_string());
+        }
+        let mut tail = if self.count == 0 {
+            format!("{}[]", self.json_wrapper_start()?)
+        } else {
+            "\n  ]".to_string()
+        };
+        if self.out_opts.wrap_count {
+            tail.push_str(&format!(",\n  \"count\": {}", self.count));
+        }
+        tail.push_str("\n}");
+        Ok(tail)
+    }
+
+    /// Writes a complete JSON value as the whole document instead of a record array
+    /// (used for --group-by, whose output is an object of arrays)
+    fn write_document(mut self, document: &Value) -> Result<()> {
+        let pretty = self.json_text(document, true)?;
+        let text = self.ascii_safe(pretty);
+        self.write_text(&text)?;
+        self.commit()
+    }
+
+    /// Flushes the output and moves the temporary file into place
+    /// 
+    /// # Errors
+    /// - Returns error if flushing or renaming fails (the temporary file is then removed)
+    fn commit(mut self) -> Result<()> {
+        self.out
+            .flush()
+            .context(ConversionError::Write("Failed to write to output file".to_string()))?;
+        if let Some(temp_path) = self.temp_path.take() {
+            temp_path
+                .persist(&self.output)
+                .context(ConversionError::Write(format!("Failed to replace output file: {:?}", self.output)))?;
+        }
+        Ok(())
+    }
+
+    /// Completes the document and flushes it to disk
+    /// 
+    /// # Returns
+    /// The number of records written
+    fn finish(mut self) -> Result<usize> {
+        let tail = match self.out_opts.format {
+            OutputFormat::Json => self.json_tail()?.into_bytes(),
+            OutputFormat::Ndjson => Vec::new(),
+            OutputFormat::Toml => {
+                let mut text = String::new();
+                for line in self.out_opts.provenance.iter().flat_map(Provenance::lines) {
+                    text.push_str(&format!("# {}\n", line));
+                }
+                text.push_str(&to_toml_document(&self.pending, &self.out_opts.toml_key)?);
+                text.into_bytes()
+            }
+            OutputFormat::Xml => {
+                let comment = self.out_opts.provenance.as_ref().map(|p| p.lines().join(", "));
+                to_xml_document(&self.pending, &self.out_opts.xml_root, comment.as_deref())?.into_bytes()
+            }
+            OutputFormat::Msgpack => to_msgpack_document(&self.pending)?,
+        };
+        self.write_bytes(&tail)?;
+        let count = self.count;
+        self.commit()?;
+        Ok(count)
+    }
+}
+
+/// Writes the records to a file in the requested output format
+/// (pretty-printed JSON by default)
+/// 
+/// # Arguments
+/// * `records` - JSON values to write
+/// * `output` - Path where the output file should be created
+/// * `out_opts` - Output format options
+/// 
+/// # Returns
+/// The number of records written
+/// 
+/// # Errors
+/// - Returns error if serialization fails
+/// - Returns error if file cannot be created
+/// - Returns error if writing to file fails
+fn write_json_to_file(
+    records: impl IntoIterator<Item = Value>,
+    output: &Path,
+    out_opts: &OutputOptions,
+) -> Result<usize> {
+    let mut writer = ChunkedWriter::create(output, out_opts)?;
+    for record in records {
+        writer.write(record)?;
+    }
+    writer.finish()
+}
+
+/// Writes records to the output file, or with --chunk-size to numbered chunk files
+/// 
+/// Each chunk is a complete document of its own in the output format. A new
+/// chunk file is only started when a record doesn't fit into the previous one,
+/// so no empty trailing file is written; without any records one empty chunk is.
+struct ChunkedWriter {
+    /// Output path the chunk file names are derived from
+    output: PathBuf,
+    /// Output format options
+    out_opts: OutputOptions,
+    /// Writer of the current file
+    current: Option<RecordWriter>,
+    /// Number of chunk files started so far
+    chunks: usize,
+    /// Number of records written so far
+    count: usize,
+}
+
+impl ChunkedWriter {
+    /// Creates the output file (or, with --chunk-size, the first chunk file)
+    /// 
+    /// # Errors
+    /// - Returns error if the file cannot be created
+    fn create(output: &Path, out_opts: &OutputOptions) -> Result<Self> {
+        let mut writer = ChunkedWriter {
+            output: output.to_path_buf(),
+            out_opts: out_opts.clone(),
+            current: None,
+            chunks: 0,
+            count: 0,
+        };
+        writer.start_chunk()?;
+        Ok(writer)
+    }
+
+    /// Starts the next file, completing the current one first
+    fn start_chunk(&mut self) -> Result<()> {
+        if let Some(writer) = self.current.take() {
+            writer.finish()?;
+        }
+        self.chunks += 1;
+        let path = match self.out_opts.chunk_size {
+            Some(_) => chunk_path(&self.output, self.chunks),
+            None => self.output.clone(),
+        };
+        self.current = Some(RecordWriter::create(&path, &self.out_opts)?);
+        Ok(())
+    }
+
+    /// Writes one record, moving on to a new chunk file when the current one is full
+    fn write(&mut self, record: Value) -> Result<()> {
+        if let Some(size) = self.out_opts.chunk_size
+            && self.count > 0
+            && self.count.is_multiple_of(size)
+        {
+            self.start_chunk()?;
+        }
+        self.current.as_mut().expect("an open output file").write(record)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Completes the last file
+    /// 
+    /// # Returns
+    /// The number of records written
+    fn finish(mut self) -> Result<usize> {
+        if let Some(writer) = self.current.take() {
+            writer.finish()?;
+        }
+        Ok(self.count)
+    }
+}
+
+/// Path of a --chunk-size output file: the output stem plus a 1-based, 4-digit number
+/// 
+/// # Examples
+/// - ("out/result.json", 1) -> "out/result_0001.json"
+/// - ("records", 12) -> "records_0012"
+fn chunk_path(output: &Path, chunk: usize) -> PathBuf {
+    let stem = output.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match output.extension() {
+        Some(ext) => format!("{}_{:04}.{}", stem, chunk, ext.to_string_lossy()),
+        None => format!("{}_{:04}", stem, chunk),
+    };
+    output.with_file_name(name)
+}
+
+/// Number of files --chunk-size writes for a record count (at least one)
+fn chunk_count(records: usize, chunk_size: usize) -> usize {
+    records.div_ceil(chunk_size).max(1)
+}
+
+/// Reads the per-cell annotations requested on the command line
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `input` - The input file the sheet was read from
+/// * `sheet_name` - Actual name of the sheet being converted
+/// * `range` - The sheet's cell range (its start anchors the annotation positions)
+/// 
+/// # Returns
+/// The annotation layers (empty if no annotation option was given)
+fn read_cell_annotations(
+    args: &Args,
+    input: &InputFile,
+    sheet_name: &str,
+    range: &calamine::Range<calamine::Data>,
+) -> Result<CellAnnotations> {
+    // Data rows start right below the header row(s) at the top of the range
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+    let mut annotations = CellAnnotations {
+        origin: (first_row + args.header_rows as u32, first_col),
+        layers: Vec::new(),
+    };
+    if !args.with_hyperlinks && !args.with_comments && !args.with_formats {
+        return Ok(annotations);
+    }
+
+    let mut parts = XlsxParts::open(&input.file, input.zip_entry.as_deref())?;
+    if args.with_hyperlinks {
+        annotations.layers.push(("url", parts.sheet_hyperlinks(sheet_name)?));
+    }
+    if args.with_comments {
+        annotations.layers.push(("comment", parts.sheet_comments(sheet_name)?));
+    }
+    if args.with_formats {
+        annotations.layers.push(("format", parts.sheet_formats(sheet_name)?));
+    }
+    Ok(annotations)
+}
+
+/// Swaps the rows and columns of a sheet for --transpose
+/// 
+/// The first column (field names) becomes the header row and every following
+/// column becomes one data row, so the regular row conversion can be reused.
+/// 
+/// # Arguments
+/// * `sheet_name` - Name of the sheet (for error messages)
+/// * `range` - The sheet's cells in key-value layout
+/// 
+/// # Returns
+/// A new Range where cell (row, col) holds the original cell (col, row)
+/// 
+/// # Errors
+/// Returns error if the sheet has fewer than two columns (keys and at least one record)
+fn transpose_range(
+    sheet_name: &str,
+    range: &calamine::Range<calamine::Data>,
+) -> Result<calamine::Range<calamine::Data>> {
+    let (height, width) = range.get_size();
+    if width < 2 {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--transpose needs at least two columns (keys and values) but sheet '{}' has {}",
+            sheet_name, width
+        ))
+        .into());
+    }
+
+    // Keep the swapped start position so the range still describes real sheet cells
+    let (first_row, first_col) = range.start().unwrap_or((0, 0));
+    let mut transposed = calamine::Range::new(
+        (first_col, first_row),
+        (first_col + width as u32 - 1, first_row + height as u32 - 1),
+    );
+    for (row_idx, row) in range.rows().enumerate() {
+        for (col_idx, cell) in row.iter().enumerate() {
+            transposed.set_value((first_col + col_idx as u32, first_row + row_idx as u32), cell.clone());
+        }
+    }
+    Ok(transposed)
+}
+
+/// A sheet (or table) that has been read and whose columns are resolved,
+/// ready to have its data rows converted
+struct PreparedSheet {
+    /// Actual name of the sheet
+    sheet_name: String,
+    /// The sheet's cells, header row(s) included (transposed with --transpose)
+    range: calamine::Range<calamine::Data>,
+    /// Number of header rows at the top of the range (--header-rows)
+    header_rows: usize,
+    /// Normalized column headers used as JSON keys
+    headers: Vec<String>,
+    /// The keys as written (--key-style), aligned with `headers`
+    keys: Vec<String>,
+    /// Actual (0-based) column indices selected for output
+    column_indices: Vec<usize>,
+    /// Header text of the selected columns as in the sheet (multi-row headers joined)
+    original_headers: Vec<String>,
+    /// 1-based visible column number of each selected column (what --columns counts)
+    visible_numbers: Vec<usize>,
+    /// Extra per-cell values (hyperlinks, comments)
+    annotations: CellAnnotations,
+    /// Absolute indices of rows hidden in Excel, skipped with --only-visible-rows
+    hidden_rows: HashSet<u32>,
+    /// Time spent opening the workbook and reading the sheet
+    read_time: Duration,
+    /// With --lazy-read, the data rows still to come (`range` holds just the header)
+    lazy_rows: Option<LazyRows>,
+}
+
+impl PreparedSheet {
+    /// Number of data rows below the header
+    /// 
+    /// With --lazy-read the rows haven't been read yet, so they're counted from the
+    /// sheet's declared dimension.
+    fn data_row_count(&self) -> usize {
+        match &self.lazy_rows {
+            Some(lazy) => {
+                let first_data_row = self.range.start().map_or(0, |(row, _)| row) + self.header_rows as u32;
+                (lazy.last_row + 1).saturating_sub(first_data_row) as usize
+            }
+            None => self.range.height().saturating_sub(self.header_rows),
+        }
+    }
+
+    /// Converts the data rows lazily, one JSON object per row
+    /// 
+    /// With --lazy-read the rows come from the reading thread and can only be
+    /// converted once.
+    /// 
+    /// # Arguments
+    /// * `opts` - Cell conversion options
+    /// * `progress` - Progress bar advanced once per data row
+    /// * `stats` - Counters updated while converting cells
+    fn records<'a>(
+        &'a self,
+        opts: &'a ConvertOptions,
+        progress: &ProgressBar,
+        stats: &'a mut ConvertStats,
+    ) -> impl Iterator<Item = Value> + 'a {
+        let first_data_row = self.range.start().map_or(0, |(row, _)| row) + self.header_rows as u32;
+        let width = self.range.width();
+        let rows: Box<dyn Iterator<Item = Cow<'a, [calamine::Data]>> + 'a> =
+            match self.lazy_rows.as_ref().and_then(|lazy| Some((lazy, lazy.receiver.take()?))) {
+                // Streamed rows are cut or padded to the header's width, like the rows of a Range;
+                // rows with values beyond the header are noted first (see check_row_widths())
+                Some((lazy, receiver)) => Box::new(receiver.into_iter().map(move |(row, mut cells)| {
+                    let found = effective_width(&cells);
+                    if found > lazy.header_width {
+                        lazy.ragged.borrow_mut().push((row + 1, found));
+                    }
+                    cells.resize(width, calamine::Data::Empty);
+                    Cow::Owned(cells)
+                })),
+                None => Box::new(self.range.rows().skip(self.header_rows).map(Cow::Borrowed)),
+            };
+        let rows = progress
+            .wrap_iter(rows.enumerate())
+            .filter(move |(row_idx, _)| !self.hidden_rows.contains(&(first_data_row + *row_idx as u32)));
+        convert_rows_to_json(rows, &self.headers, &self.column_indices, opts, &self.annotations, stats)
+    }
+
+    /// Lines the sheet's columns up with another sheet's headers (--consolidate)
+    /// 
+    /// Afterwards the sheet has exactly `headers`, in that order: columns of its own
+    /// with other names are dropped, and headers it has no column for read as
+    /// missing cells (null). `keys` are the written keys of `headers`.
+    /// 
+    /// # Returns
+    /// The (missing, extra) headers compared to `headers`
+    fn conform_to(&mut self, headers: &[String], keys: &[String]) -> (Vec<String>, Vec<String>) {
+        let extra: Vec<String> = self.headers.iter().filter(|header| !headers.contains(header)).cloned().collect();
+        let missing: Vec<String> = headers.iter().filter(|header| !self.headers.contains(header)).cloned().collect();
+
+        // A column past the end of the range has no cells in any row
+        let no_column = self.range.width();
+        let mut column_indices = Vec::with_capacity(headers.len());
+        let mut original_headers = Vec::with_capacity(headers.len());
+        let mut visible_numbers = Vec::with_capacity(headers.len());
+        for header in headers {
+            match self.headers.iter().position(|own| own == header) {
+                Some(position) => {
+                    column_indices.push(self.column_indices[position]);
+                    original_headers.push(self.original_headers[position].clone());
+                    visible_numbers.push(self.visible_numbers[position]);
+                }
+                None => {
+                    column_indices.push(no_column);
+                    original_headers.push(String::new());
+                    visible_numbers.push(0);
+                }
+            }
+        }
+        self.headers = headers.to_vec();
+        self.keys = keys.to_vec();
+        self.column_indices = column_indices;
+        self.original_headers = original_headers;
+        self.visible_numbers = visible_numbers;
+        (missing, extra)
+    }
+
+    /// Counts the cell types of each selected column over the data rows (--column-types)
+    /// 
+    /// Rows skipped by --only-visible-rows are not counted.
+    /// 
+    /// # Returns
+    /// One set of counts per selected column, in `headers` order
+    fn column_types(&self) -> Vec<ColumnTypeCounts> {
+        let first_data_row = self.range.start().map_or(0, |(row, _)| row) + self.header_rows as u32;
+        let mut counts = vec![ColumnTypeCounts::default(); self.column_indices.len()];
+        for (row_idx, row) in self.range.rows().skip(self.header_rows).enumerate() {
+            if self.hidden_rows.contains(&(first_data_row + row_idx as u32)) {
+                continue;
+            }
+            for (column, &col_idx) in counts.iter_mut().zip(&self.column_indices) {
+                column.add(row.get(col_idx));
+            }
+        }
+        counts
+    }
+}
+
+/// How many cells of a column hold each kind of value, as stored in the file (--column-types)
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+struct ColumnTypeCounts {
+    /// Integer and floating-point cells
+    numeric: usize,
+    /// Text cells, including text that looks like a number
+    string: usize,
+    /// TRUE/FALSE cells
+    boolean: usize,
+    /// Date, time and duration cells
+    date: usize,
+    /// Blank cells (and cells missing from short rows)
+    empty: usize,
+    /// Error values such as #DIV/0! or #N/A
+    error: usize,
+}
+
+impl ColumnTypeCounts {
+    /// Counts one cell (None for a cell beyond the end of its row)
+    fn add(&mut self, cell: Option<&calamine::Data>) {
+        match cell {
+            Some(calamine::Data::Int(_) | calamine::Data::Float(_)) => self.numeric += 1,
+            Some(calamine::Data::String(text)) if !text.is_empty() => self.string += 1,
+            Some(calamine::Data::Bool(_)) => self.boolean += 1,
+            Some(calamine::Data::DateTime(_) | calamine::Data::DateTimeIso(_) | calamine::Data::DurationIso(_)) => {
+                self.date += 1
+            }
+            Some(calamine::Data::Error(_)) => self.error += 1,
+            Some(calamine::Data::Empty | calamine::Data::String(_)) | None => self.empty += 1,
+        }
+    }
+
+    /// Adds the counts of the same column from another sheet
+    fn merge(&mut self, other: &ColumnTypeCounts) {
+        self.numeric += other.numeric;
+        self.string += other.string;
+        self.boolean += other.boolean;
+        self.date += other.date;
+        self.empty += other.empty;
+        self.error += other.error;
+    }
+}
+
+impl fmt::Display for ColumnTypeCounts {
+    /// Lists the non-zero counts, e.g. "2 numeric, 1 string"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = [
+            (self.numeric, "numeric"),
+            (self.string, "string"),
+            (self.boolean, "boolean"),
+            (self.date, "date"),
+            (self.empty, "empty"),
+            (self.error, "error"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{} {}", count, kind))
+            .collect();
+        if parts.is_empty() {
+            write!(f, "no cells")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
+
+/// One output file written by --split-sheets, listed by --emit-manifest
+struct SheetOutput {
+    /// Sheet the records came from
+    sheet: String,
+    /// Path of the file (before --chunk-size splits it)
+    path: PathBuf,
+    /// Number of records written to it
+    records: usize,
+}
+
+/// Writes the list of files --split-sheets produced as a JSON file (--emit-manifest)
+/// 
+/// With --chunk-size each chunk file is listed with the records it holds.
+/// 
+/// # Arguments
+/// * `path` - File to write
+/// * `outputs` - The files written, one per sheet
+/// * `chunk_size` - Records per chunk file (--chunk-size), if the files were split
+/// 
+/// # Errors
+/// Returns error if the file cannot be written
+/// 
+/// # Example
+/// [{"sheet": "Summary", "file": "out/data_Summary.json", "records": 12}]
+fn write_manifest(path: &Path, outputs: &[SheetOutput], chunk_size: Option<usize>) -> Result<()> {
+    let mut entries = Vec::new();
+    for output in outputs {
+        match chunk_size {
+            Some(size) => {
+                for chunk in 1..=chunk_count(output.records, size) {
+                    let records = output.records.saturating_sub((chunk - 1) * size).min(size);
+                    entries.push(json!({"sheet": output.sheet, "file": chunk_path(&output.path, chunk), "records": records}));
+                }
+            }
+            None => entries.push(json!({"sheet": output.sheet, "file": output.path, "records": output.records})),
+        }
+    }
+    let text = serde_json::to_string_pretty(&entries)
+        .context(ConversionError::Write("Failed to serialize the manifest".to_string()))?;
+    fs::write(path, text + "\n").context(ConversionError::Write(format!("Failed to write manifest file: {:?}", path)))
+}
+
+/// Writes the header-to-key mapping of the converted sheets as a JSON file (--emit-mapping)
+/// 
+/// # Arguments
+/// * `path` - File to write
+/// * `sheets` - The converted sheets
+/// 
+/// # Errors
+/// Returns error if the file cannot be written
+/// 
+/// # Example
+/// [{"sheet": "Sheet1", "original": "First Name", "normalized": "first_name", "visible_number": 1, "actual_index": 0}]
+fn write_column_mapping(path: &Path, sheets: &[SheetConversion]) -> Result<()> {
+    let entries: Vec<Value> = sheets
+        .iter()
+        .flat_map(|sheet| {
+            sheet
+                .keys
+                .iter()
+                .zip(&sheet.original_headers)
+                .zip(sheet.visible_numbers.iter().zip(&sheet.column_indices))
+                .filter(|(_, (visible_number, _))| **visible_number > 0) // Columns a sheet lacks (--consolidate)
+                .map(|((key, original), (visible_number, actual_index))| {
+                    json!({
+                        "sheet": sheet.sheet_name,
+                        "original": original,
+                        "normalized": key,
+                        "visible_number": visible_number,
+                        "actual_index": actual_index,
+                    })
+                })
+        })
+        .collect();
+    let text = serde_json::to_string_pretty(&entries)
+        .context(ConversionError::Write("Failed to serialize the column mapping".to_string()))?;
+    fs::write(path, text + "\n")
+        .context(ConversionError::Write(format!("Failed to write column mapping file: {:?}", path)))
+}
+
+/// Adds up the --column-types counts of all sheets by column name, in first-seen order
+fn column_type_summary(sheets: &[SheetConversion]) -> Vec<(String, ColumnTypeCounts)> {
+    let mut summary: Vec<(String, ColumnTypeCounts)> = Vec::new();
+    for sheet in sheets {
+        for (header, counts) in sheet.headers.iter().zip(&sheet.column_types) {
+            match summary.iter_mut().find(|(name, _)| name == header) {
+                Some((_, total)) => total.merge(counts),
+                None => summary.push((header.clone(), *counts)),
+            }
+        }
+    }
+    summary
+}
+
+/// Summary of one converted sheet (or table)
+struct SheetConversion {
+    /// Actual name of the sheet the records came from
+    sheet_name: String,
+    /// Normalized column headers used as JSON keys
+    headers: Vec<String>,
+    /// The keys as written (--key-style), aligned with `headers`
+    keys: Vec<String>,
+    /// Actual (0-based) column indices selected for output
+    column_indices: Vec<usize>,
+    /// Header text of the selected columns as in the sheet (multi-row headers joined)
+    original_headers: Vec<String>,
+    /// 1-based visible column number of each selected column (what --columns counts)
+    visible_numbers: Vec<usize>,
+    /// Number of records converted from this sheet
+    record_count: usize,
+    /// Time spent opening the workbook and reading the sheet
+    read_time: Duration,
+    /// Time spent converting the rows to JSON objects
+    convert_time: Duration,
+    /// Cell-level counters (truncated cells, ...)
+    stats: ConvertStats,
+    /// Cell types per selected column, aligned with `headers` (empty without --column-types)
+    column_types: Vec<ColumnTypeCounts>,
+}
+
+/// Creates the progress bar shown while converting a sheet's rows
+/// 
+/// The bar is only drawn for sheets above PROGRESS_ROW_THRESHOLD rows, when
+/// stderr is a terminal and --quiet is not set, so it never ends up in logs or pipes.
+/// Otherwise a hidden bar is returned, which ignores all updates.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `row_count` - Number of data rows that will be converted
+fn conversion_progress(args: &Args, row_count: usize) -> ProgressBar {
+    if args.quiet || row_count <= PROGRESS_ROW_THRESHOLD || !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    // ProgressBar::new draws to stderr
+    let bar = ProgressBar::new(row_count as u64);
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} [{bar:40}] {pos}/{len} rows ({eta} left)")
+            .expect("progress template is valid")
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+/// Reads one sheet (or table) and resolves its columns
+/// 
+/// Process flow:
+/// 1. Open Excel file and read the selected sheet
+/// 2. Identify visible columns (non-empty headers)
+/// 3. Parse user-specified column selection (if provided)
+/// 4. Extract and normalize column headers
+/// 5. Read annotations and hidden rows from the raw xlsx parts if requested
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `input` - The input file to read the sheet from
+/// * `selector` - Which sheet or table to read
+/// 
+/// # Returns
+/// The prepared sheet, or None if the sheet has no cells at all (not even a header row)
+fn prepare_sheet(args: &Args, input: &InputFile, selector: &SheetSelector) -> Result<Option<PreparedSheet>> {
+    // Step 1: Open Excel file and read the specified sheet
+    let read_started = Instant::now();
+    let (sheet_name, mut range, mut lazy_rows) = with_open_retries(args, || {
+        if args.lazy_read {
+            // Only the header is read now, the data rows follow while converting
+            read_sheet_lazily(&input.file, input.zip_entry.as_deref(), selector, args.header_rows)
+        } else {
+            let (sheet_name, range) = read_excel_sheet(&input.file, input.zip_entry.as_deref(), selector, args.formulas)?;
+            Ok((sheet_name, range, None))
+        }
+    })?;
+    if args.use_print_area {
+        match read_print_area(&input.file, input.zip_entry.as_deref(), &sheet_name)? {
+            Some(area) => range = slice_to_area(&range, area),
+            None if !args.quiet => {
+                eprintln!("Warning: sheet '{}' has no print area, converting the whole sheet", sheet_name);
+            }
+            None => {}
+        }
+    }
+    if let Some(sheet_range) = &args.range
+        && let (Some(start), Some(end)) = (range.start(), range.end())
+    {
+        range = slice_to_area(&range, sheet_range.area);
+        if range.is_empty() {
+            anyhow::bail!(
+                "Range {} has no cells on sheet '{}', which uses {}{}:{}{}",
+                sheet_range.reference,
+                sheet_name,
+                column_letters(start.1 as usize),
+                start.0 + 1,
+                column_letters(end.1 as usize),
+                end.0 + 1
+            );
+        }
+    }
+    if args.trim_sheet_whitespace_rows {
+        // Before the header is read, so the first non-empty row becomes the header
+        range = crop_empty_rows(&range);
+    }
+    if args.transpose && !range.is_empty() {
+        // Key-value layout: turn the key column into the header row
+        range = transpose_range(&sheet_name, &range)?;
+    }
+    let read_time = read_started.elapsed();
+
+    // The first row(s) form the header - without them there's nothing to convert
+    let header_rows: Vec<&[calamine::Data]> = range.rows().take(args.header_rows).collect();
+    if header_rows.is_empty() {
+        return Ok(None);
+    }
+    let combine = |header_rows: &[&[calamine::Data]]| {
+        if args.merge_header_with_parent {
+            combine_header_rows(header_rows, &args.parent_separator, true)
+        } else {
+            combine_header_rows(header_rows, &args.header_join, false)
+        }
+    };
+    // The header options are the library's (convert_sheet_iter() builds its keys the same way)
+    let opts = convert_options(args);
+    // Text is cleaned row by row (--normalize-unicode, then --split-case), before the rows are
+    // joined and normalized with --merge-header-with-parent
+    let cleaned_rows: Vec<Vec<calamine::Data>> = header_rows.iter().map(|row| clean_header_row(row, &opts)).collect();
+    let combined_header = combine(&cleaned_rows.iter().map(Vec::as_slice).collect::<Vec<_>>());
+    let header_row = combined_header.as_slice();
+    // The header as in the sheet, for --emit-mapping (differs from header_row with --split-case/--normalize-unicode)
+    let written_header = (args.split_case || args.normalize_unicode.is_some()).then(|| combine(&header_rows));
+
+    // Step 2: Identify which columns have non-empty headers (visible columns)
+    // With --include-blank-columns every column up to the last populated cell counts
+    let mut visible_indices = header_column_indices(header_row, range.width(), &opts);
+    if args.ignore_hidden_columns {
+        // Columns hidden in Excel count as if they had no header (indices are relative to the range)
+        let hidden = XlsxParts::open(&input.file, input.zip_entry.as_deref())?.sheet_hidden_columns(&sheet_name)?;
+        let first_col = range.start().map_or(0, |(_, col)| col);
+        visible_indices.retain(|&index| !hidden.contains(&(first_col + index as u32)));
+    }
+
+    // Step 3: Determine which columns to include in the output
+    // Either use user-specified columns or all visible columns
+    let column_indices: Vec<usize> = if let Some(ref cols_str) = args.columns {
+        // User specified specific columns - parse and validate them
+        // Header names in the list refer to the sheet's own (normalized) names, not --header-map keys
+        let visible_headers = extract_headers(header_row, &visible_indices, None);
+        let option = match &args.columns_file {
+            Some(path) => format!("--columns-file {:?}", path),
+            None => "--columns".to_string(),
+        };
+        parse_visible_column_numbers(cols_str, &visible_indices, &visible_headers).context(
+            ConversionError::InvalidColumns(format!(
+                "Invalid {} value '{}' for sheet '{}'",
+                option, cols_str, sheet_name
+            )),
+        )?
+    } else if args.columns_regex.is_some() {
+        // Only the columns matched by --columns-regex (added below)
+        Vec::new()
+    } else {
+        // No columns specified - use all visible columns
+        visible_indices.clone()
+    };
+    let column_indices = match &args.columns_regex {
+        Some(pattern) => {
+            let matched = match_columns_regex(pattern, header_row, &visible_indices);
+            if matched.is_empty() && !args.allow_empty_match {
+                return Err(ConversionError::InvalidColumns(format!(
+                    "--columns-regex '{}' matches no column of sheet '{}' (use --allow-empty-match to allow this)",
+                    pattern, sheet_name
+                ))
+                .into());
+            }
+            // Matches come after any --columns selection, without repeating a column
+            let mut column_indices = column_indices;
+            for index in matched {
+                if !column_indices.contains(&index) {
+                    column_indices.push(index);
+                }
+            }
+            column_indices
+        }
+        None => column_indices,
+    };
+
+    // Step 4: Extract and normalize the column headers (renamed via --header-map, prefixed
+    // by --numeric-header-prefix and --key-prefix)
+    let mut headers = header_keys(header_row, &column_indices, &opts);
+    if let Some(map) = &args.header_map {
+        // A mapped key must not end up next to a column that already has that name
+        let mut seen = HashSet::new();
+        let mapped_duplicate = headers
+            .iter()
+            .find(|header| !seen.insert(*header) && map.values().any(|key| key == *header));
+        if let Some(duplicate) = mapped_duplicate {
+            return Err(ConversionError::InvalidColumns(format!(
+                "--header-map key '{}' collides with another column in sheet '{}'",
+                duplicate, sheet_name
+            ))
+            .into());
+        }
+    }
+
+    // Keys that differ only in case collide for case-insensitive consumers
+    if let Some(mode) = args.dedup_headers_case_insensitive {
+        let first_col = range.start().map_or(0, |(_, col)| col as usize);
+        for group in case_insensitive_collisions(&headers) {
+            // The original headers and where they are, e.g. "'Status' (B), 'STATUS' (D)"
+            let originals = group
+                .iter()
+                .map(|&position| {
+                    let index = column_indices[position];
+                    let written = written_header.as_deref().unwrap_or(header_row);
+                    let text = written.get(index).map(|cell| cell.to_string()).unwrap_or_default();
+                    format!("'{}' ({})", text.trim(), column_letters(first_col + index))
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            if mode == CaseCollision::Error {
+                return Err(ConversionError::InvalidColumns(format!(
+                    "Headers {} of sheet '{}' give keys that differ only in case (--dedup-headers-case-insensitive error). \
+                     Rename them with --header-map",
+                    originals, sheet_name
+                ))
+                .into());
+            }
+            suffix_case_collisions(&mut headers, &group);
+            if !args.quiet {
+                let keys: Vec<&str> = group.iter().map(|&position| headers[position].as_str()).collect();
+                eprintln!(
+                    "Warning: headers {} of sheet '{}' collide ignoring case; keyed {}",
+                    originals,
+                    sheet_name,
+                    keys.join(", ")
+                );
+            }
+        }
+    }
+
+    // Tell colliding keys apart by where their columns sit in the sheet
+    if args.on_duplicate == OnDuplicate::ColumnNumber {
+        suffix_duplicate_headers(&mut headers, &column_indices, &visible_indices);
+    }
+    if args.strict {
+        check_strict_headers(&sheet_name, header_row, &headers, &column_indices, range.start())?;
+    }
+
+    // Keys must work as identifiers in generated code (--validate-json-keys)
+    if args.validate_json_keys {
+        let invalid: Vec<&str> = headers
+            .iter()
+            .filter(|header| !is_identifier_key(header))
+            .map(String::as_str)
+            .collect();
+        if !invalid.is_empty() {
+            let leading_digit = invalid.iter().any(|key| key.starts_with(|c: char| c.is_ascii_digit()));
+            let hint = if leading_digit && args.key_prefix.is_none() {
+                ". Use --key-prefix for keys starting with a digit"
+            } else {
+                ""
+            };
+            return Err(ConversionError::InvalidColumns(format!(
+                "Keys of sheet '{}' that aren't identifiers (^[a-z][a-z0-9_]*$): {}{}",
+                sheet_name,
+                invalid.join(", "),
+                hint
+            ))
+            .into());
+        }
+    }
+
+    // The keys as written; the options above, and those applied while converting, use `headers`
+    let keys = styled_keys(&headers, &opts).context(ConversionError::InvalidColumns(format!(
+        "--key-style gives two columns of sheet '{}' the same key",
+        sheet_name
+    )))?;
+
+    // Catch renamed or dropped columns before converting anything
+    if let Some(required) = &args.required_columns {
+        check_required_columns(required, &headers).context(ConversionError::InvalidColumns(format!(
+            "Required columns missing from sheet '{}'",
+            sheet_name
+        )))?;
+    }
+
+    check_row_widths(args, &sheet_name, &range, header_row)?;
+    if let Some(lazy) = &mut lazy_rows {
+        // The data rows aren't read yet - they're checked as they stream in
+        lazy.header_width = effective_width(header_row);
+    }
+
+    // Keep where each key came from, for --emit-mapping
+    let original_headers = column_indices
+        .iter()
+        .map(|&index| {
+            let written = written_header.as_deref().unwrap_or(header_row);
+            written.get(index).map(|cell| cell.to_string().trim().to_string()).unwrap_or_default()
+        })
+        .collect();
+    let visible_numbers = column_indices
+        .iter()
+        .map(|index| visible_indices.iter().position(|visible| visible == index).map_or(0, |pos| pos + 1))
+        .collect();
+
+    // Step 5: Read extra per-cell values and hidden rows from the raw xlsx parts if requested
+    let annotations = read_cell_annotations(args, input, &sheet_name, &range)?;
+    let hidden_rows = if args.only_visible_rows {
+        XlsxParts::open(&input.file, input.zip_entry.as_deref())?.sheet_hidden_rows(&sheet_name)?
+    } else {
+        HashSet::new()
+    };
+
+    Ok(Some(PreparedSheet {
+        sheet_name,
+        range,
+        header_rows: args.header_rows,
+        headers,
+        keys,
+        column_indices,
+        original_headers,
+        visible_numbers,
+        annotations,
+        hidden_rows,
+        read_time,
+        lazy_rows,
+    }))
+}
+
+/// Converts the data rows of a prepared sheet, handing each record to `sink`
+/// 
+/// Records are tagged with their sheet name (--with-sheet-name) and input file
+/// (--with-source-file) on the way.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `input` - The input file the sheet was read from
+/// * `sheet` - The prepared sheet
+/// * `opts` - Cell conversion options
+/// * `sink` - Receives every record in row order (collects or writes it)
+/// 
+/// # Returns
+/// A summary of the converted sheet
+fn convert_sheet(
+    args: &Args,
+    input: &InputFile,
+    mut sheet: PreparedSheet,
+    opts: &ConvertOptions,
+    sink: &mut dyn FnMut(Value) -> Result<()>,
+) -> Result<SheetConversion> {
+    // Look at the raw cell types first if asked to (--column-types)
+    let column_types = if args.column_types {
+        sheet.column_types()
+    } else {
+        Vec::new()
+    };
+
+    // Convert the data rows, advancing the progress bar per row
+    let convert_started = Instant::now();
+    let mut stats = ConvertStats::default();
+    let mut record_count = 0;
+    let progress = conversion_progress(args, sheet.data_row_count());
+    let source = input.label();
+    for record in sheet.records(opts, &progress, &mut stats) {
+        let record = match args.with_sheet_name {
+            Some(ref field) => tag_record(record, "--with-sheet-name", field, &sheet.sheet_name, &sheet.sheet_name)?,
+            None => record,
+        };
+        // Tagged last, so the file name ends up in front of the sheet name
+        let record = match args.with_source_file {
+            Some(ref field) => tag_record(record, "--with-source-file", field, &source, &sheet.sheet_name)?,
+            None => record,
+        };
+        sink(record)?;
+        record_count += 1;
+    }
+    progress.finish_and_clear();
+    // With --lazy-read the rows ended early if the sheet couldn't be read to the end;
+    // rows wider than the header are reported once they have all streamed past
+    if let Some(lazy_rows) = sheet.lazy_rows.take() {
+        let ragged = lazy_rows.ragged.take();
+        let header_width = lazy_rows.header_width;
+        lazy_rows.finish()?;
+        report_ragged_rows(args, &sheet.sheet_name, &ragged, header_width)?;
+    }
+
+    // With --strict-types the records stopped at the first value that didn't fit its column
+    let (first_row, first_col) = sheet.range.start().unwrap_or((0, 0));
+    let first_data_row = first_row as usize + sheet.header_rows;
+    if let Some((row_idx, header_idx)) = stats.first_type_fallback.filter(|_| opts.strict_types) {
+        anyhow::bail!(
+            "Cell {}{} of sheet '{}': the value of column '{}' doesn't match the type sampled from the first {} row(s) ({})",
+            column_letters(first_col as usize + sheet.column_indices[header_idx]),
+            first_data_row + row_idx + 1, // 1-based, as shown in Excel
+            sheet.sheet_name,
+            sheet.headers[header_idx],
+            opts.sample_size.unwrap_or_default(),
+            if args.strict { "--strict" } else { "--strict-types" }
+        );
+    }
+    // Likewise with --strict at the first --numeric-columns value that isn't a number
+    if let Some((row_idx, header_idx)) = stats.first_numeric_fallback.filter(|_| opts.strict_numeric) {
+        let position = ((first_data_row + row_idx) as u32, first_col + sheet.column_indices[header_idx] as u32);
+        anyhow::bail!(
+            "Cell {}{} of sheet '{}': '{}' in --numeric-columns column '{}' is not a number (--strict)",
+            column_letters(position.1 as usize),
+            position.0 + 1, // 1-based, as shown in Excel
+            sheet.sheet_name,
+            sheet.range.get_value(position).map(ToString::to_string).unwrap_or_default(),
+            sheet.headers[header_idx]
+        );
+    }
+    // ... and with --strict-bool-columns at the first --bool-columns value that isn't 1 or 0
+    if let Some((row_idx, header_idx)) = stats.first_bool_fallback.filter(|_| opts.strict_bool) {
+        let position = ((first_data_row + row_idx) as u32, first_col + sheet.column_indices[header_idx] as u32);
+        anyhow::bail!(
+            "Cell {}{} of sheet '{}': '{}' in --bool-columns column '{}' is not 1 or 0 ({})",
+            column_letters(position.1 as usize),
+            position.0 + 1, // 1-based, as shown in Excel
+            sheet.sheet_name,
+            sheet.range.get_value(position).map(ToString::to_string).unwrap_or_default(),
+            sheet.headers[header_idx],
+            if args.strict_bool_columns { "--strict-bool-columns" } else { "--strict" }
+        );
+    }
+
+    Ok(SheetConversion {
+        sheet_name: sheet.sheet_name,
+        headers: sheet.headers,
+        keys: sheet.keys,
+        column_indices: sheet.column_indices,
+        original_headers: sheet.original_headers,
+        visible_numbers: sheet.visible_numbers,
+        record_count,
+        read_time: sheet.read_time,
+        convert_time: convert_started.elapsed(),
+        stats,
+        column_types,
+    })
+}
+
+/// Lists the worksheet names of an Excel file (or --zip-entry inside it) in workbook order
+fn list_sheet_names(file: &Path, zip_entry: Option<&str>) -> Result<Vec<String>> {
+    let workbook = open_workbook_input(file, zip_entry)?;
+    Ok(workbook.sheet_names())
+}
+
+/// Adds where a record came from (its sheet or input file) as its first key
+/// 
+/// # Arguments
+/// * `record` - Record converted from one sheet
+/// * `option` - The option that asked for the field, for the error message (e.g. "--with-sheet-name")
+/// * `field` - Name of the field to add (e.g. "_sheet")
+/// * `value` - Value to store in the field
+/// * `sheet_name` - Sheet the record came from, for the error message
+/// 
+/// # Errors
+/// Returns error if the record already has a field with this name (e.g. a column header)
+fn tag_record(record: Value, option: &str, field: &str, value: &str, sheet_name: &str) -> Result<Value> {
+    let Value::Object(mut fields) = record else {
+        return Ok(record);
+    };
+    if fields.contains_key(field) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "{} field '{}' collides with a column in sheet '{}'",
+            option, field, sheet_name
+        ))
+        .into());
+    }
+
+    // Rebuild the object so the new field comes first
+    let mut tagged = serde_json::Map::with_capacity(fields.len() + 1);
+    tagged.insert(field.to_string(), json!(value));
+    tagged.append(&mut fields);
+    Ok(Value::Object(tagged))
+}
+
+/// One workbook to read: a file, or a workbook inside a zip archive
+#[derive(Debug, Clone, PartialEq)]
+struct InputFile {
+    /// The file to open
+    file: PathBuf,
+    /// Workbook inside `file` to read instead, if `file` is a zip archive
+    zip_entry: Option<String>,
+}
+
+impl InputFile {
+    /// Names the input file of a record for --with-source-file, as given on the command line
+    /// 
+    /// # Examples
+    /// - north.xlsx -> "north.xlsx"
+    /// - --zip-entry "2024/march.xlsx" on exports.zip -> "exports.zip!2024/march.xlsx"
+    fn label(&self) -> String {
+        match &self.zip_entry {
+            Some(entry) => format!("{}!{}", self.file.display(), entry),
+            None => self.file.display().to_string(),
+        }
+    }
+}
+
+/// The input files in command-line order: FILE, then the files given to --merge
+/// 
+/// Files given to --merge may use the "archive.zip!inner.xlsx" shorthand too
+/// (--zip-entry can't be combined with them).
+fn input_files(args: &Args) -> Vec<InputFile> {
+    let first = InputFile {
+        file: args.file.clone(),
+        zip_entry: args.zip_entry.clone(),
+    };
+    let merged = args.merge.iter().map(|file| match split_zip_path(file).filter(|_| !file.exists()) {
+        Some((archive, entry)) => InputFile {
+            file: archive,
+            zip_entry: Some(entry),
+        },
+        None => InputFile {
+            file: file.clone(),
+            zip_entry: None,
+        },
+    });
+    std::iter::once(first).chain(merged).collect()
+}
+
+/// Prints the "Input:" line of the summary - "Inputs:" with all files when merging several
+fn print_inputs(args: &Args) {
+    if args.merge.is_empty() {
+        println!("Input: {:?}", args.file);
+    } else {
+        let files: Vec<String> = input_files(args).iter().map(|input| format!("{:?}", input.label())).collect();
+        println!("Inputs: {}", files.join(", "));
+    }
+}
+
+/// Whether records can be written while they're converted instead of collected first
+/// 
+/// Deduplication, sorting, --fail-on-empty and --dry-run need every record before
+/// anything is written, and TOML/XML documents are built as a whole anyway.
+fn can_stream(args: &Args) -> bool {
+    matches!(args.format, OutputFormat::Json | OutputFormat::Ndjson)
+        && !args.unique
+        && args.unique_by.is_none()
+        && args.sort_by.is_none()
+        && args.group_by.is_none()
+        && !keyed_by_sheet(args)
+        && !args.fail_on_empty
+        && !args.dry_run
+}
+
+/// Whether the output is an object with one array per sheet (see --output-shape)
+fn keyed_by_sheet(args: &Args) -> bool {
+    match args.output_shape {
+        OutputShape::Auto => args.sheets.is_some() && args.with_sheet_name.is_none() && !args.split_sheets,
+        OutputShape::Array => false,
+        OutputShape::Object => true,
+    }
+}
+
+/// The option(s) that made the output an object keyed by sheet name, for error messages
+fn keyed_by_sheet_reason(args: &Args) -> &'static str {
+    match args.output_shape {
+        OutputShape::Object => "--output-shape object",
+        _ => "--sheets without --with-sheet-name",
+    }
+}
+
+/// Keys of the output keyed by sheet, one per sheet (--sheet-rename, --sheet-key-style)
+/// 
+/// A sheet listed in --sheet-rename gets its key as written; every other sheet
+/// name is passed through --sheet-key-style. Renamed sheets that aren't converted
+/// (a typo, or skipped by --on-missing-sheet) are reported in a warning.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `sheet_names` - Names of the converted sheets, in output order
+/// 
+/// # Errors
+/// - Returns error if a sheet name has nothing left to make a key from
+/// - Returns error if two sheets end up with the same key
+fn sheet_output_keys(args: &Args, sheet_names: &[&str]) -> Result<Vec<String>> {
+    let mut keys: Vec<String> = Vec::with_capacity(sheet_names.len());
+    for (position, name) in sheet_names.iter().enumerate() {
+        let key = match args.sheet_rename.as_ref().and_then(|rename| rename.get(name)) {
+            Some(key) => key.to_string(),
+            None => args.sheet_key_style.apply(name),
+        };
+        if key.is_empty() {
+            anyhow::bail!(
+                "Sheet '{}' leaves an empty key with --sheet-key-style; name its key with --sheet-rename",
+                name
+            );
+        }
+        // Two arrays under one key would overwrite each other in the output object
+        if let Some(other) = keys.iter().position(|other| *other == key) {
+            anyhow::bail!(
+                "Sheets '{}' and '{}' both become the key '{}'; give one another key with --sheet-rename",
+                sheet_names[other],
+                sheet_names[position],
+                key
+            );
+        }
+        keys.push(key);
+    }
+    if let Some(rename) = &args.sheet_rename
+        && !args.quiet
+    {
+        for (sheet, _) in &rename.0 {
+            if !sheet_names.iter().any(|name| name.to_lowercase() == sheet.to_lowercase()) {
+                eprintln!("Warning: --sheet-rename names sheet '{}', which isn't in the output", sheet);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Reads the sheet(s) of one input file and resolves their columns
+/// 
+/// Which sheets are read depends on the sheet options: all of them with
+/// --merge-sheets (skipping sheets without any cells), a list with --sheets,
+/// otherwise the one named or picked by --sheet-index/--table.
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// * `input` - The input file to read (see input_files())
+/// * `skipped` - Receives "file (sheet 'Name')" for each sheet that --on-missing-sheet skipped
+/// 
+/// # Returns
+/// The prepared sheets, in workbook (or --sheets) order; empty if every requested
+/// sheet was skipped
+/// 
+/// # Errors
+/// - Returns error if a sheet can't be found (unless --on-missing-sheet skips it) or read (see prepare_sheet())
+/// - Returns error if a requested sheet is empty, or no sheet has a header row
+fn prepare_input(args: &Args, input: &InputFile, skipped: &mut Vec<String>) -> Result<Vec<PreparedSheet>> {
+    let selectors = if args.merge_sheets {
+        with_open_retries(args, || list_sheet_names(&input.file, input.zip_entry.as_deref()))?
+            .into_iter()
+            .map(SheetSelector::Name)
+            .collect()
+    } else if let Some(list) = &args.sheets {
+        // Each name is resolved (and checked) like a single sheet name; repeats count once
+        let mut names: Vec<&str> = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names.into_iter().map(|name| SheetSelector::Name(name.to_string())).collect()
+    } else {
+        vec![SheetSelector::from_args(args)?]
+    };
+
+    // Read each sheet; with --merge-sheets, sheets without any cells are skipped
+    let mut prepared = Vec::new();
+    let skipped_before = skipped.len();
+    for selector in &selectors {
+        let sheet = match prepare_sheet(args, input, selector) {
+            Err(err) if args.on_missing_sheet != OnMissingSheet::Error && is_sheet_not_found(&err) => {
+                if args.on_missing_sheet == OnMissingSheet::Warn && !args.quiet {
+                    eprintln!("Warning: skipping {} of {}: {:#}", selector, input.label(), err);
+                }
+                skipped.push(format!("{} ({})", input.label(), selector));
+                continue;
+            }
+            result => result?,
+        };
+        match sheet {
+            Some(sheet) => prepared.push(sheet),
+            None if args.merge_sheets => continue,
+            None => match selector {
+                SheetSelector::Name(name) if args.sheets.is_some() => {
+                    anyhow::bail!("Excel sheet '{}' is empty, no header row found", name)
+                }
+                _ => anyhow::bail!("Excel sheet is empty, no header row found"),
+            },
+        }
+    }
+    if prepared.is_empty() && skipped.len() == skipped_before {
+        anyhow::bail!("No sheet in {:?} has a header row", input.file);
+    }
+    Ok(prepared)
+}
+
+/// Runs `open` until it succeeds or the --retry attempts are used up
+/// 
+/// Only failures to open the input file (exit code 3), such as a workbook another
+/// process is still writing, are retried; other errors are returned right away.
+/// The wait starts at --retry-delay and doubles after each attempt.
+/// 
+/// # Arguments
+/// * `args` - Arguments of the input file (--retry, --retry-delay, --quiet)
+/// * `open` - Opens the input file and reads what's needed from it
+fn with_open_retries<T>(args: &Args, mut open: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = Duration::from_millis(args.retry_delay);
+    for attempt in 1..=args.retry {
+        match open() {
+            Err(err) if matches!(err.downcast_ref::<ConversionError>(), Some(ConversionError::FileOpen(_))) => {
+                if !args.quiet {
+                    eprintln!(
+                        "Warning: {:#}; retrying in {} ms (retry {} of {})",
+                        err,
+                        delay.as_millis(),
+                        attempt,
+                        args.retry
+                    );
+                }
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+    open()
+}
+
+/// Whether an error means the requested sheet (or table) doesn't exist (exit code 4)
+fn is_sheet_not_found(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<ConversionError>(), Some(ConversionError::SheetNotFound(_)))
+}
+
+/// Runs one complete conversion, from reading the sheet(s) to writing the output
+/// 
+/// Process flow:
+/// 1. Resolve which sheet to read (all sheets with --merge-sheets, a list with --sheets)
+/// 2. Read each sheet of each input file and resolve its columns (see prepare_input())
+/// 3. Convert the records (see convert_sheet()) - straight into the output file
+///    when possible (see can_stream()), otherwise into memory
+/// 4. Deduplicate and sort the collected records if requested
+/// 5. Write them to the output file
+///    (with --dry-run, print the conversion plan instead)
+/// 6. Display summary statistics
+/// 
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// 
+/// # Returns
+/// Result indicating success or failure of the conversion process
+fn run_conversion(args: &Args) -> Result<()> {
+    let started = Instant::now();
+
+    // Step 1: Resolve the conversion options
+    let mut opts = convert_options(args);
+    let mut out_opts = OutputOptions::from_args(args);
+
+    // Step 2: Read the sheet(s) of every input file (just one without --merge)
+    let mut inputs = Vec::new(); // Input file and date system per input file
+    let mut prepared = Vec::new(); // (index into inputs, sheet)
+    let mut skipped = Vec::new(); // Sheets left out by --on-missing-sheet
+    for input in input_files(args) {
+        let sheets = if args.merge.is_empty() {
+            prepare_input(args, &input, &mut skipped)?
+        } else {
+            // Several files - say which one failed
+            prepare_input(args, &input, &mut skipped).with_context(|| format!("Failed to convert {}", input.label()))?
+        };
+        if sheets.is_empty() {
+            continue; // Every requested sheet of this file was skipped
+        }
+        // Serial-to-date conversion depends on the workbook's date system
+        let date1904 = opts.dates != DateMode::Serial
+            && uses_1904_dates(open_input(&input.file, input.zip_entry.as_deref())?)
+                .context(ConversionError::FileOpen(format!("Failed to read the date system of {:?}", input.file)))?;
+        prepared.extend(sheets.into_iter().map(|sheet| (inputs.len(), sheet)));
+        inputs.push((input, date1904));
+    }
+
+    if prepared.is_empty() {
+        return Err(ConversionError::SheetNotFound(format!(
+            "No input has the requested sheet (--on-missing-sheet skipped: {})",
+            skipped.join(", ")
+        ))
+        .into());
+    }
+
+    // With --consolidate every sheet takes the first sheet's columns
+    if args.consolidate
+        && let Some(((_, first), rest)) = prepared.split_first_mut()
+    {
+        for (_, sheet) in rest {
+            let (missing, extra) = sheet.conform_to(&first.headers, &first.keys);
+            if (!missing.is_empty() || !extra.is_empty()) && !args.quiet {
+                eprintln!(
+                    "Warning: columns of sheet '{}' differ from sheet '{}' (--consolidate): missing [{}] (null), extra [{}] (left out)",
+                    sheet.sheet_name,
+                    first.sheet_name,
+                    missing.join(", "),
+                    extra.join(", ")
+                );
+            }
+        }
+    }
+
+    if args.comment_header {
+        let mut sheet_names: Vec<&str> = Vec::new();
+        for (_, sheet) in &prepared {
+            if !sheet_names.contains(&sheet.sheet_name.as_str()) {
+                sheet_names.push(&sheet.sheet_name);
+            }
+        }
+        out_opts.provenance = Some(Provenance {
+            source: inputs.iter().map(|(input, _)| input.label()).collect::<Vec<_>>().join(", "),
+            sheet: sheet_names.join(", "),
+            generated: current_iso_timestamp(),
+        });
+    }
+
+    // Column names across all sheets, in first-seen order
+    let mut headers: Vec<String> = Vec::new();
+    for header in prepared.iter().flat_map(|(_, sheet)| &sheet.headers) {
+        if !headers.contains(header) {
+            headers.push(header.clone());
+        }
+    }
+
+    // With --strict-schema every input file must have the first file's columns
+    if args.strict_schema && inputs.len() > 1 {
+        let columns_of = |input: usize| -> BTreeSet<&String> {
+            prepared
+                .iter()
+                .filter(|(index, _)| *index == input)
+                .flat_map(|(_, sheet)| &sheet.headers)
+                .collect()
+        };
+        let expected = columns_of(0);
+        for (index, (input, _)) in inputs.iter().enumerate().skip(1) {
+            let found = columns_of(index);
+            if found != expected {
+                let list = |columns: Vec<&&String>| columns.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ");
+                return Err(ConversionError::InvalidColumns(format!(
+                    "Columns of {} don't match {} (--strict-schema): missing [{}], extra [{}]",
+                    input.label(),
+                    inputs[0].0.label(),
+                    list(expected.difference(&found).collect()),
+                    list(found.difference(&expected).collect()),
+                ))
+                .into());
+            }
+        }
+    }
+
+    // Every --replace, --split-cells, --defaults, --where and typed-column (--numeric-columns, ...)
+    // rule must target one of the selected columns
+    if let Some(rule) = opts.replace_rules.iter().find(|r| !headers.contains(&r.column)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "Replace column '{}' is not among the selected columns",
+            rule.column
+        ))
+        .into());
+    }
+    if let Some(rule) = opts.split_rules.iter().find(|r| !headers.contains(&r.column)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "Split column '{}' is not among the selected columns",
+            rule.column
+        ))
+        .into());
+    }
+    if let Some(column) = opts.defaults.keys().find(|column| !headers.contains(column)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--defaults column '{}' is not among the selected columns",
+            column
+        ))
+        .into());
+    }
+    check_typed_columns(&opts, &headers)?;
+    if let Some(condition) = opts.conditions.iter().find(|c| !headers.contains(&c.column)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--where column '{}' is not among the selected columns",
+            condition.column
+        ))
+        .into());
+    }
 
-    /// Optional: Comma-separated list of visible column numbers to include
-    /// Only columns with non-empty headers are counted
-    /// Example: "1,2,3" will include the first three visible columns
-    #[arg(short, long, help = "Visible column numbers to include (comma-separated, e.g., 1,2,3). Only counts columns with non-empty headers. If not specified, all visible columns are included")]
-    columns: Option<String>,
+    if let Some(column) = args.group_by.as_ref().filter(|c| !headers.contains(&normalize_column_name(c))) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--group-by column '{}' is not among the selected columns",
+            column
+        ))
+        .into());
+    }
+    if let Some(column) = &args.group_by {
+        // Records are grouped after --keep-keys/--drop-keys, so the group column has to survive them
+        let key = normalize_column_name(column);
+        let removed = args.keep_keys.as_ref().is_some_and(|keep| !keep.contains(&key))
+            || args.drop_keys.as_ref().is_some_and(|drop| drop.contains(&key));
+        if removed {
+            return Err(ConversionError::InvalidColumns(format!(
+                "--group-by column '{}' is removed by --keep-keys/--drop-keys (use --drop-group-key to leave it out of the records)",
+                column
+            ))
+            .into());
+        }
+    }
 
-    /// Path where the output JSON file will be saved
-    #[arg(short, long, help = "Output JSON file path")]
-    output: PathBuf,
+    // Catch a --with-sheet-name/--with-source-file clash before anything is written
+    if let Some(field) = args.with_sheet_name.as_ref().filter(|field| headers.contains(field)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--with-sheet-name field '{}' collides with a column",
+            field
+        ))
+        .into());
+    }
+    if let Some(field) = args.with_source_file.as_ref().filter(|field| headers.contains(field)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--with-source-file field '{}' collides with a column",
+            field
+        ))
+        .into());
+    }
+    // A column that --keep-keys/--drop-keys removes can't collide with the record number
+    let index_collides = |field: &String| {
+        headers.contains(field)
+            && args.keep_keys.as_ref().is_none_or(|keep| keep.contains(field))
+            && !args.drop_keys.as_ref().is_some_and(|drop| drop.contains(field))
+    };
+    if let Some(field) = args.index_key.as_ref().filter(|field| index_collides(field)) {
+        return Err(ConversionError::InvalidColumns(format!(
+            "--index-key field '{}' collides with a column (name the field with --index-key=FIELD)",
+            field
+        ))
+        .into());
+    }
+
+    // The sheet name shown in messages and used for {sheet} in --name-template
+    let sheet_label = if args.merge_sheets || args.sheets.is_some() {
+        "merged".to_string()
+    } else {
+        prepared[0].1.sheet_name.clone()
+    };
+    // Keys of output keyed by sheet are checked before anything is converted
+    let sheet_keys = if keyed_by_sheet(args) {
+        let names: Vec<&str> = prepared.iter().map(|(_, sheet)| sheet.sheet_name.as_str()).collect();
+        sheet_output_keys(args, &names)?
+    } else {
+        Vec::new()
+    };
+    // ... and so are the --nested structures of all sheets' columns
+    let nest_paths = if args.nested {
+        Some(nest_paths(args, prepared.iter().map(|(_, sheet)| sheet))?)
+    } else {
+        None
+    };
+    let key_renames = key_renames(args, prepared.iter().map(|(_, sheet)| sheet), nest_paths.as_ref());
+    let output = match &args.output_dir {
+        // Each sheet gets a file of its own in the directory (named in Step 3/5)
+        Some(dir) if args.split_sheets => dir.clone(),
+        _ => resolve_output_path(args, &sheet_label)?,
+    };
+    if let Some(manifest) = &args.emit_manifest {
+        // The manifest lists the output files, so it can't be one of them
+        for (_, sheet) in &prepared {
+            if resolve_output_path(args, &sheet.sheet_name)? == *manifest {
+                return Err(ConversionError::Write(format!(
+                    "--emit-manifest {:?} is also the output file of sheet '{}'",
+                    manifest, sheet.sheet_name
+                ))
+                .into());
+            }
+        }
+    }
+    if let Some(dir) = args.output_dir.as_ref().filter(|_| !args.dry_run) {
+        // Make sure the output directory exists before writing into it
+        fs::create_dir_all(dir)
+            .context(ConversionError::Write(format!("Failed to create output directory: {:?}", dir)))?;
+    }
+
+    let mut sheets = Vec::new();
+    let mut split_outputs = Vec::new(); // Files written by --split-sheets
+    let (record_count, removed) = if can_stream(args) {
+        // Step 3: Write each record as soon as it's converted, sheet after sheet
+        // (with --split-sheets each sheet into a file of its own)
+        let mut writer = if args.split_sheets {
+            None
+        } else {
+            Some(ChunkedWriter::create(&output, &out_opts)?)
+        };
+        let mut next_index = args.index_start;
+        let mut record_count = 0;
+        let mut empty_dropped = 0;
+        for (input, sheet) in prepared {
+            let (input, date1904) = &inputs[input];
+            opts.date1904 = *date1904;
+            let mut sheet_writer = None;
+            if args.split_sheets {
+                let path = resolve_output_path(args, &sheet.sheet_name)?;
+                sheet_writer = Some((ChunkedWriter::create(&path, &out_opts)?, path));
+                next_index = args.index_start; // Every file is numbered from the start
+            }
+            let target = match (&mut sheet_writer, &mut writer) {
+                (Some((sheet_writer, _)), _) => sheet_writer,
+                (None, writer) => writer.as_mut().expect("a writer for the whole output"),
+            };
+            sheets.push(convert_sheet(args, input, sheet, &opts, &mut |mut record| {
+                filter_record_keys(&mut record, args); // --keep-keys/--drop-keys
+                if args.drop_empty_records && is_empty_record(&record) {
+                    empty_dropped += 1;
+                    return Ok(());
+                }
+                if let Some(field) = &args.index_key {
+                    record = add_index_key(record, field, next_index)?;
+                    next_index += 1;
+                }
+                target.write(shape_record(record, nest_paths.as_ref(), &key_renames)?)
+            })?);
+            if let Some((sheet_writer, path)) = sheet_writer {
+                let records = sheet_writer.finish()?;
+                record_count += records;
+                split_outputs.push(SheetOutput {
+                    sheet: sheets.last().expect("sheet just converted").sheet_name.clone(),
+                    path,
+                    records,
+                });
+            }
+        }
+        if let Some(writer) = writer {
+            record_count += writer.finish()?;
+        }
+        let removed = RemovedRecords {
+            duplicates: None,
+            empty: args.drop_empty_records.then_some(empty_dropped),
+        };
+        (record_count, removed)
+    } else {
+        // Step 3: Collect the records of all sheets - per sheet when writing an object keyed
+        // by sheet or a file per sheet
+        let keyed = keyed_by_sheet(args);
+        let mut batches: Vec<Vec<Value>> = vec![Vec::new()];
+        for (input, sheet) in prepared {
+            if (keyed || args.split_sheets) && !sheets.is_empty() {
+                batches.push(Vec::new());
+            }
+            let records = batches.last_mut().expect("at least one batch");
+            let (input, date1904) = &inputs[input];
+            opts.date1904 = *date1904;
+            sheets.push(convert_sheet(args, input, sheet, &opts, &mut |record| {
+                records.push(record);
+                Ok(())
+            })?);
+        }
+
+        // Step 4: Remove duplicate records if requested (before sorting, so "first" means first in the sheet)
+        let unique_key = match &args.unique_by {
+            Some(column) => {
+                let key = normalize_column_name(column);
+                if !headers.contains(&key) {
+                    return Err(ConversionError::InvalidColumns(format!(
+                        "Unique column '{}' is not among the selected columns",
+                        column
+                    ))
+                    .into());
+                }
+                Some(key)
+            }
+            None => None,
+        };
+        let duplicates_removed = (args.unique || unique_key.is_some()).then(|| {
+            batches
+                .iter_mut()
+                .map(|batch| dedupe_records(batch, unique_key.as_deref()))
+                .sum()
+        });
+
+        // Sort the records if requested
+        if let Some(ref spec) = args.sort_by {
+            let keys = parse_sort_keys(spec, &headers).context(ConversionError::InvalidColumns(
+                format!("Invalid --sort-by value '{}'", spec),
+            ))?;
+            for batch in &mut batches {
+                sort_records(batch, &keys);
+            }
+        }
+
+        // Leave out keys last, so deduplication and sorting still see every column
+        if args.keep_keys.is_some() || args.drop_keys.is_some() {
+            for record in batches.iter_mut().flatten() {
+                filter_record_keys(record, args);
+            }
+        }
+        // ... and only then see which records have nothing left (--drop-empty-records)
+        let empty_dropped = args.drop_empty_records.then(|| {
+            batches
+                .iter_mut()
+                .map(|batch| {
+                    let before = batch.len();
+                    batch.retain(|record| !is_empty_record(record));
+                    before - batch.len()
+                })
+                .sum()
+        });
+        let removed = RemovedRecords { duplicates: duplicates_removed, empty: empty_dropped };
+
+        // Number the records in their final order (--index-key), per file with --split-sheets
+        if let Some(field) = &args.index_key {
+            let mut index = args.index_start;
+            for batch in &mut batches {
+                if args.split_sheets {
+                    index = args.index_start;
+                }
+                for record in batch.iter_mut() {
+                    *record = add_index_key(record.take(), field, index)?;
+                    index += 1;
+                }
+            }
+        }
+
+        // Nest the records (--nested) and style their keys (--key-style) last, so everything
+        // above sees the flat keys; --group-by still needs its column, so its groups are shaped once formed
+        if (nest_paths.is_some() || !key_renames.is_empty()) && args.group_by.is_none() {
+            for record in batches.iter_mut().flatten() {
+                *record = shape_record(record.take(), nest_paths.as_ref(), &key_renames)?;
+            }
+        }
+
+        // An empty result usually means a misconfiguration - fail loudly if asked to
+        let record_count: usize = batches.iter().map(Vec::len).sum();
+        if args.fail_on_empty && record_count == 0 {
+            return Err(ConversionError::EmptyOutput(format!(
+                "No records were produced from sheet '{}'",
+                sheet_label
+            ))
+            .into());
+        }
+
+        // Step 5: Write the records to the output file
+        if args.dry_run {
+            // Dry run - report the plan and stop before touching the file system
+            print_dry_run(args, &sheets, &output, record_count);
+            if args.stats_json {
+                print_stats_json(
+                    args,
+                    &sheets,
+                    &skipped,
+                    &output,
+                    record_count,
+                    &removed,
+                    started.elapsed(),
+                );
+            }
+            return Ok(());
+        }
+        if args.split_sheets {
+            // One file per sheet, named by the {sheet} placeholder of --name-template
+            for (sheet, batch) in sheets.iter().zip(batches) {
+                let path = resolve_output_path(args, &sheet.sheet_name)?;
+                let records = write_json_to_file(batch, &path, &out_opts)?;
+                split_outputs.push(SheetOutput { sheet: sheet.sheet_name.clone(), path, records });
+            }
+        } else if keyed {
+            // --sheets without --with-sheet-name or --output-shape object: {"Summary": [...], "Details": [...]}
+            // (keys styled or renamed by --sheet-key-style/--sheet-rename)
+            let document: serde_json::Map<String, Value> =
+                sheet_keys.into_iter().zip(batches.into_iter().map(Value::Array)).collect();
+            RecordWriter::create(&output, &out_opts)?.write_document(&Value::Object(document))?;
+        } else if let Some(ref column) = args.group_by {
+            // Group column validated above, before any conversion output
+            let key = normalize_column_name(column);
+            let json_array = batches.into_iter().flatten().collect();
+            let mut groups = group_records(json_array, &key, args.drop_group_key, &args.group_empty_key);
+            for record in groups.values_mut().filter_map(Value::as_array_mut).flatten() {
+                *record = shape_record(record.take(), nest_paths.as_ref(), &key_renames)?;
+            }
+            RecordWriter::create(&output, &out_opts)?.write_document(&Value::Object(groups))?;
+        } else {
+            write_json_to_file(batches.into_iter().flatten(), &output, &out_opts)?;
+        }
+        (record_count, removed)
+    };
+
+    // The sidecars describe the output just written
+    if let Some(path) = &args.emit_mapping {
+        write_column_mapping(path, &sheets)?;
+    }
+    if let Some(path) = &args.emit_manifest {
+        write_manifest(path, &split_outputs, args.chunk_size)?;
+    }
+
+    // Values --numeric-columns couldn't parse were kept as text - worth a warning even with --stats-json
+    let numeric_fallbacks: usize = sheets.iter().map(|sheet| sheet.stats.numeric_fallbacks).sum();
+    if numeric_fallbacks > 0 && !args.quiet {
+        eprintln!(
+            "Warning: {} value(s) in --numeric-columns columns are not numbers and were kept as text",
+            numeric_fallbacks
+        );
+    }
+    // ... and values --bool-columns couldn't map to true/false
+    let bool_fallbacks: usize = sheets.iter().map(|sheet| sheet.stats.bool_fallbacks).sum();
+    if bool_fallbacks > 0 && !args.quiet {
+        eprintln!(
+            "Warning: {} value(s) in --bool-columns columns are not 1 or 0 and were converted as usual",
+            bool_fallbacks
+        );
+    }
+    // ... and text in --date-columns that isn't a date in the --date-input-format
+    let date_fallbacks: usize = sheets.iter().map(|sheet| sheet.stats.date_fallbacks).sum();
+    if date_fallbacks > 0 && !args.quiet {
+        eprintln!(
+            "Warning: {} value(s) in --date-columns columns don't match the date format '{}' and were kept as text",
+            date_fallbacks, opts.date_input_format
+        );
+    }
+    // Likewise values that didn't match the type --sample-size fixed for their column
+    let type_fallbacks: usize = sheets.iter().map(|sheet| sheet.stats.type_fallbacks).sum();
+    if type_fallbacks > 0 && !args.quiet {
+        eprintln!(
+            "Warning: {} value(s) don't match their column's type sampled by --sample-size and were kept as text",
+            type_fallbacks
+        );
+    }
+
+    // Step 6: Display success message and statistics (as JSON with --stats-json, none with --quiet)
+    if args.stats_json {
+        print_stats_json(
+            args,
+            &sheets,
+            &skipped,
+            &output,
+            record_count,
+            &removed,
+            started.elapsed(),
+        );
+        return Ok(());
+    }
+    if args.quiet || is_stdout(&output) {
+        return Ok(()); // Standard output holds the records
+    }
+    println!("Successfully converted Excel to JSON");
+    print_inputs(args);
+    if args.merge_sheets || args.sheets.is_some() {
+        let names: Vec<&str> = sheets.iter().map(|s| s.sheet_name.as_str()).collect();
+        println!("Sheets: {}", names.join(", "));
+    } else {
+        println!("Sheet: {}", sheet_label);
+    }
+    if !skipped.is_empty() {
+        println!("Skipped (sheet not found): {}", skipped.join(", "));
+    }
+    println!("Output: {:?}", output);
+    for split_output in &split_outputs {
+        println!("  {}: {:?} ({} records)", split_output.sheet, split_output.path, split_output.records);
+    }
+    if let Some(size) = args.chunk_size.filter(|_| !args.split_sheets) {
+        let chunks = chunk_count(record_count, size);
+        println!(
+            "Chunk files: {} ({:?} ... {:?})",
+            chunks,
+            chunk_path(&output, 1),
+            chunk_path(&output, chunks)
+        );
+    }
+    if let Some(path) = &args.emit_mapping {
+        println!("Column mapping: {:?}", path);
+    }
+    if let Some(path) = &args.emit_manifest {
+        println!("Manifest: {:?}", path);
+    }
+    println!("Visible columns: {}", headers.len());
+    println!("Total records: {}", record_count);
+    if let Some(count) = removed.duplicates {
+        println!("Duplicates removed: {}", count);
+    }
+    if let Some(count) = removed.empty {
+        println!("Empty records dropped: {}", count);
+    }
+    if args.max_cell_length.is_some() {
+        let truncated_cells: usize = sheets.iter().map(|sheet| sheet.stats.truncated_cells).sum();
+        println!("Truncated cells: {}", truncated_cells);
+    }
+    if args.column_types {
+        println!("Column types:");
+        for (header, counts) in column_type_summary(&sheets) {
+            println!("  {}: {}", header, counts);
+        }
+    }
+
+    Ok(())
 }
 
-/// Normalizes Excel column header names to valid JSON keys
-/// 
-/// Rules:
-/// - Single special characters are converted to meaningful words (e.g., "#" -> "number")
-/// - Converts to lowercase
-/// - Replaces special characters with underscores or meaningful text
-/// - Removes parentheses
-/// - Removes consecutive underscores
+/// Formats the current time as "HH:MM:SS UTC" for --watch log lines
+fn current_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let day_secs = secs % 86_400; // Seconds since midnight UTC
+    format!(
+        "{:02}:{:02}:{:02} UTC",
+        day_secs / 3600,
+        (day_secs % 3600) / 60,
+        day_secs % 60
+    )
+}
+
+/// Formats the current time as "YYYY-MM-DDTHH:MM:SSZ" for --comment-header
+fn current_iso_timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    chrono::DateTime::from_timestamp(secs, 0)
+        .unwrap_or_default()
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// Waits for the input file to exist again after an editor's atomic save
 /// 
-/// # Arguments
-/// * `name` - The original column header name from Excel
+/// Many editors save by writing a temporary file and renaming it over the
+/// original, so the file can briefly disappear.
 /// 
 /// # Returns
-/// A normalized string suitable for use as a JSON key
+/// true if the file exists (or reappeared within the timeout), false otherwise
+fn wait_for_file(file: &Path) -> bool {
+    let started = SystemTime::now();
+    while !file.exists() {
+        let waited = started.elapsed().unwrap_or(WATCH_MISSING_FILE_TIMEOUT);
+        if waited >= WATCH_MISSING_FILE_TIMEOUT {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    true
+}
+
+/// Watches the input file and re-runs the conversion whenever it changes
 /// 
-/// # Examples
-/// - "First Name" -> "first_name"
-/// - "#" -> "number"
-/// - "Sales/Revenue" -> "sales_revenue"
-/// - "Profit & Loss" -> "profit_and_loss"
-fn normalize_column_name(name: &str) -> String {
-    let trimmed = name.trim();
-    
-    // Handle single special characters with meaningful names
-    let result = match trimmed {
-        "#" => "number".to_string(),
-        "@" => "at".to_string(),
-        "%" => "percent".to_string(),
-        "$" => "usd".to_string(),
-        "/" => "slash".to_string(),
-        "&" => "and".to_string(),
-        _ => {
-            // For all other cases, apply transformation rules
-            trimmed
-                .to_lowercase() // Convert to lowercase
-                .replace(" & ", "_and_") // Replace " & " with "_and_"
-                .replace("&", "_and_") // Replace "&" with "_and_"
-                .replace("/", "_") // Replace "/" with "_"
-                .replace("@", "_at_") // Replace "@" with "_at_"
-                .replace("#", "_") // Replace "#" with "_"
-                .replace("%", "_percent") // Replace "%" with "_percent"
-                .replace("$", "_usd") // Replace "$" with "_usd"
-                .replace("(", "") // Remove opening parenthesis
-                .replace(")", "") // Remove closing parenthesis
-                .replace(" ", "_") // Replace spaces with underscores
-        }
-    };
-    
-    // Clean up: remove consecutive underscores and empty segments
-    result
-        .split('_')
-        .filter(|s| !s.is_empty()) // Remove empty segments
-        .collect::<Vec<_>>()
-        .join("_") // Join with single underscore
-}
-
-/// Identifies visible columns by filtering out columns with empty headers
-/// 
-/// This function helps distinguish between actual data columns and hidden/unused columns.
-/// Only columns with non-empty header values are considered "visible".
-/// 
-/// # Arguments
-/// * `header_row` - The first row of the Excel sheet containing column headers
-/// 
-/// # Returns
-/// A vector of column indices (0-based) that have non-empty headers
+/// The parent directory is watched rather than the file itself so that
+/// atomic saves (write temp file + rename) keep being detected. Bursts of
+/// events are debounced into a single re-conversion, and conversion errors
+/// are reported without stopping the watcher.
 /// 
-/// # Example
-/// If header row is: ["Name", "Age", "", "Email", "", "Phone"]
-/// Returns: [0, 1, 3, 5] (indices of non-empty columns)
-fn get_visible_column_indices(header_row: &[calamine::Data]) -> Vec<usize> {
-    header_row
-        .iter() // Iterate through all cells in the header row
-        .enumerate() // Get index along with each cell
-        .filter_map(|(idx, cell)| {
-            // Convert cell to string and trim whitespace
-            let cell_str = cell.to_string().trim().to_string();
-            // Only include columns with non-empty headers
-            if !cell_str.is_empty() {
-                Some(idx) // Return the column index
-            } else {
-                None // Skip empty columns
-            }
-        })
-        .collect() // Collect all visible column indices into a vector
+/// # Arguments
+/// * `args` - Parsed command-line arguments
+/// 
+/// # Errors
+/// - Returns error if the file watcher cannot be created or started
+fn watch_and_reconvert(args: &Args) -> Result<()> {
+    use notify::{RecursiveMode, Watcher};
+
+    // Resolve the file and its directory to absolute paths for event matching
+    let file = fs::canonicalize(&args.file)
+        .context(ConversionError::FileOpen(format!("Failed to resolve input file: {:?}", args.file)))?;
+    let dir = file
+        .parent()
+        .context("Input file has no parent directory")?
+        .to_path_buf();
+
+    // Forward raw notify events into a channel we can debounce
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to create file watcher")?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .context(format!("Failed to watch directory: {:?}", dir))?;
+
+    println!("Watching {:?} for changes (press Ctrl+C to stop)", args.file);
+
+    // Only react to content changes of the input file itself
+    // (access events are ignored, otherwise our own reads would retrigger us)
+    let touches_input = |event: &notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            let changed = event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove();
+            changed && event.paths.iter().any(|p| p.file_name() == file.file_name())
+        }
+        Err(_) => false,
+    };
+
+    while let Ok(event) = rx.recv() {
+        if !touches_input(&event) {
+            continue;
+        }
+
+        // Debounce: swallow further events until things settle down
+        while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+
+        if !wait_for_file(&file) {
+            eprintln!("[{}] Input file is missing, waiting for it to reappear", current_timestamp());
+            continue;
+        }
+
+        println!("[{}] Change detected, re-converting", current_timestamp());
+        if let Err(err) = run_conversion(args) {
+            // Keep watching - the next save may fix the problem
+            eprintln!("[{}] Error: {:#}", current_timestamp(), err);
+        }
+    }
+
+    Ok(())
 }
 
-/// Parses user-specified column numbers and maps them to actual visible column indices
+/// Prints the header and first data rows of a sheet as a text table (`excel2json preview`)
 /// 
-/// Users specify columns using 1-based numbering (1, 2, 3, ...)
-/// This function converts those to 0-based indices and validates them.
+/// Only the visible columns (those with a header) are shown, headed by the
+/// keys they get in the JSON output; cells are shown as text, as without
+/// --infer-types. The table wraps to fit the terminal.
 /// 
 /// # Arguments
-/// * `columns_str` - Comma-separated string of column numbers (e.g., "1,2,3")
-/// * `visible_indices` - Vector of actual column indices that have non-empty headers
-/// 
-/// # Returns
-/// A Result containing a vector of actual column indices to use
+/// * `args` - Arguments of the preview command
 /// 
 /// # Errors
-/// - Returns error if column number is 0 or negative
-/// - Returns error if column number exceeds the count of visible columns
-/// - Returns error if the input string contains invalid numbers
-/// 
-/// # Example
-/// If visible_indices = [0, 2, 5, 7] and columns_str = "1,3"
-/// Returns: Ok([0, 5]) - maps user's 1st and 3rd visible columns to actual indices
-fn parse_visible_column_numbers(
-    columns_str: &str,
-    visible_indices: &[usize],
-) -> Result<Vec<usize>> {
-    columns_str
-        .split(',') // Split by comma
-        .map(|s| {
-            s.trim() // Remove whitespace
-                .parse::<usize>() // Parse string to number
-                .context("Invalid column number") // Add error context
-                .and_then(|n| {
-                    // Validate column number
-                    if n == 0 {
-                        anyhow::bail!("Column numbers must be greater than 0")
-                    }
-                    if n > visible_indices.len() {
-                        anyhow::bail!(
-                            "Column number {} exceeds visible column count ({})",
-                            n,
-                            visible_indices.len()
-                        )
-                    }
-                    // Convert 1-based user input to 0-based array index
-                    // Then map to actual column index in the Excel sheet
-                    Ok(visible_indices[n - 1])
-                })
-        })
-        .collect() // Collect all results, will fail if any parsing failed
+/// - Returns error if the file can't be opened or the sheet doesn't exist
+/// - Returns error if the sheet is empty
+fn run_preview(args: &PreviewArgs) -> Result<()> {
+    // "archive.zip!inner.xlsx" works here too
+    let (file, zip_entry) = match split_zip_path(&args.file).filter(|_| !args.file.exists()) {
+        Some((archive, entry)) => (archive, Some(entry)),
+        None => (args.file.clone(), None),
+    };
+    let selector = SheetSelector::Name(args.sheet.clone());
+    let (sheet_name, range) = read_excel_sheet(&file, zip_entry.as_deref(), &selector, FormulaMode::Value)?;
+
+    let mut rows = range.rows();
+    let Some(header_row) = rows.next() else {
+        anyhow::bail!("Excel sheet '{}' is empty, no header row found", sheet_name);
+    };
+    let visible_indices = get_visible_column_indices(header_row);
+    let headers = extract_headers(header_row, &visible_indices, None);
+
+    let mut table = Table::new();
+    table.set_content_arrangement(ContentArrangement::Dynamic).set_header(headers);
+    for row in rows.take(args.rows) {
+        table.add_row(
+            visible_indices
+                .iter()
+                .map(|&index| row.get(index).map(ToString::to_string).unwrap_or_default()),
+        );
+    }
+    println!("{}", table);
+
+    let data_rows = range.height().saturating_sub(1);
+    println!(
+        "Sheet '{}': {} of {} data row(s), {} visible column(s)",
+        sheet_name,
+        data_rows.min(args.rows),
+        data_rows,
+        visible_indices.len()
+    );
+    Ok(())
 }
 
-/// Opens an Excel file and reads a specific worksheet
+/// Lists every commented cell of a workbook as JSON (`excel2json comments`)
 /// 
-/// # Arguments
-/// * `file` - Path to the Excel file (.xlsx)
-/// * `sheet` - Name of the worksheet to read
+/// Each entry is {"sheet", "cell", "value", "comment"}, with the cell as an A1
+/// reference and its value as text (null for a comment on an empty cell).
+/// Sheets are taken in workbook order and cells row by row; sheets without
+/// comments contribute nothing.
 /// 
-/// # Returns
-/// A Result containing the Range of cells from the specified worksheet
+/// # Arguments
+/// * `args` - Arguments of the comments command
 /// 
 /// # Errors
-/// - Returns error if the file cannot be opened
-/// - Returns error if the specified sheet name doesn't exist in the workbook
-fn read_excel_sheet(file: &PathBuf, sheet: &str) -> Result<calamine::Range<calamine::Data>> {
-    // Open the Excel workbook
-    let mut workbook: Xlsx<_> = open_workbook(file)
-        .context(format!("Failed to open Excel file: {:?}", file))?;
+/// - Returns error if the file can't be opened or isn't an .xlsx workbook
+/// - Returns error if the report can't be written
+fn run_comments(args: &CommentsArgs) -> Result<()> {
+    // "archive.zip!inner.xlsx" works here too
+    let (file, zip_entry) = match split_zip_path(&args.file).filter(|_| !args.file.exists()) {
+        Some((archive, entry)) => (archive, Some(entry)),
+        None => (args.file.clone(), None),
+    };
+    if is_xlsb(&file, zip_entry.as_deref()) {
+        anyhow::bail!("Cell comments are only read from .xlsx workbooks");
+    }
+    let mut workbook = open_workbook_input(&file, zip_entry.as_deref())?;
+    let mut parts = XlsxParts::open(&file, zip_entry.as_deref())?;
 
-    // Get the specified worksheet range (all cells with data)
-    workbook
-        .worksheet_range(sheet)
-        .context(format!("Sheet '{}' not found", sheet))
+    let mut report = Vec::new();
+    for sheet_name in workbook.sheet_names() {
+        let comments = parts.sheet_comments(&sheet_name)?;
+        if comments.is_empty() {
+            continue;
+        }
+        let range = workbook
+            .worksheet_range(&sheet_name)
+            .context(ConversionError::SheetNotFound(format!("Sheet '{}' not found", sheet_name)))?;
+        let mut comments: Vec<((u32, u32), String)> = comments.into_iter().collect();
+        comments.sort_unstable_by_key(|(position, _)| *position);
+        for ((row, col), comment) in comments {
+            let value = match range.get_value((row, col)) {
+                None | Some(calamine::Data::Empty) => Value::Null,
+                Some(cell) => Value::String(cell.to_string()),
+            };
+            report.push(serde_json::json!({
+                "sheet": sheet_name,
+                "cell": format!("{}{}", column_letters(col as usize), row + 1),
+                "value": value,
+                "comment": comment,
+            }));
+        }
+    }
+
+    let json = serde_json::to_string_pretty(&report).context(ConversionError::Write("Failed to serialize JSON".to_string()))?;
+    match &args.output {
+        Some(output) => {
+            fs::write(output, json).context(ConversionError::Write(format!("Failed to write {:?}", output)))?;
+            println!("{} comment(s) written to {:?}", report.len(), output);
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
 }
 
-/// Extracts and normalizes column headers for the specified column indices
+/// Finds headers that lose information when normalized (`excel2json headers --audit`)
+/// 
+/// Looks only at the header text and normalize_column_name(), not at --header-map
+/// or the other key options, since the fix is renaming the header in the sheet.
 /// 
 /// # Arguments
-/// * `header_row` - The first row containing column headers
-/// * `column_indices` - Vector of column indices to extract headers from
+/// * `original_headers` - The headers as written in the sheet
+/// * `split_case` - Whether --split-case keeps camelCase words apart
 /// 
 /// # Returns
-/// A vector of normalized header names suitable for use as JSON keys
+/// One line per problem, each naming the header(s) involved, and the number of
+/// headers flagged
 /// 
-/// # Behavior
-/// - Normalizes each header using normalize_column_name()
-/// - If a column index is out of bounds, generates a default name "column_N"
-fn extract_headers(
-    header_row: &[calamine::Data],
-    column_indices: &[usize],
-) -> Vec<String> {
-    column_indices
-        .iter() // Iterate through selected column indices
-        .map(|&i| {
-            header_row
-                .get(i) // Try to get the cell at this index
-                .map(|cell| normalize_column_name(&cell.to_string())) // Normalize if found
-                .unwrap_or_else(|| format!("column_{}", i + 1)) // Fallback name if not found
+/// # Examples
+/// - "Rate (#)" -> "'Rate (#)' -> 'rate': '#' is dropped"
+/// - "Email", "EMAIL" -> "'Email', 'EMAIL' -> 'email': the headers give the same key"
+fn audit_headers(original_headers: &[String], split_case: bool) -> (Vec<String>, usize) {
+    let headers: Vec<(&str, String)> = original_headers
+        .iter()
+        .map(|header| header.trim())
+        .filter(|header| !header.is_empty())
+        .map(|header| {
+            let key = if split_case {
+                normalize_column_name(&split_case_words(header))
+            } else {
+                normalize_column_name(header)
+            };
+            (header, key)
         })
-        .collect() // Collect into a vector of strings
+        .collect();
+
+    let mut findings = Vec::new();
+    let mut flagged: HashSet<&str> = HashSet::new();
+    for (position, (header, key)) in headers.iter().enumerate() {
+        // Each group of colliding headers is reported once, at its first header
+        let colliding: Vec<&str> = headers.iter().filter(|(_, other)| other == key).map(|(other, _)| *other).collect();
+        if colliding.len() > 1 && !headers[..position].iter().any(|(_, other)| other == key) {
+            let names: Vec<String> = colliding.iter().map(|name| format!("'{}'", name)).collect();
+            findings.push(format!("{} -> '{}': the headers give the same key", names.join(", "), key));
+            flagged.extend(colliding);
+        }
+        let mut problems = Vec::new();
+        if key.is_empty() {
+            problems.push("nothing is left for a key".to_string());
+        }
+        if *header != "#" && header.contains('#') {
+            problems.push("'#' is dropped".to_string());
+        }
+        if !split_case && split_case_words(header) != *header {
+            problems.push(format!(
+                "camelCase words run together (--split-case gives '{}')",
+                normalize_column_name(&split_case_words(header))
+            ));
+        }
+        if !problems.is_empty() {
+            findings.push(format!("'{}' -> '{}': {}", header, key, problems.join("; ")));
+            flagged.insert(header);
+        }
+    }
+    (findings, flagged.len())
 }
 
-/// Converts an Excel cell value to a JSON value
+/// Prints each header of a sheet next to the key it becomes (`excel2json headers`)
 /// 
-/// Currently converts all cell values to strings to preserve formatting
-/// and handle cases where numbers represent identifiers (like bullet numbers)
-/// rather than numeric values.
+/// The sheet goes through the same header steps as a conversion, so the keys
+/// reflect --header-rows, --header-map, --key-prefix, --on-duplicate and the
+/// other options given; no records are converted or written. With --audit the
+/// headers that lose information when normalized are listed after the keys.
 /// 
 /// # Arguments
-/// * `cell` - Reference to a cell from the Excel sheet
+/// * `headers` - Arguments of the headers command, with its conversion options completed
+///   (see complete_args())
 /// 
-/// # Returns
-/// A serde_json::Value representing the cell content as a string
-fn convert_cell_to_json(cell: &calamine::Data) -> Value {
-    // Convert all values to strings to preserve formatting
-    // This is useful for bullet numbers, IDs, and other non-numeric data
-    json!(cell.to_string())
+/// # Errors
+/// - Returns error if the sheet can't be read or its headers are rejected
+/// - Returns error if the audit flags any header (exit code 5)
+fn run_headers(headers: &HeadersArgs) -> Result<()> {
+    let args = &headers.convert;
+    let mut skipped = Vec::new();
+    let mut flagged = 0;
+    for sheet in prepare_input(args, &input_files(args)[0], &mut skipped)? {
+        println!("Sheet '{}':", sheet.sheet_name);
+        let width = sheet.original_headers.iter().map(|header| header.chars().count()).max().unwrap_or(0);
+        for (original, key) in sheet.original_headers.iter().zip(&sheet.keys) {
+            let original = if original.trim().is_empty() { "(blank)" } else { original.as_str() };
+            println!("  {:<width$}  ->  {}", original, key, width = width.max("(blank)".len()));
+        }
+        if headers.audit {
+            let (findings, count) = audit_headers(&sheet.original_headers, args.split_case);
+            if findings.is_empty() {
+                println!("Audit: no header loses information");
+            } else {
+                println!("Audit: {} header(s) to review:", count);
+                for finding in &findings {
+                    println!("  {}", finding);
+                }
+            }
+            flagged += count;
+        }
+    }
+    if flagged > 0 {
+        return Err(ConversionError::InvalidColumns(format!(
+            "{} header(s) flagged by the audit; rename them in the sheet",
+            flagged
+        ))
+        .into());
+    }
+    Ok(())
 }
 
-/// Converts Excel rows to JSON objects
-/// 
-/// Each row becomes a JSON object where keys are the normalized column headers
-/// and values are the cell contents.
+/// Runs the conversion once and, with --watch, keeps re-running it on changes
 /// 
 /// # Arguments
-/// * `rows` - Iterator over Excel rows (excluding the header row)
-/// * `headers` - Vector of normalized column header names
-/// * `column_indices` - Vector of column indices to include in the output
+/// * `args` - Parsed command-line arguments
+fn run(args: &Args) -> Result<()> {
+    // Run the initial conversion
+    run_conversion(args)?;
+
+    // Keep re-converting on changes if requested
+    if args.watch {
+        watch_and_reconvert(args)?;
+    }
+
+    Ok(())
+}
+
+/// Options that can be given a default in a config file (.excel2json.toml or --config)
 /// 
-/// # Returns
-/// A vector of JSON values, where each value is an object representing one row
+/// Keys are the long option names, e.g. `infer-types = true` or `dates = "iso"`.
+/// Options given on the command line take precedence over the file.
 /// 
 /// # Example
-/// Input row: ["John", "25", "john@example.com"]
-/// Headers: ["name", "age", "email"]
-/// Output: {"name": "John", "age": "25", "email": "john@example.com"}
-fn convert_rows_to_json<'a>(
-    rows: impl Iterator<Item = &'a [calamine::Data]>,
-    headers: &[String],
-    column_indices: &[usize],
-) -> Vec<Value> {
-    rows.map(|row| {
-        // Create a JSON object for this row
-        let json_obj: serde_json::Map<String, Value> = column_indices
-            .iter() // Iterate through selected columns
-            .enumerate() // Get index for matching with headers
-            .map(|(header_idx, &col_idx)| {
-                // Get cell value or use null if cell doesn't exist
-                let value = row
-                    .get(col_idx) // Try to get the cell at this column index
-                    .map(convert_cell_to_json) // Convert to JSON if found
-                    .unwrap_or(json!(null)); // Use null if cell is missing
-                // Create key-value pair: (header_name, cell_value)
-                (headers[header_idx].clone(), value)
-            })
-            .collect(); // Collect into a Map
-        json!(json_obj) // Convert Map to JSON Value
-    })
-    .collect() // Collect all row objects into a vector
-}
+/// ```toml
+/// columns = "1-3,5"
+/// format = "ndjson"
+/// dates = "iso"
+/// infer-types = true
+/// ```
+const CONFIG_OPTIONS: &[&str] = &[
+    "columns", "header-rows", "header-join", "merge-header-with-parent", "parent-separator",
+    "include-blank-columns", "infer-types", "strip-currency", "sample-size", "strict-types",
+    "strict", "float-precision", "locale", "decimal-separator", "dates", "on-duplicate",
+    "dedup-headers-case-insensitive", "validate-json-keys", "key-prefix", "numeric-header-prefix", "key-style",
+    "normalize-unicode", "normalize-unicode-values", "split-case", "nested", "nested-delimiter",
+    "formulas", "trim", "max-cell-length", "truncate-marker", "sort-by", "only-visible-rows",
+    "ignore-hidden-columns", "use-print-area", "trim-sheet-whitespace-rows", "lazy-read",
+    "with-hyperlinks", "with-comments", "with-formats", "with-sheet-name", "consolidate",
+    "output-shape", "sheet-key-style", "with-source-file", "index-key", "index-start",
+    "strict-schema", "on-missing-sheet", "retry", "retry-delay", "unique", "fail-on-empty",
+    "chunk-size", "buffer-size", "format", "ensure-ascii", "no-scientific", "bom", "sort-keys",
+    "compact-empty", "drop-empty-records", "toml-key", "xml-root", "wrap-key", "wrap-count",
+    "comment-header", "name-template", "quiet", "stats-json", "column-types",
+];
 
-/// Writes JSON data to a file with pretty formatting
+/// Reads the config file into command-line arguments
+/// 
+/// Each key becomes the option of the same name: `dates = "iso"` gives "--dates=iso",
+/// `infer-types = true` gives "--infer-types" and `false` leaves the flag off.
+/// Options given on the command line are left out, so they take precedence (and
+/// --columns-file replaces a `columns` default). The arguments are parsed together
+/// with the command line (see parse_command_line()), so clap checks their values,
+/// conflicts and requirements like those of any other option.
 /// 
 /// # Arguments
-/// * `json_array` - Array of JSON values to write
-/// * `output` - Path where the JSON file should be created
+/// * `path` - The file given with --config, or None for .excel2json.toml if present
+/// * `matches` - The conversion arguments given on the command line
 /// 
 /// # Returns
-/// Result indicating success or failure
+/// The arguments; none if the default file doesn't exist
 /// 
 /// # Errors
-/// - Returns error if JSON serialization fails
-/// - Returns error if file cannot be created
-/// - Returns error if writing to file fails
-fn write_json_to_file(json_array: &[Value], output: &PathBuf) -> Result<()> {
-    // Serialize JSON array to a pretty-printed string
-    let json_output = serde_json::to_string_pretty(json_array)
-        .context("Failed to serialize JSON")?;
+/// - Returns error if a file given with --config can't be read
+/// - Returns error if the file isn't valid TOML, or has an unknown key or a value that
+///   isn't a string, number or boolean
+fn config_arguments(path: Option<&Path>, matches: &clap::ArgMatches) -> Result<Vec<String>> {
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => (Path::new(DEFAULT_CONFIG_FILE), false),
+    };
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        // No config file in the current directory - built-in defaults apply
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).context(format!("Failed to read config file {:?}", path)),
+    };
+    let table: toml::Table = toml::from_str(&text).context(format!("Invalid config file {:?}", path))?;
 
-    // Create the output file (overwrites if exists)
-    let mut file = File::create(output)
-        .context(format!("Failed to create output file: {:?}", output))?;
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let mut arguments = Vec::new();
+    for (key, value) in table {
+        if !CONFIG_OPTIONS.contains(&key.as_str()) {
+            anyhow::bail!("Invalid config file {:?}: unknown option '{}'", path, key)
+        }
+        let id = key.replace('-', "_");
+        if from_cli(&id) || (id == "columns" && from_cli("columns_file")) {
+            continue;
+        }
+        let value = match value {
+            toml::Value::Boolean(true) => {
+                arguments.push(format!("--{}", key));
+                continue;
+            }
+            toml::Value::Boolean(false) => continue,
+            toml::Value::String(text) => text,
+            toml::Value::Integer(number) => number.to_string(),
+            toml::Value::Float(number) => number.to_string(),
+            _ => anyhow::bail!("Invalid config file {:?}: '{}' must be a string, number or boolean", path, key),
+        };
+        arguments.push(format!("--{}={}", key, value));
+    }
+    Ok(arguments)
+}
 
-    // Write the JSON string to the file
-    file.write_all(json_output.as_bytes())
-        .context("Failed to write to output file")?;
+/// Reads a --columns-file into a comma-separated --columns list
+/// 
+/// Blank lines and lines starting with "#" are skipped; the rest may hold one
+/// column identifier or several separated by commas.
+/// 
+/// # Example
+/// "# ids\n1-3\nEmail, Phone\n" -> "1-3,Email,Phone"
+/// 
+/// # Errors
+/// - Returns error if the file can't be read
+/// - Returns error if the file lists no columns
+fn read_columns_file(path: &Path) -> Result<String> {
+    let text = fs::read_to_string(path).context(format!("Failed to read columns file {:?}", path))?;
+    let items: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#')) // Skip blanks and comments
+        .flat_map(|line| line.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty()) // Tolerate trailing commas
+        .collect();
+    if items.is_empty() {
+        anyhow::bail!("Columns file {:?} doesn't list any columns", path)
+    }
+    Ok(items.join(","))
+}
 
+/// Checks argument combinations that clap can't express declaratively
+/// 
+/// # Errors
+/// Returns a clap usage error (exit code 2) if:
+/// - --append is used with a format other than ndjson - a JSON array or TOML
+///   document can't be extended by appending
+/// - --ensure-ascii is used with a format other than json or ndjson
+/// - --group-by is used with a format other than json
+/// - --output - (standard output) is used with an option that needs a file, or
+///   with --format msgpack while stdout is a terminal
+fn check_arg_combinations(args: &Args) -> std::result::Result<(), clap::Error> {
+    if args.output.as_deref().is_some_and(is_stdout) {
+        let needs_file = [(args.append, "--append"), (args.chunk_size.is_some(), "--chunk-size"), (args.watch, "--watch")];
+        if let Some((_, option)) = needs_file.into_iter().find(|(given, _)| *given) {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{} writes to a file and can't be used with --output - (standard output)", option),
+            ));
+        }
+        if args.format == OutputFormat::Msgpack && std::io::stdout().is_terminal() {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--format msgpack is binary and isn't written to a terminal: redirect standard output \
+                 to a file or pipe, or give an output file",
+            ));
+        }
+    }
+    if let Some(range) = &args.range {
+        let several_sheets = args.merge_sheets || args.sheets.is_some();
+        if range.sheet.is_some() && several_sheets {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "A sheet-qualified --range reads one sheet; it can't be combined with --merge-sheets or --sheets",
+            ));
+        }
+        if range.sheet.is_none() && !several_sheets && args.sheet.is_none() && args.sheet_index.is_none() {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                format!(
+                    "--range {} needs a sheet: give the sheet name or --sheet-index, or qualify it (Sheet1!{})",
+                    range.reference, range.reference
+                ),
+            ));
+        }
+    }
+    if !args.merge.is_empty() && keyed_by_sheet(args) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("{} can't be used with several input files (--merge)", keyed_by_sheet_reason(args)),
+        ));
+    }
+    if args.consolidate && !args.merge_sheets && args.sheets.is_none() && args.merge.is_empty() {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--consolidate combines several sheets: add --merge-sheets, --sheets or more input files with --merge",
+        ));
+    }
+    if (args.sheet_key_style != SheetKeyStyle::Raw || args.sheet_rename.is_some()) && !keyed_by_sheet(args) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--sheet-key-style and --sheet-rename only apply to output keyed by sheet \
+             (--sheets without --with-sheet-name, or --output-shape object)",
+        ));
+    }
+    if args.consolidate && args.output_shape == OutputShape::Object {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--consolidate writes one array and can't be used with --output-shape object",
+        ));
+    }
+    if args.with_source_file.is_some() && args.with_source_file == args.with_sheet_name {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--with-source-file and --with-sheet-name need different field names",
+        ));
+    }
+    if let Some(option) = args.index_key.as_ref().and_then(|field| {
+        if args.with_sheet_name.as_ref() == Some(field) {
+            Some("--with-sheet-name")
+        } else if args.with_source_file.as_ref() == Some(field) {
+            Some("--with-source-file")
+        } else {
+            None
+        }
+    }) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("--index-key and {} need different field names", option),
+        ));
+    }
+    if args.append && args.format != OutputFormat::Ndjson {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--append can't be used with --format {0}: appending would break the {0} structure. \
+                 Use --format ndjson to accumulate records across runs",
+                args.format.extension()
+            ),
+        ));
+    }
+    if args.group_by.is_some() && args.format != OutputFormat::Json {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--group-by writes a JSON object of arrays and can't be used with --format {}",
+                args.format.extension()
+            ),
+        ));
+    }
+    if args.wrap_key.is_some() && args.format != OutputFormat::Json {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--wrap-key wraps the JSON array in an object and can't be used with --format {}",
+                args.format.extension()
+            ),
+        ));
+    }
+    if args.wrap_key.is_some() && keyed_by_sheet(args) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--wrap-key can't be used with {}, which writes an object keyed by sheet name",
+                keyed_by_sheet_reason(args)
+            ),
+        ));
+    }
+    if args.wrap_count && args.wrap_key.as_deref() == Some("count") {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--wrap-count adds a \"count\" key, so --wrap-key needs another name",
+        ));
+    }
+    if args.comment_header && args.wrap_key.as_deref() == Some("_meta") {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--comment-header adds a \"_meta\" key, so --wrap-key needs another name",
+        ));
+    }
+    if keyed_by_sheet(args) && (args.format != OutputFormat::Json || args.group_by.is_some()) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "{} writes a JSON object keyed by sheet name, \
+                 so it needs --format json and can't be combined with --group-by. \
+                 Use --output-shape array to get one array of records instead",
+                keyed_by_sheet_reason(args)
+            ),
+        ));
+    }
+    // These read the xlsx XML parts, which a binary workbook doesn't have
+    if input_files(args).iter().any(|input| is_xlsb(&input.file, input.zip_entry.as_deref())) {
+        let xml_only = [
+            (args.table.is_some(), "--table"),
+            (args.with_hyperlinks, "--with-hyperlinks"),
+            (args.with_comments, "--with-comments"),
+            (args.with_formats, "--with-formats"),
+            (args.only_visible_rows, "--only-visible-rows"),
+            (args.ignore_hidden_columns, "--ignore-hidden-columns"),
+        ];
+        if let Some((_, option)) = xml_only.iter().find(|(used, _)| *used) {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!("{} needs an .xlsx workbook and can't read .xlsb files", option),
+            ));
+        }
+    }
+    if let Some(prefix) = &args.numeric_header_prefix {
+        // "column_" + "3" would look like the name given to a column without a header
+        let generated = Regex::new(r"^column_[0-9]+$").expect("valid regex");
+        if prefix.is_empty() {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ValueValidation,
+                "--numeric-header-prefix can't be empty",
+            ));
+        }
+        if generated.is_match(&format!("{}1", prefix)) {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ValueValidation,
+                format!(
+                    "--numeric-header-prefix '{}' would make keys like the \"column_N\" names of columns without a header; choose another prefix, e.g. \"col_\"",
+                    prefix
+                ),
+            ));
+        }
+    }
+    if args.decimal_separator.is_some() && !args.infer_types && args.numeric_columns.is_none() {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--decimal-separator parses text into numbers: add --infer-types or --numeric-columns",
+        ));
+    }
+    if args.float_precision.is_some() && !args.infer_types && args.numeric_columns.is_none() {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--float-precision rounds values written as JSON numbers: add --infer-types or --numeric-columns",
+        ));
+    }
+    if args.merge_header_with_parent && args.header_rows < 2 {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--merge-header-with-parent combines a parent header row with the row below it: \
+             add --header-rows 2 (or more)",
+        ));
+    }
+    if args.chunk_size.is_some() && keyed_by_sheet(args) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--chunk-size splits an array of records and can't be used with {}. \
+                 Use --output-shape array to get one array of records instead",
+                keyed_by_sheet_reason(args)
+            ),
+        ));
+    }
+    if args.normalize_unicode_values && args.normalize_unicode.is_none() {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::MissingRequiredArgument,
+            "--normalize-unicode-values needs a form: add --normalize-unicode nfc or --normalize-unicode nfkc",
+        ));
+    }
+    if args.ensure_ascii && !matches!(args.format, OutputFormat::Json | OutputFormat::Ndjson) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--ensure-ascii only applies to json and ndjson output, not --format {}",
+                args.format.extension()
+            ),
+        ));
+    }
+    if args.nested && args.format == OutputFormat::Xml {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--nested objects can't be written as --format xml fields; use json, ndjson, toml or msgpack",
+        ));
+    }
+    if args.no_scientific && !matches!(args.format, OutputFormat::Json | OutputFormat::Ndjson) {
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!(
+                "--no-scientific only applies to json and ndjson output, not --format {}",
+                args.format.extension()
+            ),
+        ));
+    }
+    if args.split_sheets {
+        if !args.merge_sheets && args.sheets.is_none() {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "--split-sheets writes a file per sheet: choose the sheets with --merge-sheets or --sheets",
+            ));
+        }
+        if args.output_shape == OutputShape::Object {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--split-sheets can't be used with --output-shape object, which keeps every sheet in one file",
+            ));
+        }
+        if let Some(template) = args.name_template.as_ref().filter(|template| !template.contains("{sheet}")) {
+            return Err(Cli::command().error(
+                clap::error::ErrorKind::ValueValidation,
+                format!(
+                    "--name-template '{}' has no {{sheet}} placeholder, so every sheet would be written to the same file (--split-sheets)",
+                    template
+                ),
+            ));
+        }
+    }
+    if args.lazy_read && args.formulas == FormulaMode::Text {
+        // Formulas are looked up cell by cell in a fully loaded range
+        return Err(Cli::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--formulas text can't be used with --lazy-read",
+        ));
+    }
     Ok(())
 }
 
+/// Maps an error to the process exit code of its failure class
+/// 
+/// # Returns
+/// The ConversionError's exit code if one is attached anywhere in the chain, 1 otherwise
+fn exit_code_for(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<ConversionError>()
+        .map(ConversionError::exit_code)
+        .unwrap_or(1)
+}
+
 /// Main entry point for the Excel to JSON converter
 /// 
-/// Process flow:
-/// 1. Parse command-line arguments
-/// 2. Open Excel file and read specified sheet
-/// 3. Identify visible columns (non-empty headers)
-/// 4. Parse user-specified column selection (if provided)
-/// 5. Extract and normalize column headers
-/// 6. Convert all data rows to JSON objects
-/// 7. Write JSON output to file
-/// 8. Display summary statistics
+/// Parses the command-line arguments and runs the conversion. Failures are
+/// printed to stderr and reported through distinct exit codes:
+/// 
+/// | Code | Meaning |
+/// |------|---------|
+/// | 0 | Success |
+/// | 1 | Other error |
+/// | 2 | Invalid command-line arguments (reported by clap) |
+/// | 3 | Input file can't be opened |
+/// | 4 | Sheet not found |
+/// | 5 | Invalid column specification |
+/// | 6 | Output can't be written |
+/// | 7 | No records produced (with --fail-on-empty) |
 /// 
 /// # Returns
-/// Result indicating success or failure of the conversion process
-fn main() -> Result<()> {
-    // Step 1: Parse command-line arguments
-    let args = Args::parse();
-
-    // Step 2: Open Excel file and read the specified sheet
-    let range = read_excel_sheet(&args.file, &args.sheet)?;
-    let mut rows = range.rows();
+/// The process exit code
+fn main() -> ExitCode {
+    let cli = parse_command_line(std::env::args_os());
+    let result = match (cli.command, cli.convert) {
+        (Some(Command::Preview(args)), _) => run_preview(&args),
+        (Some(Command::Comments(args)), _) => run_comments(&args),
+        (Some(Command::Headers(args)), _) => run_headers(&args),
+        (None, Some(args)) => {
+            let has_comments = matches!(args.format, OutputFormat::Toml | OutputFormat::Xml) || args.wrap_key.is_some();
+            if args.comment_header && !has_comments && !args.quiet {
+                eprintln!(
+                    "Warning: --format {} has no comments, so --comment-header has no effect (use --wrap-key for a \"_meta\" object)",
+                    args.format.extension()
+                );
+            }
+            run(&args)
+        }
+        (None, None) => unreachable!("clap requires the conversion arguments without a subcommand"),
+    };
 
-    // Step 3: Extract the header row (first row)
-    let header_row = rows
-        .next() // Get first row
-        .context("Excel sheet is empty, no header row found")?;
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            // Same human-readable format as returning the error from main()
+            eprintln!("Error: {:?}", err);
+            ExitCode::from(exit_code_for(&err))
+        }
+    }
+}
 
-    // Step 4: Identify which columns have non-empty headers (visible columns)
-    let visible_indices = get_visible_column_indices(header_row);
+/// Parses the command line, completing the conversion arguments of a conversion
+/// or of `excel2json headers`
+/// 
+/// Exits with code 2 on invalid arguments.
+/// 
+/// # Arguments
+/// * `argv` - The command line, program name first
+fn parse_command_line(argv: impl IntoIterator<Item = std::ffi::OsString>) -> Cli {
+    // Parse command-line arguments (exits with code 2 on invalid arguments)
+    let mut argv: Vec<std::ffi::OsString> = argv.into_iter().collect();
+    let mut matches = Cli::command().get_matches_from(&argv);
 
-    // Step 5: Determine which columns to include in the output
-    // Either use user-specified columns or all visible columns
-    let column_indices: Vec<usize> = if let Some(ref cols_str) = args.columns {
-        // User specified specific columns - parse and validate them
-        parse_visible_column_numbers(cols_str, &visible_indices)?
-    } else {
-        // No columns specified - use all visible columns
-        visible_indices
+    // Options from the config file are parsed again with the command line, placed right
+    // after the program (or subcommand) name, so clap validates them like the rest
+    let conversion = match matches.subcommand() {
+        None => Some((1, &matches)),
+        Some(("headers", headers)) => Some((2, headers)),
+        Some(_) => None,
     };
+    if let Some((position, conversion)) = conversion {
+        let path = conversion.get_one::<PathBuf>("config").cloned();
+        let config = config_arguments(path.as_deref(), conversion).unwrap_or_else(|err| {
+            Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, format!("{:#}", err))
+                .exit()
+        });
+        if !config.is_empty() {
+            argv.splice(position..position, config.iter().map(std::ffi::OsString::from));
+            matches = Cli::command().try_get_matches_from(&argv).unwrap_or_else(|err| {
+                let _ = err.print();
+                let file = path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+                eprintln!("\nNote: the config file {:?} added: {}", file, config.join(" "));
+                std::process::exit(err.exit_code())
+            });
+        }
+    }
 
-    // Step 6: Extract and normalize the column headers
-    let headers = extract_headers(header_row, &column_indices);
-    
-    // Step 7: Convert all data rows to JSON objects
-    let json_array = convert_rows_to_json(rows, &headers, &column_indices);
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    match (&mut cli.command, &mut cli.convert) {
+        (Some(Command::Headers(headers)), _) => complete_args(&mut headers.convert),
+        (None, Some(args)) => complete_args(args),
+        _ => {}
+    }
+    cli
+}
 
-    // Step 8: Write the JSON array to the output file
-    write_json_to_file(&json_array, &args.output)?;
+/// Completes the conversion arguments parsed by clap
+/// 
+/// Applies --columns-file and checks the option combinations clap can't
+/// express; exits with code 2 on invalid arguments.
+/// 
+/// # Arguments
+/// * `args` - The conversion arguments as parsed (config file options included)
+fn complete_args(args: &mut Args) {
+    if let Some(path) = &args.columns_file {
+        match read_columns_file(path) {
+            Ok(columns) => args.columns = Some(columns),
+            Err(err) => Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, format!("{:#}", err))
+                .exit(),
+        }
+    }
+    if args.consolidate && args.with_sheet_name.is_none() {
+        // Consolidated records always say which sheet they came from
+        args.with_sheet_name = Some("_sheet".to_string());
+    }
+    if let Err(err) = check_arg_combinations(args) {
+        err.exit();
+    }
+    // "archive.zip!inner.xlsx" is shorthand for --zip-entry (unless a file has that exact name)
+    if args.zip_entry.is_none()
+        && !args.file.exists()
+        && let Some((archive, entry)) = split_zip_path(&args.file)
+    {
+        args.file = archive;
+        args.zip_entry = Some(entry);
+    }
+}
 
-    // Step 9: Display success message and statistics
-    println!("Successfully converted Excel to JSON");
-    println!("Input: {:?}", args.file);
-    println!("Sheet: {}", args.sheet);
-    println!("Output: {:?}", args.output);
-    println!("Visible columns: {}", column_indices.len());
-    println!("Total records: {}", json_array.len());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    /// A range from rows of cells, "" standing for an empty cell
+    fn range_of(rows: &[&[&str]]) -> calamine::Range<calamine::Data> {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut range = calamine::Range::new((0, 0), (rows.len() as u32 - 1, width as u32 - 1));
+        for (row, cells) in rows.iter().enumerate() {
+            for (col, text) in cells.iter().enumerate().filter(|(_, text)| !text.is_empty()) {
+                range.set_value((row as u32, col as u32), calamine::Data::String(text.to_string()));
+            }
+        }
+        range
+    }
+
+    #[test]
+    fn only_rows_wider_than_the_header_are_ragged() {
+        let range = range_of(&[
+            &["id", "name", "note", ""],
+            &["1", "a", "x", ""],
+            &["2", "b", "", ""], // Blank last column - a normal sparse row
+            &["3", "", "", ""],
+            &["", "", "", ""],
+            &["5", "e", "y", "stray"], // Value under a column without a header
+        ]);
+        let header_width = effective_width(range.rows().next().unwrap());
+        assert_eq!(header_width, 3);
+        assert_eq!(find_ragged_rows(&range, 1, header_width), [(6, 4)]);
+    }
+
+    #[test]
+    fn strict_accepts_sparse_rows() {
+        let range = range_of(&[&["id", "name", "note"], &["1", "a", ""], &["2", "", ""], &["3", "c", "z"]]);
+        let header_row: Vec<calamine::Data> = range.rows().next().unwrap().to_vec();
+        for option in ["--strict", "--strict-width"] {
+            let args = Cli::parse_from(["excel2json", "in.xlsx", "Sheet1", "-o", "out.json", option]).convert.unwrap();
+            assert!(check_row_widths(&args, "Sheet1", &range, &header_row).is_ok());
+        }
+
+        let range = range_of(&[&["id", "name", ""], &["1", "a", "stray"]]);
+        let header_row: Vec<calamine::Data> = range.rows().next().unwrap().to_vec();
+        let args = Cli::parse_from(["excel2json", "in.xlsx", "Sheet1", "-o", "out.json", "--strict"]).convert.unwrap();
+        let err = check_row_widths(&args, "Sheet1", &range, &header_row).unwrap_err();
+        assert_eq!(exit_code_for(&err), 5);
+    }
+
+    #[test]
+    fn headers_takes_audit_anywhere_among_the_options() {
+        for argv in [
+            ["excel2json", "headers", "--audit", "in.xlsx", "Sheet1"],
+            ["excel2json", "headers", "in.xlsx", "--audit", "Sheet1"],
+            ["excel2json", "headers", "in.xlsx", "Sheet1", "--audit"],
+        ] {
+            let Some(Command::Headers(headers)) = Cli::parse_from(argv).command else {
+                panic!("expected the headers command");
+            };
+            assert!(headers.audit);
+            assert_eq!(headers.convert.sheet.as_deref(), Some("Sheet1"));
+        }
+    }
+
+    #[test]
+    fn merge_takes_its_own_list_of_input_files() {
+        let args = Cli::parse_from(["excel2json", "a.xlsx", "Sheet1", "--merge", "b.xlsx", "c.zip!d.xlsx", "-o", "out.json"])
+            .convert
+            .unwrap();
+        assert_eq!(args.sheet.as_deref(), Some("Sheet1"));
+        let labels: Vec<String> = input_files(&args).iter().map(InputFile::label).collect();
+        assert_eq!(labels, ["a.xlsx", "b.xlsx", "c.zip!d.xlsx"]);
+        assert_eq!(input_files(&args)[2].zip_entry.as_deref(), Some("d.xlsx"));
+
+        // The sheet is still chosen one way only
+        let err = Cli::try_parse_from(["excel2json", "a.xlsx", "Sheet1", "--sheet-index", "1", "-o", "out.json"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        let err = Cli::try_parse_from(["excel2json", "a.xlsx", "-o", "out.json"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        assert!(Cli::try_parse_from(["excel2json", "a.xlsx", "--range", "Sheet1!A1:B2", "-o", "out.json"]).is_ok());
+    }
+
+    #[test]
+    fn config_file_options_become_arguments() {
+        let path = std::env::temp_dir().join(format!("excel2json-test-{}.toml", std::process::id()));
+        fs::write(&path, "infer-types = true\nbom = false\nformat = \"ndjson\"\nheader-rows = 2\ncolumns = \"1-3\"\n").unwrap();
+        let matches = Cli::command().get_matches_from(["excel2json", "a.xlsx", "Sheet1", "-o", "out.json", "--header-rows", "3"]);
+        let mut arguments = config_arguments(Some(&path), &matches).unwrap();
+        arguments.sort();
+        // header-rows was given on the command line, which wins; false flags stay off
+        assert_eq!(arguments, ["--columns=1-3", "--format=ndjson", "--infer-types"]);
+
+        let matches = Cli::command().get_matches_from(["excel2json", "a.xlsx", "Sheet1", "-o", "out.json", "--columns-file", "c.txt"]);
+        assert!(!config_arguments(Some(&path), &matches).unwrap().iter().any(|arg| arg.starts_with("--columns")));
+
+        fs::write(&path, "no-such-option = 1\n").unwrap();
+        let err = config_arguments(Some(&path), &matches).unwrap_err();
+        assert!(err.to_string().contains("unknown option 'no-such-option'"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn lazy_read_counts_rows_and_notes_ragged_ones() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ragged.xlsx");
+        let args = Cli::parse_from(["excel2json", file.to_str().unwrap(), "S", "-o", "out.json", "--lazy-read"]).convert.unwrap();
+        let mut sheets = prepare_input(&args, &input_files(&args)[0], &mut Vec::new()).unwrap();
+        let sheet = sheets.remove(0);
+        // Counted from the declared dimension before any data row is read
+        assert_eq!(sheet.data_row_count(), 3);
+
+        let opts = convert_options(&args);
+        let mut stats = ConvertStats::default();
+        assert_eq!(sheet.records(&opts, &ProgressBar::hidden(), &mut stats).count(), 3);
+        let lazy = sheet.lazy_rows.unwrap();
+        assert_eq!(lazy.ragged.take(), [(3, 3)]);
+        lazy.finish().unwrap();
+    }
+
+    #[test]
+    fn numeric_header_prefix_only_touches_all_numeric_keys() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/years.xlsx");
+        let headers = |extra: &[&str]| {
+            let mut argv = vec!["excel2json", file.to_str().unwrap(), "Sales", "-o", "out.json"];
+            argv.extend_from_slice(extra);
+            let args = Cli::parse_from(argv).convert.unwrap();
+            check_arg_combinations(&args).unwrap();
+            prepare_input(&args, &input_files(&args)[0], &mut Vec::new()).unwrap().remove(0).headers
+        };
+        // Header cells 2021 and 2022 are numbers, "2023.5" is text
+        assert_eq!(headers(&[]), ["region", "2021", "2022", "2023.5"]);
+        assert_eq!(headers(&["--numeric-header-prefix", "y_"]), ["region", "y_2021", "y_2022", "y_2023.5"]);
+        // Applied before --key-prefix, which then has no leading digit left to prefix
+        assert_eq!(
+            headers(&["--numeric-header-prefix", "y_", "--key-prefix", "k_"]),
+            ["region", "y_2021", "y_2022", "y_2023.5"]
+        );
+        assert_eq!(headers(&["--key-prefix", "k_"]), ["region", "k_2021", "k_2022", "k_2023.5"]);
+
+        // A prefix that gives "column_N" keys would clash with columns without a header
+        let args = Cli::parse_from(["excel2json", "a.xlsx", "Sheet1", "-o", "out.json", "--numeric-header-prefix", "column_"])
+            .convert
+            .unwrap();
+        assert_eq!(check_arg_combinations(&args).unwrap_err().kind(), clap::error::ErrorKind::ValueValidation);
+    }
+
+    #[test]
+    fn json_meta_names_the_generator_like_the_comments() {
+        let provenance = Provenance {
+            source: "data.xlsx".to_string(),
+            sheet: "Sheet1".to_string(),
+            generated: "2026-10-16T10:43:48Z".to_string(),
+        };
+        let meta = provenance.to_json();
+        let generator = meta["generator"].as_str().unwrap();
+        assert_eq!(generator, format!("excel2json {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(provenance.lines()[2], format!("Generated: {} by {}", meta["generated"].as_str().unwrap(), generator));
+    }
+
+    #[test]
+    fn key_style_only_changes_the_written_keys() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/people.xlsx");
+        let output = std::env::temp_dir().join(format!("excel2json-key-style-{}.json", std::process::id()));
+        let args = Cli::parse_from([
+            "excel2json",
+            file.to_str().unwrap(),
+            "Sheet1",
+            "-o",
+            output.to_str().unwrap(),
+            "--key-style",
+            "camel",
+            "--columns",
+            "Customer ID,Notes",
+            "--sort-by",
+            "customer_id:desc",
+        ])
+        .convert
+        .unwrap();
+        let sheets = prepare_input(&args, &input_files(&args)[0], &mut Vec::new()).unwrap();
+        assert_eq!(sheets[0].headers, ["customer_id", "notes"]);
+        assert_eq!(sheets[0].keys, ["customerId", "notes"]);
+
+        // --nested parts get the style too
+        let paths = NestPaths::from([("notes".to_string(), vec!["extra_info".to_string(), "notes".to_string()])]);
+        let renames = key_renames(&args, sheets.iter(), Some(&paths));
+        let record = shape_record(json!({"customer_id": "C-1", "notes": ""}), Some(&paths), &renames).unwrap();
+        assert_eq!(record, json!({"customerId": "C-1", "extraInfo": {"notes": ""}}));
+
+        // The options name the columns in snake_case, the file has the styled keys
+        run_conversion(&args).unwrap();
+        let records: Value = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert_eq!(records, json!([{"customerId": "C-2", "notes": "vip"}, {"customerId": "C-1", "notes": ""}]));
+    }
+
+    #[test]
+    fn typed_column_lists_must_name_selected_columns_once() {
+        assert_eq!(parse_column_list(&Some(" Price, Unit Cost,,".to_string())), ["price", "unit_cost"]);
+        assert!(parse_column_list(&None).is_empty());
+
+        let headers = ["id".to_string(), "price".to_string(), "active".to_string()];
+        let opts = |extra: &[&str]| {
+            let mut argv = vec!["excel2json", "a.xlsx", "Sheet1", "-o", "out.json"];
+            argv.extend_from_slice(extra);
+            convert_options(&Cli::parse_from(argv).convert.unwrap())
+        };
+        assert!(check_typed_columns(&opts(&["--numeric-columns", "Price", "--string-columns", "ID"]), &headers).is_ok());
+
+        let err = check_typed_columns(&opts(&["--bool-columns", "enabled"]), &headers).unwrap_err();
+        assert_eq!(err.to_string(), "--bool-columns column 'enabled' is not among the selected columns");
+        let err = check_typed_columns(&opts(&["--string-columns", "price", "--date-columns", "Price"]), &headers).unwrap_err();
+        assert_eq!(err.to_string(), "Column 'price' is in both --string-columns and --date-columns");
+        assert!(matches!(err.downcast_ref(), Some(ConversionError::InvalidColumns(_))));
+    }
+
+    #[test]
+    fn stdout_output_rejects_options_that_need_a_file() {
+        let parse = |extra: &[&str]| {
+            let mut argv = vec!["excel2json", "a.xlsx", "Sheet1", "-o", "-", "--format", "ndjson"];
+            argv.extend_from_slice(extra);
+            check_arg_combinations(&Cli::parse_from(argv).convert.unwrap())
+        };
+        assert!(parse(&[]).is_ok());
+        for option in [&["--append"][..], &["--chunk-size", "10"], &["--watch"]] {
+            assert_eq!(parse(option).unwrap_err().kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+        assert!(is_stdout(Path::new("-")) && !is_stdout(Path::new("./-")));
+    }
+
+    #[test]
+    fn toml_reports_the_path_of_nested_nulls() {
+        let records = [json!({"id": 1}), json!({"id": 2, "address": {"city": "Bangkok", "zip": null}})];
+        let err = to_toml_document(&records, "records").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Record 2 has a null value for 'address.zip', which TOML can't represent"
+        );
+        assert_eq!(find_null(&json!({"tags": ["a", [null]]})), Some("tags[1][0]".to_string()));
+        assert_eq!(find_null(&json!({"rows": [{"v": null}]})), Some("rows[0].v".to_string()));
+        assert_eq!(find_null(&json!({"name": "a"})), None);
+    }
+
+    #[test]
+    fn sort_order_is_total_for_mixed_numbers_and_text() {
+        let mut records: Vec<Value> = ["1a", "10", "", "9", "b", "2.5"]
+            .iter()
+            .map(|value| json!({ "code": value }))
+            .collect();
+        let keys = [SortKey { key: "code".to_string(), descending: false }];
+        sort_records(&mut records, &keys);
+        let codes: Vec<&str> = records.iter().map(|record| record["code"].as_str().unwrap()).collect();
+        assert_eq!(codes, ["2.5", "9", "10", "1a", "b", ""]);
+
+        // Every pair must agree with the reversed comparison, or sort_by() may misorder
+        let values = ["9", "10", "1a", "NaN"].map(|value| json!(value));
+        for a in &values {
+            for b in &values {
+                assert_eq!(
+                    compare_json_values(Some(a), Some(b), false),
+                    compare_json_values(Some(b), Some(a), false).reverse()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,37 @@
+//! convert_bytes() on workbooks held in memory, as a web service would get them
+
+use excel2json::{convert_bytes, ConvertOptions};
+use serde_json::json;
+
+/// Sheet1: Name, Age, Customer ID, Notes / John, 25, C-1, (empty) / Jane, 30.5, C-2, vip
+const PEOPLE: &[u8] = include_bytes!("fixtures/people.xlsx");
+
+#[test]
+fn converts_an_uploaded_workbook() {
+    let records = convert_bytes(PEOPLE, "xlsx", "Sheet1", &ConvertOptions::default()).unwrap();
+    assert_eq!(
+        records,
+        [
+            json!({"name": "John", "age": "25", "customer_id": "C-1", "notes": ""}),
+            json!({"name": "Jane", "age": "30.5", "customer_id": "C-2", "notes": "vip"}),
+        ]
+    );
+
+    let opts = ConvertOptions::builder().infer_types(true).build();
+    let records = convert_bytes(PEOPLE, ".XLSX", "Sheet1", &opts).unwrap();
+    assert_eq!(records[0]["age"], json!(25));
+    assert_eq!(records[1]["age"], json!(30.5));
+    assert_eq!(records[0]["notes"], json!(null));
+}
+
+#[test]
+fn reports_unknown_formats_and_sheets() {
+    let opts = ConvertOptions::default();
+    let error = convert_bytes(PEOPLE, "csv", "Sheet1", &opts).unwrap_err();
+    assert!(error.to_string().contains("Unsupported workbook format 'csv'"));
+
+    let error = convert_bytes(PEOPLE, "xlsx", "Missing", &opts).unwrap_err();
+    assert!(error.to_string().contains("Sheet 'Missing' not found"));
+
+    assert!(convert_bytes(b"not a workbook", "xlsx", "Sheet1", &opts).is_err());
+}
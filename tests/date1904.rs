@@ -0,0 +1,40 @@
+//! Workbooks using the 1904 date system, detected from the file
+
+use excel2json::{convert_bytes, convert_file, uses_1904_dates, ConvertOptions, DateMode};
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+/// tests/fixtures/dates.xlsx with <workbookPr date1904="1"/>: serial 45000 is 2027-03-16
+const DATES_1904: &[u8] = include_bytes!("fixtures/dates1904.xlsx");
+/// tests/fixtures/sample.xlsb with the 1904 flag set in its BrtWbProp record
+const SAMPLE_1904: &[u8] = include_bytes!("fixtures/sample1904.xlsb");
+
+#[test]
+fn reads_the_date_system_flag() {
+    assert!(uses_1904_dates(Cursor::new(DATES_1904)).unwrap());
+    assert!(uses_1904_dates(Cursor::new(SAMPLE_1904)).unwrap());
+    assert!(!uses_1904_dates(Cursor::new(include_bytes!("fixtures/dates.xlsx"))).unwrap());
+    assert!(!uses_1904_dates(Cursor::new(include_bytes!("fixtures/sample.xlsb"))).unwrap());
+    assert!(uses_1904_dates(Cursor::new(b"not a workbook")).is_err());
+}
+
+#[test]
+fn converts_serials_from_1904() {
+    let opts = ConvertOptions::builder().dates(DateMode::Iso).build();
+    let records = convert_bytes(DATES_1904, "xlsx", "Dates", &opts).unwrap();
+    let serials: Vec<&Value> = records.iter().map(|record| &record["serial"]).collect();
+    assert_eq!(serials, [&json!("2027-03-16"), &json!("2027-03-16T12:00:00"), &json!("1904-01-01T18:00:00")]);
+    // Dates stored as ISO text are the same in either system
+    assert_eq!(records[0]["iso"], "2023-03-15");
+
+    let records = convert_bytes(SAMPLE_1904, "xlsb", "Data", &opts).unwrap();
+    assert_eq!(records[0]["day"], "2027-03-16");
+    assert_eq!(records[1]["day"], "1904-01-02");
+
+    let records = convert_file("tests/fixtures/sample1904.xlsb", "Data", &opts).unwrap();
+    assert_eq!(records[0]["day"], "2027-03-16");
+
+    let opts = ConvertOptions::builder().dates(DateMode::Epoch).build();
+    let records = convert_bytes(DATES_1904, "xlsx", "Dates", &opts).unwrap();
+    assert_eq!(records[0]["serial"], json!(1678838400 + 1462 * 86_400));
+}
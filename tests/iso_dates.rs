@@ -0,0 +1,45 @@
+//! Dates stored as serials and as ISO text ("d" cells) convert alike under every --dates mode
+
+use excel2json::{convert_bytes, ConvertOptions, DateMode};
+use serde_json::{json, Value};
+
+/// Dates: serial | iso, one row per value, date-formatted serials next to the same value as a "d" cell:
+/// 2023-03-15 / 2023-03-15T12:00:00 / 18:00:00
+const DATES: &[u8] = include_bytes!("fixtures/dates.xlsx");
+
+/// Converts the fixture and returns the (serial, iso) pair of each row
+fn convert(opts: &ConvertOptions) -> Vec<(Value, Value)> {
+    convert_bytes(DATES, "xlsx", "Dates", opts)
+        .unwrap()
+        .into_iter()
+        .map(|record| (record["serial"].clone(), record["iso"].clone()))
+        .collect()
+}
+
+#[test]
+fn serial_and_iso_cells_give_the_same_output() {
+    for dates in [DateMode::Serial, DateMode::Iso, DateMode::Time, DateMode::Epoch] {
+        for infer_types in [false, true] {
+            let opts = ConvertOptions::builder().dates(dates).infer_types(infer_types).build();
+            let rows = convert(&opts);
+            assert_eq!(rows.len(), 3);
+            for (serial, iso) in rows {
+                assert_eq!(serial, iso, "--dates {:?}, infer_types {}", dates, infer_types);
+            }
+        }
+    }
+}
+
+#[test]
+fn each_mode_writes_the_expected_values() {
+    let values = |dates| -> Vec<Value> {
+        convert(&ConvertOptions::builder().dates(dates).build()).into_iter().map(|(_, iso)| iso).collect()
+    };
+    assert_eq!(values(DateMode::Serial), [json!("45000"), json!("45000.5"), json!("0.75")]);
+    assert_eq!(
+        values(DateMode::Iso),
+        [json!("2023-03-15"), json!("2023-03-15T12:00:00"), json!("1899-12-31T18:00:00")]
+    );
+    assert_eq!(values(DateMode::Time), [json!("2023-03-15"), json!("2023-03-15T12:00:00"), json!("18:00:00")]);
+    assert_eq!(values(DateMode::Epoch), [json!(1678838400), json!(1678881600), json!(-2209010400i64)]);
+}
@@ -0,0 +1,29 @@
+//! Binary (.xlsb) workbooks convert like .xlsx ones
+
+use excel2json::{convert_bytes, convert_file, ConvertOptions, DateMode};
+use serde_json::json;
+
+/// Data: name, amount, count, day / John, 12.5, 42, 45000 (date) / shared string, -3.25, true, 1 (date)
+const SAMPLE: &[u8] = include_bytes!("fixtures/sample.xlsb");
+
+#[test]
+fn reads_cells_of_every_type() {
+    let opts = ConvertOptions::builder().infer_types(true).dates(DateMode::Iso).build();
+    let records = convert_bytes(SAMPLE, "xlsb", "Data", &opts).unwrap();
+    assert_eq!(
+        records,
+        [
+            json!({"name": "John", "amount": 12.5, "count": 42, "day": "2023-03-15"}),
+            json!({"name": "shared ข้อความ", "amount": -3.25, "count": true, "day": "1900-01-01"}),
+        ]
+    );
+}
+
+#[test]
+fn picks_the_reader_from_the_file_extension() {
+    let records = convert_file("tests/fixtures/sample.xlsb", "Data", &ConvertOptions::default()).unwrap();
+    assert_eq!(records[0], json!({"name": "John", "amount": "12.5", "count": "42", "day": "45000"}));
+
+    // The same bytes aren't a valid .xlsx
+    assert!(convert_bytes(SAMPLE, "xlsx", "Data", &ConvertOptions::default()).is_err());
+}